@@ -0,0 +1,201 @@
+use crate::graph::history::HistoryPolicy;
+use crate::graph::models::{
+    ApprovalPolicy, CurrentNode, Hooks, NodeTransition, StreamWrapper, ToolSet,
+};
+use crate::guardrails::{Validator, ViolationAction};
+use crate::{Agent, CustomNode};
+use providers::{BaseProvider, Message};
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+use tools::{RunCommandTool, Tool, ToolRegistry, Workspace};
+
+/// Configures an [`Agent`], started with [`Agent::builder`]
+///
+/// Every setting applies to each run the agent starts, so [`Agent::iter`] only needs the
+/// prompt. Anything left unset keeps the default of [`Agent::new`].
+pub struct AgentBuilder<P: BaseProvider> {
+    agent: Agent<P>,
+}
+
+impl<P: BaseProvider> AgentBuilder<P> {
+    pub fn new(provider: P) -> Self {
+        AgentBuilder {
+            agent: Agent::new(provider),
+        }
+    }
+
+    /// Use a different model provider
+    pub fn provider(mut self, provider: P) -> Self {
+        self.agent.provider = Arc::new(provider);
+        self
+    }
+
+    /// Confine the tools to a directory other than the current one
+    pub fn workspace(mut self, workspace: Workspace) -> Self {
+        self.agent = self.agent.with_workspace(workspace);
+        self
+    }
+
+    /// Offer a tool alongside the built-in ones, replacing any built-in with the same name
+    pub fn tool<T: Tool + 'static>(mut self, tool: T) -> Self {
+        self.agent = self.agent.with_tool(tool);
+        self
+    }
+
+    /// Offer every tool in a registry alongside the built-in ones
+    pub fn tools(mut self, tools: &ToolRegistry) -> Self {
+        self.agent = self.agent.with_tools(tools);
+        self
+    }
+
+    /// Offer every run only some of the tools, e.g. [`ToolSet::ReadOnly`]
+    pub fn tool_set(mut self, tool_set: ToolSet) -> Self {
+        self.agent.tool_set = tool_set;
+        self
+    }
+
+    /// Start each conversation with an overview of the workspace of up to `max_bytes`, e.g.
+    /// [`crate::DEFAULT_REPO_CONTEXT_BYTES`]: the top of the README, the top-level files and the
+    /// project manifests, so the model doesn't spend its first turns exploring
+    pub fn repo_context(mut self, max_bytes: usize) -> Self {
+        self.agent.repo_context = Some(max_bytes);
+        self
+    }
+
+    /// Start each conversation with example exchanges, to steer the model's style and how it
+    /// uses the tools, see [`crate::Deps::examples`]
+    pub fn examples(mut self, examples: Vec<Message>) -> Self {
+        self.agent.examples = examples;
+        self
+    }
+
+    /// Send less of long conversations with each model request, e.g. only the latest turns
+    pub fn history_policy(mut self, history_policy: HistoryPolicy) -> Self {
+        self.agent.history_policy = history_policy;
+        self
+    }
+
+    /// Use a configured run_command tool, e.g. with a different default timeout
+    pub fn run_command(mut self, run_command: RunCommandTool) -> Self {
+        self.agent = self.agent.with_run_command(run_command);
+        self
+    }
+
+    /// Add a node to the graph of every run, see [`Agent::with_node`]
+    pub fn node<N: CustomNode + 'static>(mut self, name: &str, node: N) -> Self {
+        self.agent = self.agent.with_node(name, node);
+        self
+    }
+
+    /// Redirect a transition in every run, see [`Agent::with_route`]
+    pub fn route(
+        mut self,
+        from: CurrentNode,
+        transition: NodeTransition,
+        to: NodeTransition,
+    ) -> Self {
+        self.agent = self.agent.with_route(from, transition, to);
+        self
+    }
+
+    /// Set the system prompt, in which placeholders such as `{{cwd}}` and `{{git_branch}}` are
+    /// filled in as each run starts, see [`crate::PROMPT_VARIABLES`]
+    pub fn system_prompt(mut self, system_prompt: impl Into<String>) -> Self {
+        self.agent.system_prompt = system_prompt.into();
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.agent.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.agent.temperature = Some(temperature);
+        self
+    }
+
+    /// Wrap the model's response streams, e.g. to print text as it arrives
+    pub fn stream_wrapper<S: StreamWrapper + 'static>(mut self, stream_wrapper: S) -> Self {
+        self.agent.stream_wrapper = Arc::new(stream_wrapper);
+        self
+    }
+
+    /// Decide whether tools that change files or run commands may run
+    pub fn approval<A: ApprovalPolicy + 'static>(mut self, approval: A) -> Self {
+        self.agent.approval = Arc::new(approval);
+        self
+    }
+
+    /// Pause at [`CurrentNode::AwaitApproval`] for the caller to approve or deny tool calls on
+    /// the iterator, instead of asking the approval policy
+    pub fn await_approval(mut self, await_approval: bool) -> Self {
+        self.agent.await_approval = await_approval;
+        self
+    }
+
+    pub fn hooks<H: Hooks + 'static>(mut self, hooks: H) -> Self {
+        self.agent.hooks = Arc::new(hooks);
+        self
+    }
+
+    /// Check the model's final responses and the files it writes, after any validators added
+    /// before, e.g. with a [`crate::SecretDetector`]
+    pub fn validator<V: Validator + 'static>(mut self, validator: V) -> Self {
+        self.agent.validators.push(Arc::new(validator));
+        self
+    }
+
+    /// Choose whether output a validator rejects is sent back to the model to correct, the
+    /// default, or ends the run
+    pub fn on_violation(mut self, on_violation: ViolationAction) -> Self {
+        self.agent.on_violation = on_violation;
+        self
+    }
+
+    /// End a run with an error once it has made this many model requests
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.agent.max_iterations = max_iterations;
+        self
+    }
+
+    /// Stop a run once it has taken this long, ending it with what it has so far
+    pub fn max_run_duration(mut self, max_run_duration: Duration) -> Self {
+        self.agent.max_run_duration = Some(max_run_duration);
+        self
+    }
+
+    /// Stop a run before its next model request once it has cost this many US dollars
+    pub fn max_cost_usd(mut self, max_cost_usd: f64) -> Self {
+        self.agent.max_cost_usd = Some(max_cost_usd);
+        self
+    }
+
+    /// Stop a run before its next model request once it has used this many tokens
+    pub fn max_total_tokens(mut self, max_total_tokens: u64) -> Self {
+        self.agent.max_total_tokens = Some(max_total_tokens);
+        self
+    }
+
+    /// Truncate tool output beyond this many bytes before it reaches the model
+    pub fn max_tool_output_bytes(mut self, max_tool_output_bytes: NonZeroUsize) -> Self {
+        self.agent.max_tool_output_bytes = max_tool_output_bytes.get();
+        self
+    }
+
+    /// Offer a task tool, see [`Agent::with_task_tool`]
+    ///
+    /// The subagent is offered the read-only tools added before this.
+    pub fn task_tool(mut self) -> Self
+    where
+        P: Send + Sync + 'static,
+    {
+        self.agent = self.agent.with_task_tool();
+        self
+    }
+
+    pub fn build(self) -> Agent<P> {
+        self.agent
+    }
+}
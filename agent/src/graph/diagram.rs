@@ -0,0 +1,230 @@
+use crate::graph::models::{CurrentNode, NodeTransition, NodeVisit, Transitions};
+use crate::graph::nodes::{
+    AwaitApprovalTransition, CallToolsTransition, EndTransition, ModelRequestTransition,
+    StartTransition, UserRequestTransition,
+};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Fill for the nodes the run went through
+const VISITED_COLOR: &str = "#c6e5ff";
+
+/// Fill for a node that failed
+const FAILED_COLOR: &str = "#ffc6c6";
+
+/// Colour of the transitions the run took
+const TAKEN_COLOR: &str = "#1f6feb";
+
+/// The node graph of a run, with the path it took, see [`crate::GraphIter::to_dot`]
+pub(crate) struct Diagram {
+    /// Every node, in the order they're first seen
+    nodes: Vec<CurrentNode>,
+    /// Every transition between nodes, with how many times the run took it
+    edges: Vec<(CurrentNode, CurrentNode, usize)>,
+    /// How many times each node ran and how long it took altogether
+    visits: HashMap<CurrentNode, (usize, Duration)>,
+    /// The node that failed, if the run ended with an error
+    failed: Option<CurrentNode>,
+    /// The node the run is at or stopped at
+    current: CurrentNode,
+}
+
+impl Diagram {
+    pub(crate) fn new(
+        routes: &HashMap<(CurrentNode, NodeTransition), NodeTransition>,
+        path: &[NodeVisit],
+        current: &CurrentNode,
+    ) -> Self {
+        let mut diagram = Diagram {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            visits: HashMap::new(),
+            failed: None,
+            current: current.clone(),
+        };
+
+        // The graph as it's wired, with any transitions the caller redirected
+        let mut wiring = built_in_edges();
+        let mut redirected: Vec<_> = routes.keys().cloned().collect();
+        redirected.sort_by_key(|(from, transition)| format!("{:?}{:?}", from, transition));
+        wiring.extend(redirected);
+        for (from, transition) in wiring {
+            let transition = routes
+                .get(&(from.clone(), transition.clone()))
+                .unwrap_or(&transition);
+            if let Some(to) = transition.target() {
+                diagram.add_edge(&from, &to, 0);
+            }
+        }
+
+        // The path the run took, including transitions only custom nodes know about
+        for visit in path {
+            diagram.add_node(&visit.node);
+            let (count, duration) = diagram
+                .visits
+                .entry(visit.node.clone())
+                .or_insert((0, Duration::ZERO));
+            *count += 1;
+            *duration += visit.duration;
+            if visit.failed {
+                diagram.failed = Some(visit.node.clone());
+            }
+        }
+        for pair in path.windows(2) {
+            diagram.add_edge(&pair[0].node, &pair[1].node, 1);
+        }
+        diagram.add_node(current);
+        diagram
+    }
+
+    fn add_node(&mut self, node: &CurrentNode) {
+        if !self.nodes.contains(node) {
+            self.nodes.push(node.clone());
+        }
+    }
+
+    fn add_edge(&mut self, from: &CurrentNode, to: &CurrentNode, taken: usize) {
+        self.add_node(from);
+        self.add_node(to);
+        match self
+            .edges
+            .iter_mut()
+            .find(|(edge_from, edge_to, _)| edge_from == from && edge_to == to)
+        {
+            Some((_, _, count)) => *count += taken,
+            None => self.edges.push((from.clone(), to.clone(), taken)),
+        }
+    }
+
+    /// The node's name, with how many times it ran and for how long if it did
+    fn label(&self, node: &CurrentNode, line_break: &str) -> String {
+        let name = match node {
+            CurrentNode::Custom(name) => name.clone(),
+            node => format!("{:?}", node),
+        };
+        match self.visits.get(node) {
+            Some((count, duration)) => format!(
+                "{}{}{}× {:.1}s",
+                name,
+                line_break,
+                count,
+                duration.as_secs_f64()
+            ),
+            None => name,
+        }
+    }
+
+    fn fill(&self, node: &CurrentNode) -> Option<&'static str> {
+        if self.failed.as_ref() == Some(node) {
+            Some(FAILED_COLOR)
+        } else if self.visits.contains_key(node) {
+            Some(VISITED_COLOR)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph run {\n    node [shape=box, style=rounded];\n");
+        for node in &self.nodes {
+            let mut attributes = format!("label=\"{}\"", escape(&self.label(node, "\\n")));
+            if let Some(fill) = self.fill(node) {
+                let _ = write!(
+                    attributes,
+                    ", style=\"rounded,filled\", fillcolor=\"{}\"",
+                    fill
+                );
+            }
+            if *node == self.current {
+                attributes.push_str(", penwidth=3");
+            }
+            let _ = writeln!(dot, "    \"{}\" [{}];", id(node), attributes);
+        }
+        for (from, to, taken) in &self.edges {
+            let attributes = match taken {
+                0 => "style=dashed, color=gray".to_string(),
+                _ => format!(
+                    "color=\"{}\", penwidth=2, label=\"{}×\"",
+                    TAKEN_COLOR, taken
+                ),
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [{}];",
+                id(from),
+                id(to),
+                attributes
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub(crate) fn to_mermaid(&self) -> String {
+        let index = |node: &CurrentNode| self.nodes.iter().position(|n| n == node).unwrap_or(0);
+        let mut mermaid = String::from("flowchart TD\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let _ = writeln!(
+                mermaid,
+                "    n{}[\"{}\"]",
+                i,
+                self.label(node, "<br/>").replace('"', "#quot;")
+            );
+        }
+        for (from, to, taken) in &self.edges {
+            let _ = match taken {
+                0 => writeln!(mermaid, "    n{} -.-> n{}", index(from), index(to)),
+                _ => writeln!(
+                    mermaid,
+                    "    n{} ==>|\"{}×\"| n{}",
+                    index(from),
+                    taken,
+                    index(to)
+                ),
+            };
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mut style = Vec::new();
+            if let Some(fill) = self.fill(node) {
+                style.push(format!("fill:{}", fill));
+            }
+            if *node == self.current {
+                style.push("stroke-width:3px".to_string());
+            }
+            if !style.is_empty() {
+                let _ = writeln!(mermaid, "    style n{} {}", i, style.join(","));
+            }
+        }
+        mermaid
+    }
+}
+
+/// The transitions each built-in node can return
+fn built_in_edges() -> Vec<(CurrentNode, NodeTransition)> {
+    fn edges<T: Transitions>(from: CurrentNode) -> Vec<(CurrentNode, NodeTransition)> {
+        T::ALL
+            .iter()
+            .map(|transition| (from.clone(), (*transition).into()))
+            .collect()
+    }
+
+    [
+        edges::<StartTransition>(CurrentNode::Start),
+        edges::<UserRequestTransition>(CurrentNode::UserRequest),
+        edges::<ModelRequestTransition>(CurrentNode::ModelRequest),
+        edges::<AwaitApprovalTransition>(CurrentNode::AwaitApproval),
+        edges::<CallToolsTransition>(CurrentNode::CallTools),
+        edges::<EndTransition>(CurrentNode::End),
+    ]
+    .concat()
+}
+
+/// A node's id in DOT, which a custom node's name can't clash with
+fn id(node: &CurrentNode) -> String {
+    escape(&format!("{:?}", node))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('"', "\\\"")
+}
@@ -0,0 +1,247 @@
+use crate::graph::models::{AgentEvent, Deps, GraphError, State};
+use futures_util::StreamExt;
+use providers::models::{ContentBlock, StreamEvent, StreamProcessor};
+use providers::{BaseProvider, Message, Role};
+use serde::{Deserialize, Serialize};
+
+/// The most bytes of each tool call's input or output written into the transcript to summarize
+const SUMMARY_EXCERPT_BYTES: usize = 2_000;
+
+const SUMMARY_SYSTEM_PROMPT: &str = "You summarize the earlier part of a conversation between a \
+user and a coding agent, so the agent can carry on without it. Keep what it needs: what the user \
+asked for, decisions made, files read or changed and what was found in them, commands run and \
+their outcome, and anything left to do. Leave out pleasantries and output that no longer \
+matters. Reply with the summary only.";
+
+/// How much of a long conversation is sent with each model request, trading cost for fidelity
+///
+/// A turn starts at each prompt from the user, including those of [`Deps::examples`]. The state
+/// keeps the whole history whichever is used, only what the model sees is pruned.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HistoryPolicy {
+    /// Send the whole conversation
+    #[default]
+    KeepAll,
+    /// Send only the latest this many turns
+    SlidingWindow(usize),
+    /// Send the whole conversation, but replace the output of all but the latest this many
+    /// rounds of tool calls with a note that it was dropped
+    DropOldToolResults(usize),
+    /// Send the latest this many turns, with a summary of the turns before them written by the
+    /// model, which is updated as turns drop out of the window
+    Summarize(usize),
+}
+
+/// A summary of the start of the conversation, see [`HistoryPolicy::Summarize`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySummary {
+    /// How many messages from the start of the history it covers
+    pub messages: usize,
+    pub text: String,
+}
+
+/// The messages to send with the next model request, pruned by [`Deps::history_policy`]
+pub(crate) async fn prune<P: BaseProvider>(
+    state: &mut State,
+    deps: &Deps<P>,
+) -> Result<Vec<Message>, GraphError> {
+    let history = &state.message_history;
+    match deps.history_policy {
+        HistoryPolicy::KeepAll => Ok(history.clone()),
+        HistoryPolicy::SlidingWindow(turns) => Ok(history[window_start(history, turns)..].to_vec()),
+        HistoryPolicy::DropOldToolResults(rounds) => {
+            let mut messages = history.clone();
+            let old = messages
+                .iter_mut()
+                .rev()
+                .filter(|message| has_tool_results(message))
+                .skip(rounds);
+            for message in old {
+                for block in &mut message.content {
+                    if let ContentBlock::ToolResult {
+                        content, images, ..
+                    } = block
+                    {
+                        *content = format!(
+                            "[Output of {} bytes dropped from the history, run the tool again if \
+                            it's still needed]",
+                            content.len()
+                        );
+                        images.clear();
+                    }
+                }
+            }
+            Ok(messages)
+        }
+        HistoryPolicy::Summarize(turns) => {
+            let start = window_start(history, turns);
+            if start == 0 {
+                return Ok(history.clone());
+            }
+
+            // Only the turns that dropped out of the window since the last summary are read
+            let covered = state
+                .history_summary
+                .as_ref()
+                .map_or(0, |summary| summary.messages);
+            if covered < start {
+                let text = summarize(state, deps, covered, start).await?;
+                state.history_summary = Some(HistorySummary {
+                    messages: start,
+                    text,
+                });
+            }
+
+            let mut messages = state.message_history[start..].to_vec();
+            if let (Some(summary), Some(first)) = (&state.history_summary, messages.first_mut()) {
+                first.content.insert(
+                    0,
+                    ContentBlock::Text {
+                        text: format!(
+                            "Summary of the conversation before this point:\n{}",
+                            summary.text
+                        ),
+                    },
+                );
+            }
+            Ok(messages)
+        }
+    }
+}
+
+/// Where the latest `turns` turns start, at a prompt from the user so no tool result is
+/// separated from its call
+fn window_start(history: &[Message], turns: usize) -> usize {
+    let prompts: Vec<usize> = history
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| is_prompt(message))
+        .map(|(index, _)| index)
+        .collect();
+    match prompts.len().checked_sub(turns.max(1)) {
+        Some(index) => prompts[index],
+        None => 0,
+    }
+}
+
+/// Whether a message is a prompt from the user, rather than the results of tool calls
+fn is_prompt(message: &Message) -> bool {
+    message.role == Role::User && !has_tool_results(message)
+}
+
+fn has_tool_results(message: &Message) -> bool {
+    message
+        .content
+        .iter()
+        .any(|block| matches!(block, ContentBlock::ToolResult { .. }))
+}
+
+/// Ask the model for a summary of the messages from `start` to `end`, building on the summary
+/// of those before
+async fn summarize<P: BaseProvider>(
+    state: &mut State,
+    deps: &Deps<P>,
+    start: usize,
+    end: usize,
+) -> Result<String, GraphError> {
+    let mut transcript = String::new();
+    if let Some(summary) = &state.history_summary {
+        transcript.push_str(&format!(
+            "Summary of the conversation so far:\n{}\n\nThe conversation since:\n",
+            summary.text
+        ));
+    }
+    for message in &state.message_history[start..end] {
+        transcript.push_str(&render(message));
+    }
+
+    let request = [Message {
+        role: Role::User,
+        content: vec![ContentBlock::Text { text: transcript }],
+    }];
+    let stream = deps
+        .provider
+        .stream(
+            &request,
+            SUMMARY_SYSTEM_PROMPT,
+            None,
+            None,
+            deps.max_tokens,
+            None,
+        )
+        .await?;
+    tokio::pin!(stream);
+    let mut events = Vec::new();
+    loop {
+        let event = tokio::select! {
+            biased;
+            _ = deps.cancellation.cancelled() => return Err(GraphError::Cancelled),
+            event = stream.next() => event,
+        };
+        match event {
+            Some(event) => events.push(event?),
+            None => break,
+        }
+    }
+    let response = <StreamEvent as StreamProcessor<StreamEvent>>::process_events(events)?;
+
+    // The summary is paid for like any other request
+    if let Some(usage) = &response.usage {
+        state.usage.accumulate(usage);
+        if let Some(pricing) = deps.provider.pricing() {
+            state.cost_usd += pricing.cost(usage);
+        }
+        deps.emit(AgentEvent::Usage(usage.clone()));
+    }
+
+    Message::try_from(response)?
+        .text()
+        .ok_or_else(|| GraphError::Other(anyhow::anyhow!("The model returned an empty summary")))
+}
+
+/// A message as plain text for the transcript, with long tool input and output cut short
+fn render(message: &Message) -> String {
+    let speaker = match message.role {
+        Role::User => "User",
+        Role::Assistant => "Assistant",
+    };
+    let mut text = String::new();
+    for block in &message.content {
+        match block {
+            ContentBlock::Text { text: t } | ContentBlock::CitedText { text: t, .. } => {
+                text.push_str(&format!("{}: {}\n", speaker, t));
+            }
+            ContentBlock::ToolUse { name, input, .. } => {
+                text.push_str(&format!(
+                    "Tool call: {} {}\n",
+                    name,
+                    excerpt(&input.to_string())
+                ));
+            }
+            ContentBlock::ToolResult { content, .. } => {
+                text.push_str(&format!("Tool result: {}\n", excerpt(content)));
+            }
+            ContentBlock::ServerToolUse { name, input, .. } => {
+                text.push_str(&format!(
+                    "Server tool call: {} {}\n",
+                    name,
+                    excerpt(&input.to_string())
+                ));
+            }
+            ContentBlock::WebSearchToolResult { .. } => {}
+        }
+    }
+    text.push('\n');
+    text
+}
+
+fn excerpt(text: &str) -> String {
+    if text.len() <= SUMMARY_EXCERPT_BYTES {
+        return text.to_string();
+    }
+    let mut end = SUMMARY_EXCERPT_BYTES;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{} [... {} more bytes]", &text[..end], text.len() - end)
+}
@@ -1,6 +1,56 @@
-use crate::graph::models::{CurrentNode, Deps, GraphError, NodeRunner, NodeTransition, State};
-use crate::graph::nodes::{CallTools, End, ModelRequest, Start, UserRequest};
-use providers::{models::ContentBlock, BaseProvider, Role};
+use crate::graph::diagram::Diagram;
+use crate::graph::models::{
+    AgentEvent, CurrentNode, Deps, GraphError, GraphEvent, NodeRunner, NodeVisit, PendingApproval,
+    RunMetrics, State, ToolInvocation, ToolSet,
+};
+use crate::graph::nodes::{
+    pending_approvals, AwaitApproval, CallTools, End, ModelRequest, Start, UserRequest,
+    INTERRUPTED_MARKER,
+};
+use crate::replay::Trace;
+use crate::session_store::SessionStore;
+use providers::models::Usage;
+use providers::{BaseProvider, Message, Role, StopReason};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tools::FileChange;
+use tracing::Instrument;
+
+/// Everything needed to carry on with a run later, e.g. after restarting the process
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub state: State,
+    /// The node the run had reached, the first to run when it's resumed
+    pub current_node: CurrentNode,
+    /// Whether the run had reached its end, resuming it then runs nothing
+    pub finished: bool,
+    pub result: Option<String>,
+}
+
+/// Everything a finished run produced, see [`GraphIter::run_to_completion`]
+#[derive(Debug, Clone)]
+pub struct RunResult {
+    /// The text of the model's final response, with all of its text blocks joined together
+    pub text: Option<String>,
+    /// The input the model passed to the respond tool, if it was offered one
+    pub structured_result: Option<serde_json::Value>,
+    /// Every tool call the run made, in order
+    pub tool_invocations: Vec<ToolInvocation>,
+    /// The tokens of every model request, added together
+    pub usage: Usage,
+    /// Why the model stopped its final response
+    pub stop_reason: Option<StopReason>,
+    /// The files the run changed, relative to the workspace root
+    pub modified_paths: Vec<PathBuf>,
+    /// Whether the run created, modified or deleted each of those files, undone with
+    /// [`GraphIter::rollback`]
+    pub file_changes: Vec<FileChange>,
+}
 
 /// A struct to hold the state of a graph iteration
 pub struct GraphIter<P: BaseProvider> {
@@ -9,15 +59,41 @@ pub struct GraphIter<P: BaseProvider> {
     current_node: CurrentNode,
     finished: bool,
     result: Option<String>,
+    /// Where a snapshot is saved after every node, see [`GraphIter::checkpoint_to`]
+    checkpoint: Option<(SessionStore, String)>,
+    /// When the run is out of time, set once it starts from [`Deps::max_run_duration`]
+    deadline: Option<Instant>,
 }
 
 impl<P: BaseProvider> GraphIter<P> {
     /// Create a new graph iterator
     pub fn new(deps: Deps<P>, user_prompt: String) -> Self {
+        Self::with_history(deps, user_prompt, Vec::new())
+    }
+
+    /// Create a graph iterator that continues a conversation, the model sees the earlier
+    /// messages before the prompt
+    pub fn with_history(deps: Deps<P>, user_prompt: String, message_history: Vec<Message>) -> Self {
         let state = State {
-            message_history: Vec::new(),
+            message_history,
             current_user_prompt: user_prompt,
             tool_outputs: std::collections::HashMap::new(),
+            tool_invocations: Vec::new(),
+            modified_paths: Vec::new(),
+            file_changes: Vec::new(),
+            model_requests: 0,
+            structured_result: None,
+            context_tokens: 0,
+            counted_messages: 0,
+            events: Vec::new(),
+            usage: Usage::default(),
+            turn_usage: Vec::new(),
+            path: Vec::new(),
+            cost_usd: 0.0,
+            tool_call_corrections: 0,
+            history_summary: None,
+            stop_reason: None,
+            approvals: std::collections::HashMap::new(),
         };
 
         GraphIter {
@@ -26,118 +102,419 @@ impl<P: BaseProvider> GraphIter<P> {
             current_node: CurrentNode::Start,
             finished: false,
             result: None,
+            checkpoint: None,
+            deadline: None,
+        }
+    }
+
+    /// Carry on with a run from a snapshot of it
+    ///
+    /// A run that failed part way starts again at the node that failed, e.g. retrying the
+    /// model request. The files changed before the snapshot aren't undone with this run.
+    pub fn resume(deps: Deps<P>, snapshot: Snapshot) -> Self {
+        GraphIter {
+            deps,
+            state: snapshot.state,
+            current_node: snapshot.current_node,
+            finished: snapshot.finished,
+            result: snapshot.result,
+            checkpoint: None,
+            deadline: None,
+        }
+    }
+
+    /// A copy of the run at this point that carries on separately with `deps`, e.g. to try
+    /// another instruction without losing this branch, see [`crate::Agent::fork`]
+    ///
+    /// Only the conversation is copied, the files on disk are shared, so changes one branch
+    /// makes are seen by the other. Neither branch's checkpoints are saved for the other.
+    pub fn fork(&self, deps: Deps<P>) -> GraphIter<P> {
+        GraphIter {
+            deps,
+            state: self.state.clone(),
+            current_node: self.current_node.clone(),
+            finished: self.finished,
+            result: self.result.clone(),
+            checkpoint: None,
+            deadline: None,
+        }
+    }
+
+    /// Offer the run only some of its tools, e.g. [`ToolSet::ReadOnly`] for a question about
+    /// the code that shouldn't change anything
+    ///
+    /// It can only narrow the tools the agent offers, see [`crate::AgentBuilder::tool_set`].
+    pub fn with_tool_set(mut self, tool_set: ToolSet) -> Self {
+        if let Some(tools) = &mut self.deps.tools {
+            tool_set.apply(tools);
+        }
+        self
+    }
+
+    /// Save a snapshot of the run to session `id` after every node, so a crash or restart loses
+    /// at most the node that was running
+    ///
+    /// Carry on from the last one with [`SessionStore::load`] and [`GraphIter::resume`]. A
+    /// snapshot that can't be saved doesn't stop the run, it's recorded as a
+    /// [`GraphEvent::CheckpointFailed`].
+    pub fn checkpoint_to(mut self, store: SessionStore, id: impl Into<String>) -> Self {
+        self.checkpoint = Some((store, id.into()));
+        self
+    }
+
+    /// A snapshot of the run so far, e.g. to save it and resume it with [`GraphIter::resume`]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            state: self.state.clone(),
+            current_node: self.current_node.clone(),
+            // A failed run stops part way, it's only finished once it reached the end
+            finished: self.finished && matches!(self.current_node, CurrentNode::End),
+            result: self.result.clone(),
         }
     }
 
+    /// Run every remaining node, returning what the run produced
+    ///
+    /// Tool calls that would wait at [`CurrentNode::AwaitApproval`] are denied, drive the
+    /// iterator with [`GraphIter::next`] to approve them.
+    pub async fn run_to_completion(&mut self) -> std::result::Result<RunResult, GraphError> {
+        while let Some(result) = self.next().await {
+            result?;
+        }
+
+        Ok(RunResult {
+            text: self.result.clone(),
+            structured_result: self.state.structured_result.clone(),
+            tool_invocations: self.state.tool_invocations.clone(),
+            usage: self.state.usage.clone(),
+            stop_reason: self.state.stop_reason.clone(),
+            modified_paths: self.state.modified_paths.clone(),
+            file_changes: self.state.file_changes.clone(),
+        })
+    }
+
     /// Get the result of the graph execution
     pub fn get_result(&self) -> Option<&str> {
         self.result.as_deref()
     }
 
+    /// Get the structured result of the graph execution, deserialized into `T`
+    ///
+    /// Returns `None` if the model never called the respond tool.
+    pub fn get_structured_result<T: DeserializeOwned>(
+        &self,
+    ) -> Option<std::result::Result<T, GraphError>> {
+        self.state.structured_result.as_ref().map(|value| {
+            serde_json::from_value(value.clone())
+                .map_err(|e| GraphError::InvalidStructuredResponse(e.to_string()))
+        })
+    }
+
     /// Run the next node in the graph
     pub async fn next(&mut self) -> Option<std::result::Result<CurrentNode, GraphError>> {
+        let result = self.step().await?;
+
+        if let Some((store, id)) = &self.checkpoint {
+            if let Err(err) = store.save(id, &self.snapshot()) {
+                tracing::warn!(target: "aria::agent", session = %id, error = %err, "Failed to save the session");
+                self.state
+                    .events
+                    .push(GraphEvent::CheckpointFailed(err.to_string()));
+            }
+        }
+
+        Some(result)
+    }
+
+    async fn step(&mut self) -> Option<std::result::Result<CurrentNode, GraphError>> {
         if self.finished {
             return None;
         }
 
-        let transition_result = match self.current_node {
-            CurrentNode::Start => {
-                let result = Start.run(&mut self.state, &self.deps).await;
-                self.current_node = CurrentNode::UserRequest;
-                result.map(|_| self.current_node.clone())
+        // A resumed run gets the full time again
+        if self.deadline.is_none() {
+            self.deadline = self
+                .deps
+                .max_run_duration
+                .map(|duration| Instant::now() + duration);
+        }
+        let deadline = self.deadline;
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Some(Ok(self.time_out()));
+        }
+
+        let span = tracing::debug_span!(target: "aria::agent", "node", node = ?self.current_node);
+        let cancellation = self.deps.cancellation.clone();
+        let run = async {
+            match &self.current_node {
+                CurrentNode::Start => Start.run(&mut self.state, &self.deps).await.map(Into::into),
+                CurrentNode::UserRequest => UserRequest
+                    .run(&mut self.state, &self.deps)
+                    .await
+                    .map(Into::into),
+                CurrentNode::ModelRequest => ModelRequest
+                    .run(&mut self.state, &self.deps)
+                    .await
+                    .map(Into::into),
+                CurrentNode::CallTools => CallTools
+                    .run(&mut self.state, &self.deps)
+                    .await
+                    .map(Into::into),
+                CurrentNode::End => End.run(&mut self.state, &self.deps).await.map(Into::into),
+                CurrentNode::AwaitApproval => AwaitApproval
+                    .run(&mut self.state, &self.deps)
+                    .await
+                    .map(Into::into),
+                CurrentNode::Custom(name) => match self.deps.nodes.get(name) {
+                    Some(node) => node.run(&mut self.state).await,
+                    None => Err(GraphError::UnknownNode(name.clone())),
+                },
             }
-            CurrentNode::UserRequest => {
-                let result = UserRequest.run(&mut self.state, &self.deps).await;
-                match &result {
-                    Ok(transition) => match transition {
-                        NodeTransition::ToModelRequest => {
-                            self.current_node = CurrentNode::ModelRequest;
-                        }
-                        _ => {
-                            return Some(Err(GraphError::InvalidStateTransition(
-                                "Invalid transition from UserRequest".to_string(),
-                            )));
-                        }
-                    },
-                    Err(_) => {
-                        // On error, we'll return the error and mark as finished
-                        self.finished = true;
+        }
+        .instrument(span.clone());
+        let started = std::time::Instant::now();
+        let result = match deadline {
+            // Out of time, the node is cancelled so it stops at the next chance, keeping what
+            // it had done, e.g. the text streamed so far
+            Some(deadline) => {
+                tokio::pin!(run);
+                tokio::select! {
+                    result = &mut run => result,
+                    _ = tokio::time::sleep_until(deadline) => {
+                        cancellation.cancel();
+                        run.await
                     }
                 }
-                result.map(|_| self.current_node.clone())
             }
-            CurrentNode::ModelRequest => {
-                let result = ModelRequest.run(&mut self.state, &self.deps).await;
-                match &result {
-                    Ok(transition) => match transition {
-                        NodeTransition::ToCallTools => {
-                            self.current_node = CurrentNode::CallTools;
-                        }
-                        NodeTransition::ToEnd => {
-                            self.current_node = CurrentNode::End;
-                        }
-                        _ => {
-                            return Some(Err(GraphError::InvalidStateTransition(
-                                "Invalid transition from ModelRequest".to_string(),
-                            )));
-                        }
-                    },
-                    Err(_) => {
-                        // On error, we'll return the error and mark as finished
-                        self.finished = true;
-                    }
-                }
-                result.map(|_| self.current_node.clone())
+            None => run.await,
+        };
+        self.state.path.push(NodeVisit {
+            node: self.current_node.clone(),
+            duration: started.elapsed(),
+            failed: result
+                .as_ref()
+                .is_err_and(|err| !matches!(err, GraphError::Cancelled)),
+        });
+        match &result {
+            Ok(transition) => {
+                tracing::debug!(target: "aria::agent", parent: &span, ?transition, "Node finished")
             }
-            CurrentNode::CallTools => {
-                let result = CallTools.run(&mut self.state, &self.deps).await;
-                match &result {
-                    Ok(transition) => match transition {
-                        NodeTransition::ToModelRequest => {
-                            self.current_node = CurrentNode::ModelRequest;
-                        }
-                        NodeTransition::ToEnd => {
-                            self.current_node = CurrentNode::End;
-                        }
-                        _ => {
-                            return Some(Err(GraphError::InvalidStateTransition(
-                                "Invalid transition from CallTools".to_string(),
-                            )));
-                        }
-                    },
-                    Err(_) => {
-                        // On error, we'll return the error and mark as finished
-                        self.finished = true;
-                    }
+            Err(err) => {
+                tracing::debug!(target: "aria::agent", parent: &span, error = %err, "Node failed")
+            }
+        }
+
+        let transition = match result {
+            Ok(transition) => transition,
+            Err(err) => {
+                // The calls were answered as cancelled, so a resumed run carries on with the
+                // model
+                if let (CurrentNode::CallTools, GraphError::Cancelled) = (&self.current_node, &err)
+                {
+                    self.current_node = CurrentNode::ModelRequest;
+                }
+                if matches!(err, GraphError::Cancelled)
+                    && deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                {
+                    return Some(Ok(self.time_out()));
                 }
-                result.map(|_| self.current_node.clone())
+                // On error, we'll return the error and mark as finished
+                self.finished = true;
+                return Some(Err(err));
             }
-            CurrentNode::End => {
-                let result = End.run(&mut self.state, &self.deps).await;
+        };
+
+        // The caller may have sent the transition elsewhere, e.g. through a node of their own
+        let transition = self
+            .deps
+            .routes
+            .get(&(self.current_node.clone(), transition.clone()))
+            .cloned()
+            .unwrap_or(transition);
 
+        self.current_node = match transition.target() {
+            Some(node) => node,
+            None => {
                 // Store the result if we've reached the end
                 if let Some(last_message) = self.state.message_history.last() {
                     if last_message.role == Role::Assistant {
-                        // Look for text blocks in the content array
-                        for content_block in &last_message.content {
-                            if let ContentBlock::Text { text } = content_block {
-                                self.result = Some(text.clone());
-                                break;
-                            }
-                        }
+                        self.result = last_message.text();
                     }
                 }
 
                 self.finished = true;
-                result.map(|_| self.current_node.clone())
+                let metrics = self.metrics();
+                tracing::debug!(
+                    target: "aria::agent",
+                    duration_ms = metrics.duration.as_millis() as u64,
+                    model_requests = metrics.model_requests,
+                    mean_latency_ms = metrics.mean_latency.as_millis() as u64,
+                    output_tokens_per_second = metrics.output_tokens_per_second,
+                    "Run finished"
+                );
+                self.deps.emit(AgentEvent::TurnCompleted {
+                    text: self.result.clone(),
+                });
+                self.current_node.clone()
             }
         };
 
-        Some(transition_result)
+        Some(Ok(self.current_node.clone()))
+    }
+
+    /// End a run that is out of time with what it has so far
+    ///
+    /// It isn't finished, so resuming a snapshot of it carries on from the node it stopped at.
+    fn time_out(&mut self) -> CurrentNode {
+        let seconds = self.deps.max_run_duration.unwrap_or_default().as_secs();
+        tracing::debug!(target: "aria::agent", seconds, "The run is out of time");
+        self.state.events.push(GraphEvent::TimedOut { seconds });
+
+        // What the model had said before it was cut short, without the marker saying so
+        if let Some(last_message) = self.state.message_history.last() {
+            if last_message.role == Role::Assistant {
+                self.result = last_message
+                    .text()
+                    .map(|text| {
+                        text.trim_end_matches(INTERRUPTED_MARKER)
+                            .trim_end()
+                            .to_string()
+                    })
+                    .filter(|text| !text.is_empty());
+            }
+        }
+
+        self.finished = true;
+        self.deps.emit(AgentEvent::TurnCompleted {
+            text: self.result.clone(),
+        });
+        self.current_node.clone()
+    }
+
+    /// Receive the run's events as they happen, e.g. to show tool calls while they run
+    pub fn subscribe(&mut self) -> UnboundedReceiver<AgentEvent> {
+        self.deps.subscribe()
+    }
+
+    /// The tool calls waiting at [`CurrentNode::AwaitApproval`] for [`GraphIter::approve`] or
+    /// [`GraphIter::deny`]
+    pub fn pending_approvals(&self) -> Vec<PendingApproval> {
+        match self.current_node {
+            CurrentNode::AwaitApproval => pending_approvals(&self.state, &self.deps),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Let the tool calls waiting for approval run once the iterator carries on
+    pub fn approve(&mut self) {
+        self.decide(true);
+    }
+
+    /// Report the tool calls waiting for approval to the model as denied, instead of running them
+    pub fn deny(&mut self) {
+        self.decide(false);
+    }
+
+    fn decide(&mut self, approved: bool) {
+        for pending in self.pending_approvals() {
+            self.state.approvals.insert(pending.tool_use_id, approved);
+        }
     }
 
     /// Get the current state
     pub fn state(&self) -> &State {
         &self.state
     }
+
+    /// Every tool call made so far, with how long it took and how it went
+    pub fn tool_invocations(&self) -> &[ToolInvocation] {
+        &self.state.tool_invocations
+    }
+
+    /// How long each node and model request has taken so far, with the provider's latency and
+    /// how fast it streamed
+    pub fn metrics(&self) -> RunMetrics {
+        self.state.metrics()
+    }
+
+    /// Record the model's responses and the tools' results as the run goes, to replay it later
+    /// with [`Trace::replay`], e.g. as a regression test
+    pub fn record_trace(mut self) -> Self {
+        self.deps.recorder = Some(Arc::new(Mutex::new(Trace {
+            system_prompt: self.deps.system_prompt.clone(),
+            user_prompt: self.state.current_user_prompt.clone(),
+            message_history: self.state.message_history.clone(),
+            ..Trace::default()
+        })));
+        self
+    }
+
+    /// What the run has recorded so far, `None` unless [`GraphIter::record_trace`] was called
+    pub fn trace(&self) -> Option<Trace> {
+        let mut trace = self.deps.recorder.as_ref()?.lock().ok()?.clone();
+        trace.path = self.state.path.clone();
+        Some(trace)
+    }
+
+    /// The node graph in Graphviz DOT, with the path the run has taken so far highlighted
+    ///
+    /// Each node it went through is filled and labelled with how many times it ran and for how
+    /// long, a failed one in red, and the transitions it took are drawn bold with how often. The
+    /// node it's at or stopped at has a thick border, e.g. to see why it looped or ended there.
+    pub fn to_dot(&self) -> String {
+        Diagram::new(&self.deps.routes, &self.state.path, &self.current_node).to_dot()
+    }
+
+    /// The same as [`GraphIter::to_dot`] as a Mermaid flowchart, e.g. to paste into Markdown
+    pub fn to_mermaid(&self) -> String {
+        Diagram::new(&self.deps.routes, &self.state.path, &self.current_node).to_mermaid()
+    }
+
+    /// Everything worth telling the user about so far, e.g. that the context window is nearly full
+    pub fn events(&self) -> &[GraphEvent] {
+        &self.state.events
+    }
+
+    /// The files the run has changed so far, relative to the workspace root
+    pub fn modified_paths(&self) -> &[PathBuf] {
+        &self.state.modified_paths
+    }
+
+    /// Whether the run created, modified or deleted each file it has changed so far
+    pub fn file_changes(&self) -> &[FileChange] {
+        &self.state.file_changes
+    }
+
+    /// Put back the contents every file had before the run, removing the ones it created and
+    /// returning their paths
+    ///
+    /// Only changes made through the file tools are tracked, not those of commands the model
+    /// ran. A resumed run only rolls back the changes made since it was resumed.
+    pub fn rollback(&mut self) -> std::result::Result<Vec<PathBuf>, GraphError> {
+        let restored = self.deps.backups.restore().map_err(GraphError::Storage)?;
+        self.state.modified_paths.clear();
+        self.state.file_changes.clear();
+        Ok(restored)
+    }
+
+    /// Run the node that failed again on the next call to [`GraphIter::next`], e.g. once a
+    /// budget is raised with [`GraphIter::deps_mut`] or the error was retryable
+    pub fn retry(&mut self) {
+        if self.current_node != CurrentNode::End {
+            self.finished = false;
+        }
+    }
+
+    /// A token that stops the run when cancelled, e.g. from a Ctrl-C handler
+    ///
+    /// The run ends with [`GraphError::Cancelled`]. Whatever the model had said is kept in the
+    /// history and any tool calls it made are answered, so the conversation can be continued.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.deps.cancellation.clone()
+    }
+
+    /// Get mutable access to the dependencies, e.g. to adjust the run before it starts
+    pub fn deps_mut(&mut self) -> &mut Deps<P> {
+        &mut self.deps
+    }
 }
@@ -1,9 +1,20 @@
 // Re-export types and functionality from submodules
+mod diagram;
+pub mod history;
 pub mod iter;
 pub mod models;
 pub mod nodes;
 
 // Re-export common types for convenience
-pub use iter::GraphIter;
-pub use models::{CurrentNode, Deps, GraphError, NodeRunner, NodeTransition, State};
-pub use nodes::{CallTools, End, ModelRequest, Start, UserRequest};
+pub use history::{HistoryPolicy, HistorySummary};
+pub use iter::{GraphIter, RunResult, Snapshot};
+pub use models::{
+    AgentEvent, CurrentNode, CustomNode, Deps, GraphError, GraphEvent, Hooks, NodeMetrics,
+    NodeRunner, NodeTransition, NodeVisit, PendingApproval, RunMetrics, State, ToolDecision,
+    ToolInvocation, ToolSet, Transitions, TurnUsage,
+};
+pub use nodes::{
+    AwaitApproval, AwaitApprovalTransition, CallTools, CallToolsTransition, End, EndTransition,
+    ModelRequest, ModelRequestTransition, Start, StartTransition, UserRequest,
+    UserRequestTransition, INTERRUPTED_MARKER,
+};
@@ -1,28 +1,122 @@
+use crate::graph::history::{HistoryPolicy, HistorySummary};
+use crate::guardrails::{Validator, ViolationAction};
+use crate::replay::Trace;
+use async_trait::async_trait;
 use futures_util::Stream;
-use providers::models::StreamEvent;
-use providers::{BaseProvider, Message};
+use providers::models::{ContentBlock, StreamEvent, Usage};
+use providers::{BaseProvider, Message, ProviderError, StopReason, ToolChoice};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 use std::fmt::{Debug, Display};
+use std::path::PathBuf;
 use std::pin::Pin;
-use tools::ToolType;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use tools::models::{ToolName, ToolResult};
+use tools::{Backups, FileChange, ToolRegistry, Workspace};
+
+/// The most bytes of a tool's output added to the message history by default
+pub const DEFAULT_MAX_TOOL_OUTPUT_BYTES: usize = 50_000;
+
+/// The most model requests a run makes by default, each following the last one's tool calls
+pub const DEFAULT_MAX_ITERATIONS: usize = 50;
+
+/// How many rounds of tool calls in a row may have a mistake the model is asked to correct by
+/// default, before the run ends with an error
+pub const DEFAULT_MAX_TOOL_CALL_CORRECTIONS: usize = 3;
+
+/// A warning is raised once the conversation takes up this fraction of the model's context window
+pub const CONTEXT_WARNING_THRESHOLD: f64 = 0.8;
+
+/// Roughly how many bytes of text make up a token, for estimating messages the model hasn't
+/// counted yet
+const BYTES_PER_TOKEN: usize = 4;
+
+/// Roughly how many tokens an image takes up, at the largest size the model sees it
+const TOKENS_PER_IMAGE: usize = 1_600;
 
 /// Custom error type for the graph
+///
+/// Check [`GraphError::is_retryable`] to decide whether to run the failed node again, e.g. by
+/// resuming a snapshot of the run.
 #[derive(Debug)]
 pub enum GraphError {
     MaxTokens,
+    /// The run made this many model requests without finishing
+    MaxIterations(usize),
     ToolNotImplemented(String),
+    /// A tool call couldn't be answered at all, as opposed to the tool reporting an error to
+    /// the model
+    ToolExecution {
+        tool: ToolName,
+        message: String,
+    },
+    /// The conversation no longer fits in the model's context window
+    ContextOverflow(String),
     InvalidStateTransition(String),
+    /// A transition led to a custom node that wasn't added with [`Deps::add_node`]
+    UnknownNode(String),
+    InvalidStructuredResponse(String),
+    /// The run was cancelled through its cancellation token
+    Cancelled,
+    /// The run has spent as much as [`Deps::max_cost_usd`] or [`Deps::max_total_tokens`] allow
+    BudgetExceeded(String),
+    /// A validator rejected the model's output, see [`ViolationAction::Block`]
+    GuardrailViolation(String),
+    /// A replayed run went through other nodes than the recording, see [`crate::Trace::replay`]
+    ReplayDiverged(String),
+    Provider(ProviderError),
+    /// Reading or writing the run's files under `.aria` failed, e.g. a session or a backup
+    Storage(String),
     Other(anyhow::Error),
 }
 
+impl GraphError {
+    /// Whether running the failed node again may succeed without changing anything, e.g. after
+    /// a network error or a cancelled run
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            GraphError::Provider(err) => err.is_retryable(),
+            GraphError::Cancelled => true,
+            _ => false,
+        }
+    }
+}
+
 impl Display for GraphError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             GraphError::MaxTokens => write!(f, "Max tokens reached"),
+            GraphError::MaxIterations(max) => write!(
+                f,
+                "Stopped after {} model requests without finishing, raise max_iterations to allow more",
+                max
+            ),
             GraphError::ToolNotImplemented(tool) => write!(f, "Tool not implemented: {}", tool),
+            GraphError::ToolExecution { tool, message } => {
+                write!(f, "The {} tool failed: {}", tool, message)
+            }
+            GraphError::ContextOverflow(msg) => write!(
+                f,
+                "The conversation is too long for the model's context window: {}",
+                msg
+            ),
             GraphError::InvalidStateTransition(msg) => {
                 write!(f, "Invalid state transition: {}", msg)
             }
+            GraphError::UnknownNode(name) => write!(f, "There is no node named {}", name),
+            GraphError::InvalidStructuredResponse(msg) => {
+                write!(f, "Invalid structured response: {}", msg)
+            }
+            GraphError::Cancelled => write!(f, "Cancelled"),
+            GraphError::BudgetExceeded(msg) => write!(f, "Budget exceeded: {}", msg),
+            GraphError::GuardrailViolation(msg) => write!(f, "Guardrail violation: {}", msg),
+            GraphError::ReplayDiverged(msg) => write!(f, "Replay diverged: {}", msg),
+            GraphError::Provider(err) => write!(f, "Provider error: {}", err),
+            GraphError::Storage(msg) => write!(f, "Storage error: {}", msg),
             GraphError::Other(err) => write!(f, "Error: {}", err),
         }
     }
@@ -30,18 +124,92 @@ impl Display for GraphError {
 
 impl std::error::Error for GraphError {}
 
+impl From<ProviderError> for GraphError {
+    fn from(err: ProviderError) -> Self {
+        match err {
+            ProviderError::ContextTooLong(msg) => GraphError::ContextOverflow(msg),
+            err => GraphError::Provider(err),
+        }
+    }
+}
+
 impl From<anyhow::Error> for GraphError {
     fn from(err: anyhow::Error) -> Self {
         GraphError::Other(err)
     }
 }
 
+/// Something worth telling the user about that doesn't stop the run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GraphEvent {
+    /// The conversation takes up most of the model's context window, so it may soon be cut off
+    ContextWindowNearlyFull { tokens: usize, max_tokens: usize },
+    /// The run reached its time limit, see [`Deps::max_run_duration`], and stopped with what
+    /// it had so far
+    TimedOut { seconds: u64 },
+    /// The run couldn't be saved to its session, so resuming it would start from an older point
+    CheckpointFailed(String),
+    /// A validator rejected a response or file write, and the model was asked to correct it
+    GuardrailViolation(String),
+}
+
+impl Display for GraphEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphEvent::ContextWindowNearlyFull { tokens, max_tokens } => write!(
+                f,
+                "The conversation takes up about {} of the model's {} tokens of context, start a \
+                new one soon",
+                tokens, max_tokens
+            ),
+            GraphEvent::TimedOut { seconds } => write!(
+                f,
+                "The run was stopped after {} seconds, its results are partial",
+                seconds
+            ),
+            GraphEvent::CheckpointFailed(err) => write!(f, "Failed to save the session: {}", err),
+            GraphEvent::GuardrailViolation(reason) => {
+                write!(f, "Output held back for the model to correct: {}", reason)
+            }
+        }
+    }
+}
+
+/// Something that happened during a run, sent to subscribers as it happens, e.g. for a UI
+#[derive(Debug, Clone)]
+pub enum AgentEvent {
+    /// A tool is about to run
+    ToolStarted {
+        tool_use_id: String,
+        tool_name: ToolName,
+        input: Value,
+    },
+    /// A tool is still running, sent every few seconds so a UI can show it hasn't stalled
+    ToolProgress {
+        tool_use_id: String,
+        tool_name: ToolName,
+        elapsed: Duration,
+    },
+    /// A tool call was answered, whether or not the tool ran
+    ToolFinished(ToolInvocation),
+    /// Text the model is streaming
+    TextDelta(String),
+    /// The model's response is starting over, after the connection dropped or the request
+    /// failed and is retried, so the text streamed since the request was sent should be
+    /// discarded
+    ResponseRestarted { attempt: u32 },
+    /// The tokens a model request used
+    Usage(Usage),
+    /// The run reached its end, with the text of the model's final response
+    TurnCompleted { text: Option<String> },
+}
+
 /// A trait for wrapping the stream from the provider
 pub trait StreamWrapper: Send + Sync {
     fn wrap<'a>(
         &'a self,
-        stream: Pin<Box<dyn Stream<Item = anyhow::Result<StreamEvent>> + Send + 'a>>,
-    ) -> Pin<Box<dyn Stream<Item = anyhow::Result<StreamEvent>> + Send + 'a>>;
+        stream: Pin<Box<dyn Stream<Item = Result<StreamEvent, ProviderError>> + Send + 'a>>,
+    ) -> Pin<Box<dyn Stream<Item = Result<StreamEvent, ProviderError>> + Send + 'a>>;
 }
 
 /// Default implementation of StreamWrapper that does nothing
@@ -51,79 +219,628 @@ pub struct NoopStreamWrapper;
 impl StreamWrapper for NoopStreamWrapper {
     fn wrap<'a>(
         &'a self,
-        stream: Pin<Box<dyn Stream<Item = anyhow::Result<StreamEvent>> + Send + 'a>>,
-    ) -> Pin<Box<dyn Stream<Item = anyhow::Result<StreamEvent>> + Send + 'a>> {
+        stream: Pin<Box<dyn Stream<Item = Result<StreamEvent, ProviderError>> + Send + 'a>>,
+    ) -> Pin<Box<dyn Stream<Item = Result<StreamEvent, ProviderError>> + Send + 'a>> {
         // Just return the stream unchanged
         stream
     }
 }
 
+/// Shared between runs, e.g. one set on an [`crate::Agent`]
+impl<S: StreamWrapper + ?Sized> StreamWrapper for Arc<S> {
+    fn wrap<'a>(
+        &'a self,
+        stream: Pin<Box<dyn Stream<Item = Result<StreamEvent, ProviderError>> + Send + 'a>>,
+    ) -> Pin<Box<dyn Stream<Item = Result<StreamEvent, ProviderError>> + Send + 'a>> {
+        (**self).wrap(stream)
+    }
+}
+
+/// Told to the model when a tool call is denied without a reason
+pub const DEFAULT_DENIAL_REASON: &str = "Ask the user how to proceed rather than retrying it";
+
+/// Decides whether a tool that changes files or runs commands may be executed
+#[async_trait]
+pub trait ApprovalPolicy: Send + Sync {
+    /// Whether the tool may run with this input, it is reported to the model as denied if not
+    async fn approve(&self, tool_name: &ToolName, input: &Value) -> bool;
+
+    /// The same as [`ApprovalPolicy::approve`], with what to tell the model when the call is
+    /// denied, e.g. what the user would rather it did
+    async fn decide(&self, tool_name: &ToolName, input: &Value) -> ToolDecision {
+        match self.approve(tool_name, input).await {
+            true => ToolDecision::Allow,
+            false => ToolDecision::Block(DEFAULT_DENIAL_REASON.to_string()),
+        }
+    }
+}
+
+/// Approves every tool call
+#[derive(Default, Debug)]
+pub struct AlwaysApprove;
+
+#[async_trait]
+impl ApprovalPolicy for AlwaysApprove {
+    async fn approve(&self, _tool_name: &ToolName, _input: &Value) -> bool {
+        true
+    }
+}
+
+/// Denies every tool call, leaving the agent read-only
+#[derive(Default, Debug)]
+pub struct NeverApprove;
+
+#[async_trait]
+impl ApprovalPolicy for NeverApprove {
+    async fn approve(&self, _tool_name: &ToolName, _input: &Value) -> bool {
+        false
+    }
+}
+
+#[async_trait]
+impl<A: ApprovalPolicy + ?Sized> ApprovalPolicy for Arc<A> {
+    async fn approve(&self, tool_name: &ToolName, input: &Value) -> bool {
+        (**self).approve(tool_name, input).await
+    }
+
+    async fn decide(&self, tool_name: &ToolName, input: &Value) -> ToolDecision {
+        (**self).decide(tool_name, input).await
+    }
+}
+
+/// What a hook decided about a tool call that is about to run
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolDecision {
+    Allow,
+    /// Don't run the tool, telling the model why
+    Block(String),
+}
+
+/// Callbacks fired at key points of a run, e.g. to enforce a policy or drive a custom UI
+///
+/// Every callback does nothing by default, so implement only the ones you need.
+#[async_trait]
+pub trait Hooks: Send + Sync {
+    /// Before a tool runs, blocking it reports the reason to the model instead of running it
+    async fn on_tool_start(&self, _tool_name: &ToolName, _input: &Value) -> ToolDecision {
+        ToolDecision::Allow
+    }
+
+    /// After a tool ran, any text returned is added to its result for the model to see
+    async fn on_tool_end(
+        &self,
+        _tool_name: &ToolName,
+        _input: &Value,
+        _result: &ToolResult,
+    ) -> Option<String> {
+        None
+    }
+
+    /// After a message is added to the history: the user's prompt, each of the model's
+    /// responses and the results of each round of tool calls
+    async fn on_message(&self, _message: &Message) {}
+
+    /// Once the run reaches its end
+    async fn on_turn_end(&self, _state: &State) {}
+}
+
+/// Hooks that do nothing
+#[derive(Default, Debug)]
+pub struct NoHooks;
+
+impl Hooks for NoHooks {}
+
+#[async_trait]
+impl<H: Hooks + ?Sized> Hooks for Arc<H> {
+    async fn on_tool_start(&self, tool_name: &ToolName, input: &Value) -> ToolDecision {
+        (**self).on_tool_start(tool_name, input).await
+    }
+
+    async fn on_tool_end(
+        &self,
+        tool_name: &ToolName,
+        input: &Value,
+        result: &ToolResult,
+    ) -> Option<String> {
+        (**self).on_tool_end(tool_name, input, result).await
+    }
+
+    async fn on_message(&self, message: &Message) {
+        (**self).on_message(message).await
+    }
+
+    async fn on_turn_end(&self, state: &State) {
+        (**self).on_turn_end(state).await
+    }
+}
+
+/// A node added to the graph by the caller, e.g. to lint the files after each round of tool calls
+///
+/// Add it with [`Deps::add_node`] and lead to it with [`Deps::route`]. The transition it returns
+/// decides where the run goes next, e.g. [`NodeTransition::ToModelRequest`] to carry on.
+#[async_trait]
+pub trait CustomNode: Send + Sync {
+    async fn run(&self, state: &mut State) -> Result<NodeTransition, GraphError>;
+}
+
+/// Which of the agent's tools a run is offered
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ToolSet {
+    #[default]
+    All,
+    /// Only tools that don't change files or run commands, e.g. to explain some code
+    ReadOnly,
+    /// Only the tools with these names
+    Only(Vec<ToolName>),
+}
+
+impl ToolSet {
+    /// Remove the tools outside the set from `tools`
+    pub fn apply(&self, tools: &mut ToolRegistry) {
+        match self {
+            ToolSet::All => {}
+            ToolSet::ReadOnly => {
+                tools.retain(|tool| !tool.is_mutating());
+            }
+            ToolSet::Only(names) => {
+                tools.retain(|tool| names.contains(&tool.title()));
+            }
+        }
+    }
+}
+
+/// One step of a run through a node, e.g. to see where it looped, see
+/// [`crate::GraphIter::to_dot`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeVisit {
+    pub node: CurrentNode,
+    /// How long the node took to run
+    pub duration: Duration,
+    /// Whether the node ended the run with an error
+    pub failed: bool,
+}
+
+/// The tokens and time one model request took, e.g. to see which turns were expensive
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TurnUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Input tokens written to or read from the prompt cache
+    pub cache_tokens: u32,
+    /// How long the request took, including any retries
+    pub duration: Duration,
+    /// How long the provider took to send the first event of the response
+    #[serde(default)]
+    pub latency: Duration,
+    /// How long the response took to stream after its first event
+    #[serde(default)]
+    pub streaming: Duration,
+}
+
+/// Where a run spent its time, e.g. to notice a change that slows the agent loop down, see
+/// [`crate::GraphIter::metrics`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunMetrics {
+    /// Each node the run went through, in the order they first ran
+    pub nodes: Vec<NodeMetrics>,
+    /// How long the nodes took altogether
+    pub duration: Duration,
+    pub model_requests: usize,
+    /// The mean time the provider took to send the first event of a response
+    pub mean_latency: Duration,
+    /// Output tokens streamed per second over every model request, after their first event
+    pub output_tokens_per_second: f64,
+}
+
+/// How long a node took over every time it ran
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeMetrics {
+    pub node: CurrentNode,
+    pub runs: usize,
+    pub duration: Duration,
+    /// The longest it took to run once
+    pub max_duration: Duration,
+}
+
+/// A record of one tool call, e.g. for a UI to show how long it took
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolInvocation {
+    pub tool_use_id: String,
+    pub tool_name: ToolName,
+    /// How long the tool ran, zero if it didn't run
+    pub duration: Duration,
+    /// Whether the tool failed, or the call was denied
+    pub is_error: bool,
+    /// Whether the user or a hook denied the call, so the tool never ran
+    pub denied: bool,
+    /// The size of the full output, before any truncation
+    pub output_bytes: usize,
+    /// The exit code of the process the tool ran, e.g. the command of run_command
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// A unified diff of the file the tool changed, if it reported one
+    pub diff: Option<String>,
+}
+
+/// A tool call waiting at the approval gate for the caller to approve or deny it
+#[derive(Debug, Clone)]
+pub struct PendingApproval {
+    pub tool_use_id: String,
+    pub tool_name: ToolName,
+    pub input: Value,
+}
+
 /// State shared between nodes
-#[derive(Debug)]
+///
+/// It holds nothing but data, so a run can be saved, exported or inspected as JSON, see
+/// [`crate::SessionStore`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct State {
     pub message_history: Vec<Message>,
     pub current_user_prompt: String,
+    /// The full output of each tool call by its tool use id, including any truncated in the history
     pub tool_outputs: HashMap<String, String>,
+    /// Every tool call so far, in the order they were made
+    pub tool_invocations: Vec<ToolInvocation>,
+    /// The files the run has changed, relative to the workspace root
+    pub modified_paths: Vec<PathBuf>,
+    /// Whether the run created, modified or deleted each of the files it changed
+    #[serde(default)]
+    pub file_changes: Vec<FileChange>,
+    /// How many requests the run has made to the model, not counting retries
+    pub model_requests: usize,
+    /// The input the model passed to the respond tool, if it was offered one
+    pub structured_result: Option<serde_json::Value>,
+    /// Roughly how many tokens the conversation takes up, counted by the model up to its last
+    /// response and estimated for the messages since
+    #[serde(default)]
+    pub context_tokens: usize,
+    /// How many messages of the history `context_tokens` covers
+    #[serde(default)]
+    pub(crate) counted_messages: usize,
+    /// Everything worth telling the user about so far, in the order it happened
+    #[serde(default)]
+    pub events: Vec<GraphEvent>,
+    /// The tokens of every model request so far, added together
+    #[serde(default)]
+    pub usage: Usage,
+    /// The tokens and time of each model request so far, in order
+    #[serde(default)]
+    pub turn_usage: Vec<TurnUsage>,
+    /// Every node the run has been through so far, in order
+    #[serde(default)]
+    pub path: Vec<NodeVisit>,
+    /// What the model requests so far cost in US dollars, zero if the provider's prices aren't
+    /// known
+    #[serde(default)]
+    pub cost_usd: f64,
+    /// How many rounds of tool calls in a row, up to the latest, had a call to an unknown tool
+    /// or with invalid input
+    #[serde(default)]
+    pub tool_call_corrections: usize,
+    /// The summary standing in for the start of the conversation, see
+    /// [`HistoryPolicy::Summarize`]
+    #[serde(default)]
+    pub history_summary: Option<HistorySummary>,
+    /// Why the model stopped its latest response
+    #[serde(default)]
+    pub stop_reason: Option<StopReason>,
+    /// Whether each tool call waiting at the approval gate may run, by its tool use id
+    #[serde(default)]
+    pub(crate) approvals: HashMap<String, bool>,
+}
+
+impl State {
+    /// Estimate the tokens of the messages added since they were last counted
+    pub(crate) fn count_new_messages(&mut self) {
+        let start = self.counted_messages.min(self.message_history.len());
+        self.context_tokens += self.message_history[start..]
+            .iter()
+            .flat_map(|message| &message.content)
+            .map(estimate_tokens)
+            .sum::<usize>();
+        self.counted_messages = self.message_history.len();
+    }
+
+    /// Every token the model requests so far used, input, output and cached alike
+    pub fn total_tokens(&self) -> u64 {
+        self.usage.input_tokens as u64
+            + self.usage.output_tokens as u64
+            + self.usage.cache_creation_input_tokens as u64
+            + self.usage.cache_read_input_tokens as u64
+    }
+
+    /// How long the run has spent waiting for the model, over every request so far
+    pub fn model_duration(&self) -> Duration {
+        self.turn_usage.iter().map(|turn| turn.duration).sum()
+    }
+
+    /// How long each node and model request has taken so far
+    pub fn metrics(&self) -> RunMetrics {
+        let mut nodes: Vec<NodeMetrics> = Vec::new();
+        for visit in &self.path {
+            match nodes.iter_mut().find(|metrics| metrics.node == visit.node) {
+                Some(metrics) => {
+                    metrics.runs += 1;
+                    metrics.duration += visit.duration;
+                    metrics.max_duration = metrics.max_duration.max(visit.duration);
+                }
+                None => nodes.push(NodeMetrics {
+                    node: visit.node.clone(),
+                    runs: 1,
+                    duration: visit.duration,
+                    max_duration: visit.duration,
+                }),
+            }
+        }
+
+        let requests = self.turn_usage.len();
+        let latency: Duration = self.turn_usage.iter().map(|turn| turn.latency).sum();
+        let streaming: Duration = self.turn_usage.iter().map(|turn| turn.streaming).sum();
+        let output_tokens: u64 = self
+            .turn_usage
+            .iter()
+            .map(|turn| turn.output_tokens as u64)
+            .sum();
+        RunMetrics {
+            duration: nodes.iter().map(|metrics| metrics.duration).sum(),
+            nodes,
+            model_requests: requests,
+            mean_latency: latency / (requests.max(1) as u32),
+            output_tokens_per_second: match streaming.as_secs_f64() {
+                0.0 => 0.0,
+                seconds => output_tokens as f64 / seconds,
+            },
+        }
+    }
+
+    /// Take the model's count of the conversation up to and including its latest response
+    pub(crate) fn count_usage(&mut self, usage: &Usage) {
+        self.context_tokens = (usage.input_tokens
+            + usage.cache_creation_input_tokens
+            + usage.cache_read_input_tokens
+            + usage.output_tokens) as usize;
+        self.counted_messages = self.message_history.len();
+    }
+}
+
+/// Roughly how many tokens a content block takes up
+fn estimate_tokens(block: &ContentBlock) -> usize {
+    let bytes = match block {
+        ContentBlock::Text { text } | ContentBlock::CitedText { text, .. } => text.len(),
+        ContentBlock::ToolUse { name, input, .. } => name.as_str().len() + input.to_string().len(),
+        ContentBlock::ToolResult {
+            content, images, ..
+        } => {
+            return content.len().div_ceil(BYTES_PER_TOKEN) + images.len() * TOKENS_PER_IMAGE;
+        }
+        block => serde_json::to_string(block).map_or(0, |json| json.len()),
+    };
+    bytes.div_ceil(BYTES_PER_TOKEN)
 }
 
 /// Dependencies that nodes need to function
 pub struct Deps<P: BaseProvider> {
-    pub provider: P,
-    pub tools: Option<Vec<ToolType>>,
+    /// Shared with the agent and its other runs, so a provider needn't be `Clone`
+    pub provider: Arc<P>,
+    pub tools: Option<ToolRegistry>,
+    pub tool_choice: Option<ToolChoice>,
     pub system_prompt: String,
-    pub max_tokens: u32,
+    pub max_tokens: Option<u32>,
     pub temperature: Option<f64>,
+    /// Tool output beyond this many bytes is truncated before it reaches the model
+    pub max_tool_output_bytes: usize,
+    /// The run ends with an error once it has made this many model requests
+    pub max_iterations: usize,
+    /// How much of a long conversation is sent with each model request
+    pub history_policy: HistoryPolicy,
+    /// Calls to unknown tools or with invalid input are sent back to the model to correct, and
+    /// the run ends with an error once this many rounds of tool calls in a row had one
+    pub max_tool_call_corrections: usize,
+    /// The run is cancelled once it has taken this long, ending with what it has so far
+    pub max_run_duration: Option<Duration>,
+    /// The run ends with an error before a model request once it has cost this many US
+    /// dollars, only enforced for providers whose prices are known
+    pub max_cost_usd: Option<f64>,
+    /// The run ends with an error before a model request once its requests have used this many
+    /// tokens in total
+    pub max_total_tokens: Option<u64>,
     pub stream_wrapper: Box<dyn StreamWrapper>,
+    /// Consulted before running a tool that changes files or runs commands
+    pub approval: Box<dyn ApprovalPolicy>,
+    /// Pause at [`CurrentNode::AwaitApproval`] before running tools that change files or run
+    /// commands, for the caller to approve or deny them on the iterator instead of `approval`
+    pub await_approval: bool,
+    /// Snapshots of the files the run changes, shared with the tools that change them
+    pub backups: Backups,
+    /// The directory the tools are confined to, which the paths they write are checked against
+    pub workspace: Workspace,
+    /// Called at key points of the run, e.g. before and after each tool call
+    pub hooks: Box<dyn Hooks>,
+    /// Check the model's final responses and the files it writes, in order
+    pub validators: Vec<Arc<dyn Validator>>,
+    /// What happens when a validator rejects the model's output
+    pub on_violation: ViolationAction,
+    /// Background given to the model ahead of the first prompt of a conversation, e.g. an
+    /// overview of the repository
+    pub context: Option<String>,
+    /// Example exchanges that lead a new conversation, to show the model the style and use of
+    /// tools wanted. They start with a user message and end with an assistant one, and any tool
+    /// call in them is answered by a tool result in the next message
+    pub examples: Vec<Message>,
+    /// Cancelling it stops the run at the next chance, e.g. mid-stream or while tools run
+    pub cancellation: CancellationToken,
+    /// Nodes added by the caller, by name
+    pub(crate) nodes: HashMap<String, Arc<dyn CustomNode>>,
+    /// Transitions the caller redirected, by the node they leave and the transition it returned
+    pub(crate) routes: HashMap<(CurrentNode, NodeTransition), NodeTransition>,
+    /// Where the model's responses and the tools' results are recorded, see
+    /// [`crate::GraphIter::record_trace`]
+    pub(crate) recorder: Option<Arc<Mutex<Trace>>>,
+    /// Where events are sent as they happen, see [`Deps::subscribe`]
+    pub(crate) subscribers: Vec<UnboundedSender<AgentEvent>>,
 }
 
 impl<P: BaseProvider> Deps<P> {
     pub fn new(
         provider: P,
-        tools: Option<Vec<ToolType>>,
+        tools: Option<ToolRegistry>,
         system_prompt: String,
-        max_tokens: u32,
+        max_tokens: Option<u32>,
+        temperature: Option<f64>,
+        stream_wrapper: Option<Box<dyn StreamWrapper>>,
+    ) -> Self {
+        Self::with_shared_provider(
+            Arc::new(provider),
+            tools,
+            system_prompt,
+            max_tokens,
+            temperature,
+            stream_wrapper,
+        )
+    }
+
+    /// The same as [`Deps::new`] with a provider that other runs use too, e.g. a subagent's
+    pub fn with_shared_provider(
+        provider: Arc<P>,
+        tools: Option<ToolRegistry>,
+        system_prompt: String,
+        max_tokens: Option<u32>,
         temperature: Option<f64>,
         stream_wrapper: Option<Box<dyn StreamWrapper>>,
     ) -> Self {
         Self {
             provider,
             tools,
+            tool_choice: None,
             system_prompt,
             max_tokens,
             temperature,
-            stream_wrapper: stream_wrapper
-                .unwrap_or_else(|| Box::new(NoopStreamWrapper::default())),
+            max_tool_output_bytes: DEFAULT_MAX_TOOL_OUTPUT_BYTES,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            max_tool_call_corrections: DEFAULT_MAX_TOOL_CALL_CORRECTIONS,
+            history_policy: HistoryPolicy::KeepAll,
+            max_run_duration: None,
+            max_cost_usd: None,
+            max_total_tokens: None,
+            stream_wrapper: stream_wrapper.unwrap_or_else(|| Box::new(NoopStreamWrapper)),
+            approval: Box::new(AlwaysApprove),
+            await_approval: false,
+            backups: Backups::default(),
+            workspace: Workspace::default(),
+            hooks: Box::new(NoHooks),
+            validators: Vec::new(),
+            on_violation: ViolationAction::Reprompt,
+            context: None,
+            examples: Vec::new(),
+            cancellation: CancellationToken::new(),
+            nodes: HashMap::new(),
+            routes: HashMap::new(),
+            recorder: None,
+            subscribers: Vec::new(),
         }
     }
+
+    /// Receive the run's events as they happen, e.g. to show tool calls while they run
+    pub fn subscribe(&mut self) -> UnboundedReceiver<AgentEvent> {
+        let (sender, receiver) = unbounded_channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Send an event to every subscriber still listening
+    pub(crate) fn emit(&self, event: AgentEvent) {
+        for subscriber in &self.subscribers {
+            let _ = subscriber.send(event.clone());
+        }
+    }
+
+    /// Add a node to the graph, reached through [`NodeTransition::ToNode`] with its name
+    pub fn add_node(&mut self, name: &str, node: Arc<dyn CustomNode>) -> &mut Self {
+        self.nodes.insert(name.to_string(), node);
+        self
+    }
+
+    /// Take `to` wherever the `from` node returns `transition`
+    ///
+    /// For example, route [`CurrentNode::CallTools`]'s [`NodeTransition::ToModelRequest`] to
+    /// `NodeTransition::ToNode("lint".to_string())` to run a lint node after each round of tool
+    /// calls, which itself returns [`NodeTransition::ToModelRequest`] to carry on.
+    pub fn route(
+        &mut self,
+        from: CurrentNode,
+        transition: NodeTransition,
+        to: NodeTransition,
+    ) -> &mut Self {
+        self.routes.insert((from, transition), to);
+        self
+    }
 }
 
-/// A trait for running node logic without the associated type
-/// This allows us to use dynamic dispatch with trait objects
+/// A trait for running a built-in node's logic
+#[allow(async_fn_in_trait)]
 pub trait NodeRunner<P: BaseProvider>: Debug {
+    /// The transitions the node can return, only those leading to nodes it may go to next
+    type Transition: Transitions;
+
     /// Run the node's logic
     async fn run(
         &self,
         state: &mut State,
         deps: &Deps<P>,
-    ) -> std::result::Result<NodeTransition, GraphError>;
+    ) -> std::result::Result<Self::Transition, GraphError>;
+}
+
+/// The transitions one built-in node can return, each one of [`NodeTransition`]
+///
+/// Giving each node a type of its own means it can't return a transition the graph isn't wired
+/// for, and the graph's edges can be read off the types, see [`crate::GraphIter::to_dot`].
+pub trait Transitions: Copy + Into<NodeTransition> + 'static {
+    /// Every transition of the type
+    const ALL: &'static [Self];
 }
 
 /// Enum to represent all possible node transitions
-#[derive(Debug)]
+///
+/// Custom nodes and routes use it directly, the built-in nodes return a narrower type, see
+/// [`Transitions`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum NodeTransition {
     ToUserRequest,
     ToModelRequest,
     ToCallTools,
     ToEnd,
+    ToAwaitApproval,
+    /// To a node added with [`Deps::add_node`], by its name
+    ToNode(String),
     Terminal,
 }
 
+impl NodeTransition {
+    /// The node the transition leads to, `None` for [`NodeTransition::Terminal`]
+    pub fn target(&self) -> Option<CurrentNode> {
+        match self {
+            NodeTransition::ToUserRequest => Some(CurrentNode::UserRequest),
+            NodeTransition::ToModelRequest => Some(CurrentNode::ModelRequest),
+            NodeTransition::ToCallTools => Some(CurrentNode::CallTools),
+            NodeTransition::ToEnd => Some(CurrentNode::End),
+            NodeTransition::ToAwaitApproval => Some(CurrentNode::AwaitApproval),
+            NodeTransition::ToNode(name) => Some(CurrentNode::Custom(name.clone())),
+            NodeTransition::Terminal => None,
+        }
+    }
+}
+
 /// Enum representing the current node in the graph
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CurrentNode {
     Start,
     UserRequest,
     ModelRequest,
     CallTools,
     End,
+    /// Waiting for the caller to approve or deny the tool calls that change something
+    AwaitApproval,
+    /// A node added with [`Deps::add_node`], by its name
+    Custom(String),
 }
@@ -0,0 +1,78 @@
+use crate::graph::models::{
+    Deps, GraphError, NodeRunner, NodeTransition, PendingApproval, State, Transitions,
+};
+use providers::models::ContentBlock;
+use providers::{BaseProvider, Role};
+
+/// The approval gate node, entered before running tools that change files or run commands
+///
+/// The iterator yields it so the caller can approve or deny the calls, running it then moves
+/// on to the tool calls. Any call left undecided is denied.
+#[derive(Debug)]
+pub struct AwaitApproval;
+
+impl<P: BaseProvider> NodeRunner<P> for AwaitApproval {
+    type Transition = AwaitApprovalTransition;
+
+    async fn run(
+        &self,
+        state: &mut State,
+        deps: &Deps<P>,
+    ) -> std::result::Result<Self::Transition, GraphError> {
+        for pending in pending_approvals(state, deps) {
+            state.approvals.insert(pending.tool_use_id, false);
+        }
+
+        Ok(AwaitApprovalTransition::ToCallTools)
+    }
+}
+
+/// Where [`AwaitApproval`] can lead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwaitApprovalTransition {
+    ToCallTools,
+}
+
+impl Transitions for AwaitApprovalTransition {
+    const ALL: &'static [Self] = &[AwaitApprovalTransition::ToCallTools];
+}
+
+impl From<AwaitApprovalTransition> for NodeTransition {
+    fn from(transition: AwaitApprovalTransition) -> Self {
+        match transition {
+            AwaitApprovalTransition::ToCallTools => NodeTransition::ToCallTools,
+        }
+    }
+}
+
+/// The calls in the model's latest response to tools that change something, that haven't been
+/// approved or denied yet
+pub(crate) fn pending_approvals<P: BaseProvider>(
+    state: &State,
+    deps: &Deps<P>,
+) -> Vec<PendingApproval> {
+    let (Some(tools), Some(message)) = (&deps.tools, state.message_history.last()) else {
+        return Vec::new();
+    };
+    if message.role != Role::Assistant {
+        return Vec::new();
+    }
+
+    message
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse { id, name, input }
+                if !state.approvals.contains_key(id)
+                    && tools.get(name).is_some_and(|tool| tool.is_mutating()) =>
+            {
+                Some(PendingApproval {
+                    tool_use_id: id.clone(),
+                    tool_name: name.clone(),
+                    input: input.clone(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
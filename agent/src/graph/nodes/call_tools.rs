@@ -1,26 +1,72 @@
-use crate::graph::models::{Deps, GraphError, NodeRunner, NodeTransition, State};
-use providers::{models::ContentBlock, Message, BaseProvider, Role};
-use serde_json::Value;
-use tools::{
-    models::{ToolName, ToolResult},
-    ListFilesInput, ReadFileInput, RunCommandInput, Tool, ToolType, TreeInput, WriteFileInput,
+use crate::graph::models::{
+    AgentEvent, Deps, GraphError, GraphEvent, NodeRunner, NodeTransition, State, ToolDecision,
+    ToolInvocation, Transitions, DEFAULT_DENIAL_REASON,
 };
+use crate::guardrails::{self, Output, ViolationAction};
+use crate::replay::TracedToolCall;
+use futures_util::future::join_all;
+use providers::models::{ContentBlock, Image, Usage};
+use providers::{BaseProvider, Message, Role};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tools::models::{ToolContent, ToolName, ToolResult};
+use tools::{ReadToolOutputInput, Tool};
+use tracing::Instrument;
+
+/// How often a tool that is still running is reported with [`AgentEvent::ToolProgress`]
+const TOOL_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
 
 /// The tool calling node
 #[derive(Debug)]
 pub struct CallTools;
 
+/// Where [`CallTools`] can lead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallToolsTransition {
+    /// Send the results to the model
+    ToModelRequest,
+    /// The model gave its final answer through the respond tool
+    ToEnd,
+}
+
+impl Transitions for CallToolsTransition {
+    const ALL: &'static [Self] = &[
+        CallToolsTransition::ToModelRequest,
+        CallToolsTransition::ToEnd,
+    ];
+}
+
+impl From<CallToolsTransition> for NodeTransition {
+    fn from(transition: CallToolsTransition) -> Self {
+        match transition {
+            CallToolsTransition::ToModelRequest => NodeTransition::ToModelRequest,
+            CallToolsTransition::ToEnd => NodeTransition::ToEnd,
+        }
+    }
+}
+
+/// How one of the model's tool calls is handled, decided before any of them run
+enum Call<'a> {
+    /// Answered without running a tool, e.g. a call to an unknown tool or one the user denied
+    Answered { result: ToolResult, denied: bool },
+    /// Run the tool
+    Run(&'a Arc<dyn Tool>),
+}
+
 impl<P: BaseProvider> NodeRunner<P> for CallTools {
+    type Transition = CallToolsTransition;
+
     async fn run(
         &self,
         state: &mut State,
         deps: &Deps<P>,
-    ) -> std::result::Result<NodeTransition, GraphError> {
+    ) -> std::result::Result<Self::Transition, GraphError> {
         // Check if the last message is from the assistant and contains a tool use request
-        let last_msg = state
-            .message_history
-            .last()
-            .ok_or_else(|| GraphError::Other(anyhow::anyhow!("No messages in history")))?;
+        let last_msg = state.message_history.last().ok_or_else(|| {
+            GraphError::InvalidStateTransition("No messages in history".to_string())
+        })?;
 
         // Only process if the last message is from the assistant
         if last_msg.role != Role::Assistant {
@@ -29,165 +75,479 @@ impl<P: BaseProvider> NodeRunner<P> for CallTools {
             ));
         }
 
-        // Extract tool use content block and process it
-        for content_block in &last_msg.content {
-            if let ContentBlock::ToolUse { id, name, input } = content_block {
-                // Make sure we have tools available
-                let tools = deps.tools.as_ref().ok_or_else(|| {
-                    GraphError::Other(anyhow::anyhow!(
-                        "No tools available in the agent's dependencies"
-                    ))
-                })?;
-
-                // Execute the tool
-                let tool_result = execute_tool(name, input, tools)
-                    .await
-                    .map_err(|e| GraphError::Other(e))?;
-
-                // Create result message text
-                let result_content = match tool_result.is_error {
-                    true => format!("Error: {}", tool_result.content),
-                    false => format!("{}", tool_result.content),
+        // Every tool call in the message needs a result
+        let tool_uses: Vec<(String, ToolName, Value)> = last_msg
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { id, name, input } => {
+                    Some((id.clone(), name.clone(), input.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        if tool_uses.is_empty() {
+            return Err(GraphError::InvalidStateTransition(
+                "No tool use request found in the last message".to_string(),
+            ));
+        }
+
+        // Make sure we have tools available
+        let tools = deps.tools.as_ref().ok_or_else(|| {
+            GraphError::InvalidStateTransition(
+                "No tools available in the agent's dependencies".to_string(),
+            )
+        })?;
+
+        if deps.cancellation.is_cancelled() {
+            return Err(cancel(state, &tool_uses));
+        }
+
+        // Decide how each call is handled before running any, so the user is asked about
+        // those that change something one at a time. Mistakes the model can correct, such as
+        // an unknown tool or invalid input, are reported back to it rather than ending the run
+        let mut calls = Vec::with_capacity(tool_uses.len());
+        let mut mistake = None;
+        let mut violation = None;
+        for (id, name, input) in &tool_uses {
+            // Files are checked before they're written, so a rejected write never happens
+            let tool = tools.get(name);
+            let rejection = tool
+                .filter(|tool| tool.is_mutating())
+                .and_then(|_| reject(deps, name, input));
+            let call = match (
+                tool.map(|tool| (tool, tool.validate_input(input))),
+                rejection,
+            ) {
+                (None, _) => {
+                    mistake.get_or_insert(GraphError::ToolNotImplemented(name.to_string()));
+                    Call::Answered {
+                        result: ToolResult::error(format!(
+                            "There is no tool named {}, use one of: {}",
+                            name,
+                            tools
+                                .iter()
+                                .map(|tool| tool.title().to_string())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )),
+                        denied: false,
+                    }
+                }
+                (Some((tool, Err(e))), _) => {
+                    let schema = tool.input_schema().unwrap_or_default();
+                    mistake.get_or_insert(GraphError::ToolExecution {
+                        tool: name.clone(),
+                        message: format!("Invalid input: {}", e),
+                    });
+                    Call::Answered {
+                        result: ToolResult::error(format!(
+                            "Invalid input for {}: {}. The call was not run, call it again \
+                                with input matching this schema: {}",
+                            name, e, schema
+                        )),
+                        denied: false,
+                    }
+                }
+                (Some(_), Some(reason)) => {
+                    state
+                        .events
+                        .push(GraphEvent::GuardrailViolation(reason.clone()));
+                    violation.get_or_insert(GraphError::GuardrailViolation(reason.clone()));
+                    Call::Answered {
+                        result: ToolResult::error(format!(
+                            "Rejected: the {} call was not run. {}",
+                            name, reason
+                        )),
+                        denied: true,
+                    }
+                }
+                (Some((tool, Ok(()))), None) => match deps.hooks.on_tool_start(name, input).await {
+                    ToolDecision::Block(reason) => Call::Answered {
+                        result: ToolResult::error(format!(
+                            "Blocked: the {} call was not run. {}",
+                            name, reason
+                        )),
+                        denied: true,
+                    },
+                    // Earlier outputs are kept in the state, so reading them is answered here
+                    ToolDecision::Allow if name.as_str() == "read_tool_output" => Call::Answered {
+                        result: read_tool_output(state, input, deps.max_tool_output_bytes),
+                        denied: false,
+                    },
+                    ToolDecision::Allow if tool.is_mutating() => {
+                        match approve(state, deps, id, name, input).await {
+                            ToolDecision::Allow => Call::Run(tool),
+                            ToolDecision::Block(reason) => Call::Answered {
+                                result: ToolResult::error(format!(
+                                    "Denied by user: the {} call was not run. {}",
+                                    name, reason
+                                )),
+                                denied: true,
+                            },
+                        }
+                    }
+                    ToolDecision::Allow => Call::Run(tool),
+                },
+            };
+            calls.push(call);
+        }
+        // Decisions made at the approval gate only cover this round of calls
+        state.approvals.clear();
+
+        // Calls run in the order they were made, except that consecutive calls that only read
+        // run at the same time. One that changes something runs on its own once the calls
+        // before it are done, so a read after a write sees what was written
+        let run = |index: usize, tool: &Arc<dyn Tool>| {
+            let (_, name, input) = &tool_uses[index];
+            deps.emit(AgentEvent::ToolStarted {
+                tool_use_id: tool_uses[index].0.clone(),
+                tool_name: name.clone(),
+                input: input.clone(),
+            });
+            let span = tracing::debug_span!(
+                target: "aria::agent",
+                "tool",
+                name = %name,
+                tool_use_id = %tool_uses[index].0,
+            );
+            let id = tool_uses[index].0.clone();
+            let name = name.clone();
+            let input = input.clone();
+            let tool = tool.clone();
+            async move {
+                let started = Instant::now();
+                let call = tool.call(input);
+                tokio::pin!(call);
+                let mut progress = tokio::time::interval_at(
+                    tokio::time::Instant::now() + TOOL_PROGRESS_INTERVAL,
+                    TOOL_PROGRESS_INTERVAL,
+                );
+                let result = loop {
+                    tokio::select! {
+                        result = &mut call => break result,
+                        _ = progress.tick() => deps.emit(AgentEvent::ToolProgress {
+                            tool_use_id: id.clone(),
+                            tool_name: name.clone(),
+                            elapsed: started.elapsed(),
+                        }),
+                    }
                 };
+                let duration = started.elapsed();
+                tracing::debug!(
+                    target: "aria::agent",
+                    duration_ms = duration.as_millis() as u64,
+                    is_error = result.is_error,
+                    "Tool finished"
+                );
+                (index, result, duration)
+            }
+            .instrument(span)
+        };
+        let all = async {
+            let mut results = Vec::with_capacity(calls.len());
+            let mut reads = Vec::new();
+            for (index, call) in calls.iter().enumerate() {
+                let Call::Run(tool) = call else {
+                    continue;
+                };
+                if !tool.is_mutating() {
+                    reads.push((index, *tool));
+                    continue;
+                }
+                let batch = reads.drain(..).map(|(index, tool)| run(index, tool));
+                results.extend(join_all(batch).await);
+                results.push(run(index, tool).await);
+            }
+            let batch = reads.into_iter().map(|(index, tool)| run(index, tool));
+            results.extend(join_all(batch).await);
+            results
+        };
+        let results = tokio::select! {
+            biased;
+            _ = deps.cancellation.cancelled() => return Err(cancel(state, &tool_uses)),
+            results = all => results,
+        };
+        let mut ran: HashMap<usize, (ToolResult, Duration)> = results
+            .into_iter()
+            .map(|(index, result, duration)| (index, (result, duration)))
+            .collect();
 
-                // Store the tool output in the state's tool_outputs HashMap
-                state
-                    .tool_outputs
-                    .insert(id.clone(), result_content.clone());
-
-                // Add the tool result message to the message history
-                state.message_history.push(Message {
-                    role: Role::User,
-                    content: vec![ContentBlock::ToolResult {
-                        tool_use_id: id.clone(),
-                        content: result_content,
-                    }],
+        // The results are sent back together, in the order the calls were made
+        let mut results = Vec::with_capacity(tool_uses.len());
+        let mut is_final_response = false;
+        for (index, ((id, name, input), call)) in tool_uses.iter().zip(calls).enumerate() {
+            let (tool_result, duration, denied, context) = match call {
+                Call::Answered { result, denied } => (result, Duration::ZERO, denied, None),
+                Call::Run(_) => {
+                    let (result, duration) =
+                        ran.remove(&index)
+                            .ok_or_else(|| GraphError::ToolExecution {
+                                tool: name.clone(),
+                                message: "The call produced no result".to_string(),
+                            })?;
+                    let context = deps.hooks.on_tool_end(name, input, &result).await;
+                    (result, duration, false, context)
+                }
+            };
+
+            if let Some(Ok(mut trace)) = deps.recorder.as_ref().map(|recorder| recorder.lock()) {
+                trace.tool_calls.push(TracedToolCall {
+                    tool_name: name.clone(),
+                    input: input.clone(),
+                    is_mutating: tools.get(name).is_some_and(|tool| tool.is_mutating()),
+                    is_error: tool_result.is_error,
+                    output: tool_result.content.to_string(),
                 });
+            }
 
-                // Found and processed a tool, transition to the model request node
-                return Ok(NodeTransition::ToModelRequest);
+            // Create result message text
+            let mut result_content = match tool_result.is_error {
+                true => format!("Error: {}", tool_result.content),
+                false => format!("{}", tool_result.content),
+            };
+            if let Some(context) = context {
+                result_content.push_str("\n\n");
+                result_content.push_str(&context);
             }
-        }
 
-        // If we get here, no tool use was found
-        Err(GraphError::InvalidStateTransition(
-            "No tool use request found in the last message".to_string(),
-        ))
-    }
-}
+            let invocation = ToolInvocation {
+                tool_use_id: id.clone(),
+                tool_name: name.clone(),
+                duration,
+                is_error: tool_result.is_error,
+                denied,
+                output_bytes: result_content.len(),
+                exit_code: tool_result.exit_code,
+                diff: match &tool_result.content {
+                    ToolContent::Diff { diff, .. } => Some(diff.clone()),
+                    _ => None,
+                },
+            };
+            deps.emit(AgentEvent::ToolFinished(invocation.clone()));
+            state.tool_invocations.push(invocation);
 
-/// Execute a tool based on its name and input
-async fn execute_tool(
-    tool_name: &ToolName,
-    input: &Value,
-    tools: &Vec<ToolType>,
-) -> anyhow::Result<ToolResult> {
-    // Execute the tool based on its name
-    match tool_name {
-        ToolName::ListFiles => {
-            // Find the ListFiles tool in the tools vec
-            let tool = tools
-                .iter()
-                .find_map(|t| {
-                    if let ToolType::ListFiles(tool) = t {
-                        Some(tool)
-                    } else {
-                        None
-                    }
-                })
-                .ok_or_else(|| anyhow::anyhow!("ListFiles tool not found"))?;
+            // A subagent's requests are part of this run, so they count towards its limits
+            if let Some(spend) = tool_result.model_spend {
+                state.usage.accumulate(&Usage {
+                    input_tokens: spend.input_tokens,
+                    output_tokens: spend.output_tokens,
+                    cache_creation_input_tokens: spend.cache_creation_input_tokens,
+                    cache_read_input_tokens: spend.cache_read_input_tokens,
+                });
+                state.cost_usd += spend.cost_usd;
+            }
 
-            // Parse the input
-            let input: ListFilesInput = serde_json::from_value(input.clone())?;
+            // The respond tool carries the final answer, so record it and end the run. If its
+            // input didn't match the schema the model is told why and can try again
+            if name.as_str() == "respond" && !tool_result.is_error {
+                state.structured_result = Some(input.clone());
+                is_final_response = true;
+            }
 
-            println!("ListFiles input: {:?}", input);
+            // Images are passed alongside the text, which only names them
+            let images = match tool_result.content {
+                ToolContent::Image { media_type, data } => vec![Image { media_type, data }],
+                _ => Vec::new(),
+            };
 
-            // Execute the tool
-            Ok(tool.run(input).await)
+            // Keep the whole output, so a truncated one can be read a page at a time
+            state
+                .tool_outputs
+                .insert(id.clone(), result_content.clone());
+            let result_content = match name.as_str() == "read_tool_output" {
+                true => result_content,
+                false => page_output(&result_content, id, 0, deps.max_tool_output_bytes, name),
+            };
+
+            results.push(ContentBlock::ToolResult {
+                tool_use_id: id.clone(),
+                content: result_content,
+                images,
+            });
         }
-        ToolName::ReadFile => {
-            // Find the ReadFile tool in the tools vec
-            let tool = tools
-                .iter()
-                .find_map(|t| {
-                    if let ToolType::ReadFile(tool) = t {
-                        Some(tool)
-                    } else {
-                        None
-                    }
-                })
-                .ok_or_else(|| anyhow::anyhow!("ReadFile tool not found"))?;
+        state.modified_paths = deps.backups.modified_paths();
+        state.file_changes = deps.backups.changes();
 
-            // Parse the input
-            let input: ReadFileInput = serde_json::from_value(input.clone())?;
+        // Add the tool results to the message history
+        let message = Message {
+            role: Role::User,
+            content: results,
+        };
+        deps.hooks.on_message(&message).await;
+        state.message_history.push(message);
 
-            println!("ListFiles input: {:?}", input);
+        // The model is told about its mistakes above, but a model that keeps making them isn't
+        // going to get anywhere
+        if let (Some(e), ViolationAction::Block) = (violation, deps.on_violation) {
+            return Err(e);
+        }
+        match mistake {
+            Some(e) => {
+                state.tool_call_corrections += 1;
+                if state.tool_call_corrections > deps.max_tool_call_corrections {
+                    return Err(e);
+                }
+            }
+            None => state.tool_call_corrections = 0,
+        }
 
-            // Execute the tool
-            Ok(tool.run(input).await)
+        if is_final_response {
+            return Ok(CallToolsTransition::ToEnd);
         }
-        ToolName::RunCommand => {
-            // Find the RunCommand tool in the tools vec
-            let tool = tools
-                .iter()
-                .find_map(|t| {
-                    if let ToolType::RunCommand(tool) = t {
-                        Some(tool)
-                    } else {
-                        None
-                    }
-                })
-                .ok_or_else(|| anyhow::anyhow!("RunCommand tool not found"))?;
 
-            // Parse the input
-            let input: RunCommandInput = serde_json::from_value(input.clone())?;
+        // Processed the tools, transition to the model request node
+        Ok(CallToolsTransition::ToModelRequest)
+    }
+}
 
-            println!("ListFiles input: {:?}", input);
+/// Why the validators reject the file a tool call would write, if they do
+fn reject<P: BaseProvider>(deps: &Deps<P>, name: &ToolName, input: &Value) -> Option<String> {
+    guardrails::file_writes(name, input, &deps.workspace)
+        .iter()
+        .find_map(|(path, contents)| {
+            guardrails::check(&deps.validators, &Output::FileWrite { path, contents }).err()
+        })
+}
 
-            // Execute the tool
-            Ok(tool.run(input).await)
-        }
-        ToolName::Tree => {
-            // Find the Tree tool in the tools vec
-            let tool = tools
-                .iter()
-                .find_map(|t| {
-                    if let ToolType::Tree(tool) = t {
-                        Some(tool)
-                    } else {
-                        None
-                    }
-                })
-                .ok_or_else(|| anyhow::anyhow!("Tree tool not found"))?;
+/// Whether a call to a tool that changes something may run, as decided at the approval gate
+/// or otherwise by the approval policy
+async fn approve<P: BaseProvider>(
+    state: &State,
+    deps: &Deps<P>,
+    id: &str,
+    name: &ToolName,
+    input: &Value,
+) -> ToolDecision {
+    match state.approvals.get(id) {
+        Some(true) => ToolDecision::Allow,
+        Some(false) => ToolDecision::Block(DEFAULT_DENIAL_REASON.to_string()),
+        None => deps.approval.decide(name, input).await,
+    }
+}
 
-            // Parse the input
-            let input: TreeInput = serde_json::from_value(input.clone())?;
+/// Answer every tool call as cancelled, so the conversation can still be continued, returning
+/// the error that ends the run
+fn cancel(state: &mut State, tool_uses: &[(String, ToolName, Value)]) -> GraphError {
+    state.message_history.push(Message {
+        role: Role::User,
+        content: tool_uses
+            .iter()
+            .map(|(id, name, _)| ContentBlock::ToolResult {
+                tool_use_id: id.clone(),
+                content: format!(
+                    "Error: Cancelled by user, the {} call may not have run or finished",
+                    name
+                ),
+                images: Vec::new(),
+            })
+            .collect(),
+    });
+    GraphError::Cancelled
+}
 
-            println!("ListFiles input: {:?}", input);
+/// Answer a read_tool_output call with a page of an earlier output kept in the state
+fn read_tool_output(state: &State, input: &Value, max_bytes: usize) -> ToolResult {
+    let input: ReadToolOutputInput = match serde_json::from_value(input.clone()) {
+        Ok(input) => input,
+        Err(e) => return ToolResult::error(format!("Invalid input for read_tool_output: {}", e)),
+    };
 
-            // Execute the tool
-            Ok(tool.run(input).await)
-        }
-        ToolName::WriteFile => {
-            // Find the WriteFile tool in the tools vec
-            let tool = tools
-                .iter()
-                .find_map(|t| {
-                    if let ToolType::WriteFile(tool) = t {
-                        Some(tool)
-                    } else {
-                        None
-                    }
-                })
-                .ok_or_else(|| anyhow::anyhow!("WriteFile tool not found"))?;
+    let Some(output) = state.tool_outputs.get(&input.id) else {
+        return ToolResult::error(format!("No tool output with id {}", input.id));
+    };
+    let offset = input.offset.unwrap_or(0);
+    if offset >= output.len() {
+        return ToolResult::error(format!(
+            "Offset {} is past the end of the output, which is {} bytes",
+            offset,
+            output.len()
+        ));
+    }
 
-            // Parse the input
-            let input: WriteFileInput = serde_json::from_value(input.clone())?;
+    ToolResult::success(ToolContent::String(page_output(
+        output,
+        &input.id,
+        offset,
+        max_bytes,
+        &ToolName::from("read_tool_output"),
+    )))
+}
+
+/// The page of output starting at `offset`, at most `max_bytes` long but always at least one
+/// character so paging gets through, telling the model how to read the next page if there is more
+fn page_output(
+    output: &str,
+    id: &str,
+    offset: usize,
+    max_bytes: usize,
+    tool_name: &ToolName,
+) -> String {
+    let mut start = offset.min(output.len());
+    while !output.is_char_boundary(start) {
+        start -= 1;
+    }
+    if start == 0 && output.len() <= max_bytes {
+        return output.to_string();
+    }
 
-            println!("ListFiles input: {:?}", input);
+    let mut end = start.saturating_add(max_bytes).min(output.len());
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    if end == start {
+        end = output[start..]
+            .chars()
+            .next()
+            .map_or(start, |c| start + c.len_utf8());
+    }
+    if end == output.len() {
+        return format!(
+            "{}\n\n[End of output: showing bytes {} to {} of {}]",
+            &output[start..end],
+            start,
+            end,
+            output.len()
+        );
+    }
 
-            // Execute the tool
-            Ok(tool.run(input).await)
+    let hint = match tool_name.as_str() {
+        "read_file" => {
+            "read the rest in smaller pieces with start_line and end_line, continuing after the \
+            last line number shown."
+        }
+        "run_command" => {
+            "narrow the output with the command's own flags, or set shell and pipe it through \
+            head, tail or grep."
         }
+        "read_notebook" => "set include_outputs to false to see only the source of the cells.",
+        _ => "narrow the request to see the rest.",
+    };
+
+    format!(
+        "{}\n\n[Output truncated: showing bytes {} to {} of {}. Read the next page with \
+        read_tool_output, id \"{}\" and offset {}, or {}]",
+        &output[start..end],
+        start,
+        end,
+        output.len(),
+        id,
+        end,
+        hint
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_advance_by_at_least_a_character() {
+        let output = "é🦀";
+        let tool_name = ToolName::from("run_command");
+        let first = page_output(output, "1", 0, 1, &tool_name);
+        assert!(first.starts_with("é\n\n"));
+        let second = page_output(output, "1", "é".len(), 2, &tool_name);
+        assert!(second.starts_with("🦀\n\n[End of output"));
     }
 }
@@ -1,4 +1,4 @@
-use crate::graph::models::{Deps, GraphError, NodeRunner, NodeTransition, State};
+use crate::graph::models::{Deps, GraphError, NodeRunner, NodeTransition, State, Transitions};
 use providers::BaseProvider;
 
 /// The end node
@@ -6,12 +6,34 @@ use providers::BaseProvider;
 pub struct End;
 
 impl<P: BaseProvider> NodeRunner<P> for End {
+    type Transition = EndTransition;
+
     async fn run(
         &self,
-        _state: &mut State,
-        _deps: &Deps<P>,
-    ) -> std::result::Result<NodeTransition, GraphError> {
+        state: &mut State,
+        deps: &Deps<P>,
+    ) -> std::result::Result<Self::Transition, GraphError> {
+        deps.hooks.on_turn_end(state).await;
+
         // End node doesn't transition to any other node
-        Ok(NodeTransition::Terminal)
+        Ok(EndTransition::Terminal)
+    }
+}
+
+/// Where [`End`] can lead, nowhere as the run is over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndTransition {
+    Terminal,
+}
+
+impl Transitions for EndTransition {
+    const ALL: &'static [Self] = &[EndTransition::Terminal];
+}
+
+impl From<EndTransition> for NodeTransition {
+    fn from(transition: EndTransition) -> Self {
+        match transition {
+            EndTransition::Terminal => NodeTransition::Terminal,
+        }
     }
 }
@@ -1,11 +1,14 @@
+mod await_approval;
 mod call_tools;
 mod end;
 mod model_request;
 mod start;
 mod user_request;
 
-pub use call_tools::CallTools;
-pub use end::End;
-pub use model_request::ModelRequest;
-pub use start::Start;
-pub use user_request::UserRequest;
+pub(crate) use await_approval::pending_approvals;
+pub use await_approval::{AwaitApproval, AwaitApprovalTransition};
+pub use call_tools::{CallTools, CallToolsTransition};
+pub use end::{End, EndTransition};
+pub use model_request::{ModelRequest, ModelRequestTransition, INTERRUPTED_MARKER};
+pub use start::{Start, StartTransition};
+pub use user_request::{UserRequest, UserRequestTransition};
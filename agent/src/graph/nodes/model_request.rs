@@ -1,9 +1,34 @@
-use crate::graph::models::{Deps, GraphError, NodeRunner, NodeTransition, State};
+use crate::graph::history;
+use crate::graph::models::{
+    AgentEvent, Deps, GraphError, GraphEvent, NodeRunner, NodeTransition, State, Transitions,
+    TurnUsage, CONTEXT_WARNING_THRESHOLD,
+};
+use crate::graph::nodes::pending_approvals;
+use crate::guardrails::{self, Output, ViolationAction};
 use anyhow::Context;
 use futures_util::StreamExt;
-use providers::models::StreamEvent;
-use providers::Response;
-use providers::{models::StreamProcessor, BaseProvider, StopReason};
+use providers::models::{ContentBlock, ContentBlockStartData, ContentDelta, StreamEvent};
+use providers::{models::StreamProcessor, BaseProvider, ProviderError, StopReason};
+use providers::{Message, Response, Role};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+/// How many times a request is retried after a transient provider failure
+const MAX_RETRIES: u32 = 3;
+
+/// Delay before the first retry, doubled for each attempt after that
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Ends what the model had said when its response was cut short, so it knows on the next turn
+pub const INTERRUPTED_MARKER: &str = "[interrupted]";
+
+/// How streaming a response ended
+enum Streamed {
+    /// The response, with how long the provider took to send its first event
+    Finished(Response, Duration),
+    /// The run was cancelled part way, with the events received until then
+    Cancelled(Vec<StreamEvent>),
+}
 
 /// The model request node
 ///
@@ -12,43 +37,312 @@ use providers::{models::StreamProcessor, BaseProvider, StopReason};
 #[derive(Debug)]
 pub struct ModelRequest;
 
+/// Where [`ModelRequest`] can lead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelRequestTransition {
+    /// Run the tools the model called
+    ToCallTools,
+    /// Wait for the caller to approve tool calls that change something
+    ToAwaitApproval,
+    /// Ask the model again, e.g. to revise a response a validator rejected
+    ToModelRequest,
+    /// The model gave its final response
+    ToEnd,
+}
+
+impl Transitions for ModelRequestTransition {
+    const ALL: &'static [Self] = &[
+        ModelRequestTransition::ToCallTools,
+        ModelRequestTransition::ToAwaitApproval,
+        ModelRequestTransition::ToModelRequest,
+        ModelRequestTransition::ToEnd,
+    ];
+}
+
+impl From<ModelRequestTransition> for NodeTransition {
+    fn from(transition: ModelRequestTransition) -> Self {
+        match transition {
+            ModelRequestTransition::ToCallTools => NodeTransition::ToCallTools,
+            ModelRequestTransition::ToAwaitApproval => NodeTransition::ToAwaitApproval,
+            ModelRequestTransition::ToModelRequest => NodeTransition::ToModelRequest,
+            ModelRequestTransition::ToEnd => NodeTransition::ToEnd,
+        }
+    }
+}
+
 impl<P: BaseProvider> NodeRunner<P> for ModelRequest {
+    type Transition = ModelRequestTransition;
+
     async fn run(
         &self,
         state: &mut State,
         deps: &Deps<P>,
-    ) -> std::result::Result<NodeTransition, GraphError> {
-        let message_history = state.message_history.clone();
-        
-        let stream = deps
-            .provider
-            .stream(&message_history, deps.tools.clone(), Some(deps.max_tokens), deps.temperature)
-            .await
-            .context("Failed to create stream from provider")?;
-
-        let mut events = Vec::new();
-        let mut stream = deps.stream_wrapper.wrap(Box::pin(stream));
+    ) -> std::result::Result<Self::Transition, GraphError> {
+        // A model that keeps calling tools without getting anywhere would otherwise run forever
+        if state.model_requests >= deps.max_iterations {
+            return Err(GraphError::MaxIterations(deps.max_iterations));
+        }
+        // Stop before spending any more once the run has used up its budget
+        if let Some(max_cost_usd) = deps.max_cost_usd {
+            if state.cost_usd >= max_cost_usd {
+                return Err(GraphError::BudgetExceeded(format!(
+                    "the run has cost ${:.2} of its ${:.2} budget",
+                    state.cost_usd, max_cost_usd
+                )));
+            }
+        }
+        if let Some(max_total_tokens) = deps.max_total_tokens {
+            if state.total_tokens() >= max_total_tokens {
+                return Err(GraphError::BudgetExceeded(format!(
+                    "the run has used {} of its {} tokens",
+                    state.total_tokens(),
+                    max_total_tokens
+                )));
+            }
+        }
+        state.model_requests += 1;
 
-        while let Some(event_result) = stream.next().await {
-            let event = event_result.context("Error in event stream")?;
-            events.push(event);
+        // Warn once a run's conversation nears the context window, before it gets cut off
+        state.count_new_messages();
+        let max_tokens = deps.provider.capabilities().max_context_tokens as usize;
+        let warned = state
+            .events
+            .iter()
+            .any(|event| matches!(event, GraphEvent::ContextWindowNearlyFull { .. }));
+        if !warned && state.context_tokens as f64 >= max_tokens as f64 * CONTEXT_WARNING_THRESHOLD {
+            state.events.push(GraphEvent::ContextWindowNearlyFull {
+                tokens: state.context_tokens,
+                max_tokens,
+            });
         }
 
-        let response: Response =
-            <StreamEvent as StreamProcessor<StreamEvent>>::process_events(events)
-                .context("Failed to process stream events")?;
+        let message_history = history::prune(state, deps).await?;
+
+        let span = tracing::debug_span!(
+            target: "aria::agent",
+            "model_request",
+            request = state.model_requests,
+            messages = message_history.len(),
+            context_tokens = state.context_tokens,
+        );
+        let started = Instant::now();
+
+        // Retry transient failures, anything else is returned to the caller as is
+        let mut attempt = 0;
+        let (response, latency, streaming) = loop {
+            let attempt_started = Instant::now();
+            match request(&message_history, deps)
+                .instrument(span.clone())
+                .await
+            {
+                Ok(Streamed::Finished(response, latency)) => {
+                    break (
+                        response,
+                        latency,
+                        attempt_started.elapsed().saturating_sub(latency),
+                    )
+                }
+                Ok(Streamed::Cancelled(events)) => {
+                    // Keep what the model had said, leaving out any tool calls it hadn't
+                    // finished making as they would never be answered. The marker tells it
+                    // the response was cut short, so a follow-up in the same conversation can
+                    // pick up from there
+                    let mut content: Vec<ContentBlock> =
+                        <StreamEvent as StreamProcessor<StreamEvent>>::process_events(events)
+                            .and_then(Message::try_from)
+                            .map(|message| message.content)
+                            .unwrap_or_default();
+                    content.retain(|block| {
+                        matches!(
+                            block,
+                            ContentBlock::Text { .. } | ContentBlock::CitedText { .. }
+                        )
+                    });
+                    content.push(ContentBlock::Text {
+                        text: INTERRUPTED_MARKER.to_string(),
+                    });
+                    state.message_history.push(Message {
+                        role: Role::Assistant,
+                        content,
+                    });
+                    return Err(GraphError::Cancelled);
+                }
+                Err(err) if err.is_retryable() && attempt < MAX_RETRIES => {
+                    let delay = err
+                        .retry_after()
+                        .unwrap_or(RETRY_BACKOFF * 2u32.pow(attempt));
+                    attempt += 1;
+                    deps.emit(AgentEvent::ResponseRestarted { attempt });
+                    tracing::debug!(
+                        target: "aria::agent",
+                        parent: &span,
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %err,
+                        "Retrying the model request"
+                    );
+                    tokio::select! {
+                        _ = deps.cancellation.cancelled() => return Err(GraphError::Cancelled),
+                        _ = tokio::time::sleep(delay) => {}
+                    }
+                }
+                Err(err) => return Err(err.into()),
+            }
+        };
+
+        let usage = response.usage.clone().unwrap_or_default();
+        tracing::debug!(
+            target: "aria::agent",
+            parent: &span,
+            latency_ms = started.elapsed().as_millis() as u64,
+            first_event_ms = latency.as_millis() as u64,
+            input_tokens = usage.input_tokens,
+            output_tokens = usage.output_tokens,
+            cache_read_input_tokens = usage.cache_read_input_tokens,
+            stop_reason = ?response.stop_reason,
+            "Model responded"
+        );
 
         let message = response
             .clone()
             .try_into()
             .context("Failed to convert response to message")?;
 
+        deps.hooks.on_message(&message).await;
         state.message_history.push(message);
+        if let Some(usage) = &response.usage {
+            state.count_usage(usage);
+            state.usage.accumulate(usage);
+            if let Some(pricing) = deps.provider.pricing() {
+                state.cost_usd += pricing.cost(usage);
+            }
+            deps.emit(AgentEvent::Usage(usage.clone()));
+        }
+        state.turn_usage.push(TurnUsage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cache_tokens: usage.cache_creation_input_tokens + usage.cache_read_input_tokens,
+            duration: started.elapsed(),
+            latency,
+            streaming,
+        });
+        state.stop_reason = response.stop_reason.clone();
 
         match response.stop_reason {
             Some(StopReason::MaxTokens) => Err(GraphError::MaxTokens),
-            Some(StopReason::ToolUse) => Ok(NodeTransition::ToCallTools),
-            _ => Ok(NodeTransition::ToEnd),
+            // Pause for the caller before running anything that changes files or runs commands
+            Some(StopReason::ToolUse)
+                if deps.await_approval && !pending_approvals(state, deps).is_empty() =>
+            {
+                Ok(ModelRequestTransition::ToAwaitApproval)
+            }
+            Some(StopReason::ToolUse) => Ok(ModelRequestTransition::ToCallTools),
+            // A long server-side tool call such as a web search paused the turn, sending it back
+            // as it is lets the model carry on where it stopped
+            Some(StopReason::PauseTurn) => Ok(ModelRequestTransition::ToModelRequest),
+            _ => {
+                // The final response is checked before it's accepted, a rejected one is sent
+                // back for the model to revise
+                let text = state
+                    .message_history
+                    .last()
+                    .and_then(Message::text)
+                    .unwrap_or_default();
+                let Err(reason) = guardrails::check(&deps.validators, &Output::Response(&text))
+                else {
+                    return Ok(ModelRequestTransition::ToEnd);
+                };
+                if deps.on_violation == ViolationAction::Block {
+                    return Err(GraphError::GuardrailViolation(reason));
+                }
+                state
+                    .events
+                    .push(GraphEvent::GuardrailViolation(reason.clone()));
+                let message = Message {
+                    role: Role::User,
+                    content: vec![ContentBlock::Text {
+                        text: format!(
+                            "Your response was rejected before reaching the user. {}. Revise \
+                            it and respond again.",
+                            reason.trim_end_matches('.')
+                        ),
+                    }],
+                };
+                deps.hooks.on_message(&message).await;
+                state.message_history.push(message);
+                Ok(ModelRequestTransition::ToModelRequest)
+            }
+        }
+    }
+}
+
+/// Stream a single response from the provider, stopping early if the run is cancelled
+async fn request<P: BaseProvider>(
+    message_history: &[Message],
+    deps: &Deps<P>,
+) -> Result<Streamed, ProviderError> {
+    // Don't offer tools to a provider that can't use them
+    let (tools, tool_choice) = if deps.provider.capabilities().supports_tools {
+        (deps.tools.clone(), deps.tool_choice.clone())
+    } else {
+        (None, None)
+    };
+
+    let started = Instant::now();
+    let stream = tokio::select! {
+        biased;
+        _ = deps.cancellation.cancelled() => return Ok(Streamed::Cancelled(Vec::new())),
+        stream = deps.provider.stream(
+            message_history,
+            &deps.system_prompt,
+            tools,
+            tool_choice,
+            deps.max_tokens,
+            deps.temperature,
+        ) => stream?,
+    };
+
+    let mut events = Vec::new();
+    let mut stream = deps.stream_wrapper.wrap(Box::pin(stream));
+    let mut latency = None;
+
+    loop {
+        // Dropping the stream when cancelled closes the connection to the provider
+        let event_result = tokio::select! {
+            biased;
+            _ = deps.cancellation.cancelled() => return Ok(Streamed::Cancelled(events)),
+            event_result = stream.next() => event_result,
+        };
+        let event = match event_result {
+            Some(event_result) => event_result?,
+            None => break,
+        };
+        latency.get_or_insert_with(|| started.elapsed());
+        if let StreamEvent::ContentBlockStart {
+            content_block: ContentBlockStartData::Text { text },
+            ..
         }
+        | StreamEvent::ContentBlockDelta {
+            delta: ContentDelta::TextDelta { text },
+            ..
+        } = &event
+        {
+            if !text.is_empty() {
+                deps.emit(AgentEvent::TextDelta(text.clone()));
+            }
+        }
+        if let StreamEvent::Reconnecting { attempt } = &event {
+            deps.emit(AgentEvent::ResponseRestarted { attempt: *attempt });
+        }
+        events.push(event);
+    }
+
+    if let Some(Ok(mut trace)) = deps.recorder.as_ref().map(|recorder| recorder.lock()) {
+        trace.responses.push(events.clone());
     }
+    let response = <StreamEvent as StreamProcessor<StreamEvent>>::process_events(events)?;
+    Ok(Streamed::Finished(
+        response,
+        latency.unwrap_or_else(|| started.elapsed()),
+    ))
 }
@@ -1,4 +1,4 @@
-use crate::graph::models::{Deps, GraphError, NodeRunner, NodeTransition, State};
+use crate::graph::models::{Deps, GraphError, NodeRunner, NodeTransition, State, Transitions};
 use providers::{models::ContentBlock, BaseProvider, Role};
 
 /// The starting node
@@ -6,18 +6,50 @@ use providers::{models::ContentBlock, BaseProvider, Role};
 pub struct Start;
 
 impl<P: BaseProvider> NodeRunner<P> for Start {
+    type Transition = StartTransition;
+
     async fn run(
         &self,
         state: &mut State,
-        _deps: &Deps<P>,
-    ) -> std::result::Result<NodeTransition, GraphError> {
-        // Setup initial state with user input
+        deps: &Deps<P>,
+    ) -> std::result::Result<Self::Transition, GraphError> {
+        // Setup initial state with user input, the examples and context only lead a new
+        // conversation
+        let new_conversation = state.message_history.is_empty();
+        if new_conversation {
+            state.message_history.extend(deps.examples.iter().cloned());
+        }
+        let mut content = Vec::new();
+        if let (Some(context), true) = (&deps.context, new_conversation) {
+            content.push(ContentBlock::Text {
+                text: context.clone(),
+            });
+        }
+        content.push(ContentBlock::Text {
+            text: state.current_user_prompt.clone(),
+        });
         state.message_history.push(providers::Message {
             role: Role::User,
-            content: vec![ContentBlock::Text {
-                text: state.current_user_prompt.clone(),
-            }],
+            content,
         });
-        Ok(NodeTransition::ToUserRequest)
+        Ok(StartTransition::ToUserRequest)
+    }
+}
+
+/// Where [`Start`] can lead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartTransition {
+    ToUserRequest,
+}
+
+impl Transitions for StartTransition {
+    const ALL: &'static [Self] = &[StartTransition::ToUserRequest];
+}
+
+impl From<StartTransition> for NodeTransition {
+    fn from(transition: StartTransition) -> Self {
+        match transition {
+            StartTransition::ToUserRequest => NodeTransition::ToUserRequest,
+        }
     }
 }
@@ -1,4 +1,4 @@
-use crate::graph::models::{Deps, GraphError, NodeRunner, NodeTransition, State};
+use crate::graph::models::{Deps, GraphError, NodeRunner, NodeTransition, State, Transitions};
 use providers::{models::ContentBlock, BaseProvider, Message, Role};
 
 /// The user request node
@@ -6,20 +6,42 @@ use providers::{models::ContentBlock, BaseProvider, Message, Role};
 pub struct UserRequest;
 
 impl<P: BaseProvider> NodeRunner<P> for UserRequest {
+    type Transition = UserRequestTransition;
+
     async fn run(
         &self,
         state: &mut State,
-        _deps: &Deps<P>,
-    ) -> std::result::Result<NodeTransition, GraphError> {
+        deps: &Deps<P>,
+    ) -> std::result::Result<Self::Transition, GraphError> {
         // Add the user's message to the message history
-        state.message_history.push(Message {
+        let message = Message {
             role: Role::User,
             content: vec![ContentBlock::Text {
                 text: state.current_user_prompt.clone(),
             }],
-        });
+        };
+        deps.hooks.on_message(&message).await;
+        state.message_history.push(message);
 
         // Transition to the model request node
-        Ok(NodeTransition::ToModelRequest)
+        Ok(UserRequestTransition::ToModelRequest)
+    }
+}
+
+/// Where [`UserRequest`] can lead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserRequestTransition {
+    ToModelRequest,
+}
+
+impl Transitions for UserRequestTransition {
+    const ALL: &'static [Self] = &[UserRequestTransition::ToModelRequest];
+}
+
+impl From<UserRequestTransition> for NodeTransition {
+    fn from(transition: UserRequestTransition) -> Self {
+        match transition {
+            UserRequestTransition::ToModelRequest => NodeTransition::ToModelRequest,
+        }
     }
 }
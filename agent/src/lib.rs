@@ -1,47 +1,396 @@
-use providers::BaseProvider;
+use providers::{BaseProvider, Message, ToolChoice};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
 
+mod builder;
 pub mod graph;
-pub use graph::models::StreamWrapper;
-pub use graph::{CurrentNode, Deps, GraphError, GraphIter, NodeRunner, NodeTransition, State};
-use tools::{ListFilesTool, ReadFileTool, RunCommandTool, ToolType, TreeTool, WriteFileTool};
+mod guardrails;
+mod prompt_template;
+mod replay;
+mod repo_context;
+mod session;
+mod session_store;
+mod task;
+pub use builder::AgentBuilder;
+pub use graph::models::{
+    AlwaysApprove, ApprovalPolicy, Hooks, NeverApprove, NoHooks, StreamWrapper, ToolDecision,
+    DEFAULT_DENIAL_REASON,
+};
+use graph::models::{NoopStreamWrapper, DEFAULT_MAX_ITERATIONS, DEFAULT_MAX_TOOL_OUTPUT_BYTES};
+pub use graph::{
+    AgentEvent, CurrentNode, CustomNode, Deps, GraphError, GraphEvent, GraphIter, HistoryPolicy,
+    HistorySummary, NodeMetrics, NodeRunner, NodeTransition, NodeVisit, PendingApproval,
+    RunMetrics, RunResult, Snapshot, State, ToolInvocation, ToolSet, Transitions, TurnUsage,
+    INTERRUPTED_MARKER,
+};
+pub use guardrails::{
+    ForbiddenPaths, Output, RegexValidator, SecretDetector, Validator, ViolationAction,
+};
+pub use prompt_template::PROMPT_VARIABLES;
+pub use replay::{ReplayProvider, Trace, TracedToolCall};
+pub use repo_context::DEFAULT_REPO_CONTEXT_BYTES;
+pub use session::Session;
+pub use session_store::{SessionInfo, SessionStore};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+pub use task::{TaskInput, TaskTool, DEFAULT_TASK_MAX_ITERATIONS, DEFAULT_TASK_TOKEN_BUDGET};
+pub use tokio_util::sync::CancellationToken;
+use tools::{
+    Backups, DeleteFileTool, DiagnosticsTool, DockerListTool, DockerLogsTool, EditFileTool,
+    EditNotebookTool, EstimateTokensTool, FindReferencesTool, GitBranchTool, GitCommitTool,
+    GitDiffTool, GitLogTool, GitStatusTool, GotoDefinitionTool, LanguageServers, ListFilesTool,
+    MemoryTool, MoveFileTool, ReadFileTool, ReadFilesTool, ReadNotebookTool, ReadToolOutputTool,
+    RespondTool, RunCommandTool, RunTestsTool, SearchTool, Tool, ToolRegistry, TreeTool,
+    ViewImageTool, Workspace, WriteFileTool,
+};
 
+/// Runs of the model and tools with shared settings, see [`Agent::builder`]
+///
+/// It's `Send` and `Sync`, so one agent in an `Arc` can drive many sessions at once, e.g. one
+/// per connection of a server, each run on a task of its own.
 pub struct Agent<P: BaseProvider> {
-    provider: P,
+    /// Shared by every run, see [`Deps::provider`]
+    provider: Arc<P>,
+    run_command: RunCommandTool,
+    workspace: Workspace,
+    /// Started on demand and shared by the code navigation tools
+    language_servers: LanguageServers,
+    /// Offered alongside the built-in tools, replacing any with the same name
+    tools: ToolRegistry,
+    /// Added to the graph of every run, see [`Deps::add_node`]
+    nodes: HashMap<String, Arc<dyn CustomNode>>,
+    /// Transitions redirected in every run, see [`Deps::route`]
+    routes: Vec<(CurrentNode, NodeTransition, NodeTransition)>,
+    system_prompt: String,
+    max_tokens: Option<u32>,
+    temperature: Option<f64>,
+    stream_wrapper: Arc<dyn StreamWrapper>,
+    /// See [`Deps::approval`]
+    approval: Arc<dyn ApprovalPolicy>,
+    /// See [`Deps::await_approval`]
+    await_approval: bool,
+    hooks: Arc<dyn Hooks>,
+    /// See [`Deps::max_iterations`]
+    max_iterations: usize,
+    /// See [`Deps::max_run_duration`]
+    max_run_duration: Option<Duration>,
+    /// See [`Deps::max_cost_usd`]
+    max_cost_usd: Option<f64>,
+    /// See [`Deps::max_total_tokens`]
+    max_total_tokens: Option<u64>,
+    /// See [`Deps::max_tool_output_bytes`]
+    max_tool_output_bytes: usize,
+    /// The tools each run is offered, narrowed further with [`GraphIter::with_tool_set`]
+    tool_set: ToolSet,
+    /// Give the model an overview of the workspace of up to this many bytes with the first
+    /// prompt of a conversation, disabled when unset
+    repo_context: Option<usize>,
+    /// See [`Deps::examples`]
+    examples: Vec<Message>,
+    /// See [`Deps::history_policy`]
+    history_policy: HistoryPolicy,
+    /// See [`Deps::validators`]
+    validators: Vec<Arc<dyn Validator>>,
+    /// See [`Deps::on_violation`]
+    on_violation: ViolationAction,
 }
 
 impl<P: BaseProvider> Agent<P> {
     pub fn new(provider: P) -> Self {
-        Agent { provider }
+        Agent {
+            provider: Arc::new(provider),
+            run_command: RunCommandTool::default(),
+            workspace: Workspace::default(),
+            language_servers: LanguageServers::default(),
+            tools: ToolRegistry::default(),
+            nodes: HashMap::new(),
+            routes: Vec::new(),
+            system_prompt: String::new(),
+            max_tokens: None,
+            temperature: None,
+            stream_wrapper: Arc::new(NoopStreamWrapper),
+            approval: Arc::new(AlwaysApprove),
+            await_approval: false,
+            hooks: Arc::new(NoHooks),
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            max_run_duration: None,
+            max_cost_usd: None,
+            max_total_tokens: None,
+            max_tool_output_bytes: DEFAULT_MAX_TOOL_OUTPUT_BYTES,
+            tool_set: ToolSet::All,
+            repo_context: None,
+            examples: Vec::new(),
+            history_policy: HistoryPolicy::KeepAll,
+            validators: Vec::new(),
+            on_violation: ViolationAction::Reprompt,
+        }
     }
 
-    pub fn iter(
-        &self,
-        user_prompt: &str,
-        system_prompt: &str,
-        max_tokens: u32,
-        temperature: Option<f64>,
-        stream_wrapper: Option<Box<dyn StreamWrapper>>,
-    ) -> GraphIter<P>
+    /// Configure an agent step by step, e.g. its system prompt, hooks and limits, which then
+    /// apply to every run
+    pub fn builder(provider: P) -> AgentBuilder<P> {
+        AgentBuilder::new(provider)
+    }
+
+    /// Confine the tools to a directory other than the current one
+    pub fn with_workspace(mut self, workspace: Workspace) -> Self {
+        self.workspace = workspace;
+        self
+    }
+
+    /// Offer a tool alongside the built-in ones, replacing any built-in with the same name
+    pub fn with_tool<T: Tool + 'static>(mut self, tool: T) -> Self {
+        self.tools.register(tool);
+        self
+    }
+
+    /// Offer every tool in a registry alongside the built-in ones, e.g. those of an MCP server
+    pub fn with_tools(mut self, tools: &ToolRegistry) -> Self {
+        self.tools.extend(tools);
+        self
+    }
+
+    /// Add a node to the graph of every run, reached through a route to it
+    pub fn with_node<N: CustomNode + 'static>(mut self, name: &str, node: N) -> Self {
+        self.nodes.insert(name.to_string(), Arc::new(node));
+        self
+    }
+
+    /// Take `to` wherever the `from` node returns `transition`, e.g. to go through a node added
+    /// with [`Agent::with_node`]
+    pub fn with_route(
+        mut self,
+        from: CurrentNode,
+        transition: NodeTransition,
+        to: NodeTransition,
+    ) -> Self {
+        self.routes.push((from, transition, to));
+        self
+    }
+
+    /// Offer a task tool, which hands self-contained work such as broad searches to a subagent
+    ///
+    /// The subagent is offered the read-only tools, including those added before this but not
+    /// those added after.
+    pub fn with_task_tool(self) -> Self
     where
-        P: Clone,
+        P: Send + Sync + 'static,
     {
-        let tools: Vec<ToolType> = vec![
-            ToolType::ListFiles(ListFilesTool),
-            ToolType::ReadFile(ReadFileTool),
-            ToolType::RunCommand(RunCommandTool),
-            ToolType::Tree(TreeTool),
-            ToolType::WriteFile(WriteFileTool),
-        ];
-
-        let deps = Deps::new(
+        let tools = self.deps().tools.unwrap_or_default();
+        let task = TaskTool::new(self.provider.clone(), &tools);
+        self.with_tool(task)
+    }
+
+    /// Use a configured run_command tool, e.g. with a different default timeout
+    pub fn with_run_command(mut self, run_command: RunCommandTool) -> Self {
+        self.run_command = run_command;
+        self
+    }
+
+    /// Start a run with the agent's settings, see [`Agent::builder`]
+    pub fn iter(&self, user_prompt: &str) -> GraphIter<P> {
+        GraphIter::new(self.deps(), user_prompt.to_string())
+    }
+
+    /// Carry on with a run from a snapshot, e.g. one saved in the [`SessionStore`]
+    pub fn resume(&self, snapshot: Snapshot) -> GraphIter<P> {
+        GraphIter::resume(self.deps(), snapshot)
+    }
+
+    /// A copy of a run at its current point with the agent's settings, see [`GraphIter::fork`]
+    pub fn fork(&self, graph_iter: &GraphIter<P>) -> GraphIter<P> {
+        graph_iter.fork(self.deps())
+    }
+
+    /// The runs saved in the workspace, see [`SessionStore`]
+    pub fn sessions(&self) -> SessionStore {
+        SessionStore::new(self.workspace.clone())
+    }
+
+    /// Start a conversation whose turns each see the ones before, e.g. for follow-up questions
+    pub fn session(&self) -> Session<'_, P> {
+        Session::new(self)
+    }
+
+    /// The dependencies of a run, with the built-in tools and any added alongside them
+    pub(crate) fn deps(&self) -> Deps<P> {
+        // Each run keeps its own backups, so it can be undone on its own
+        let backups = Backups::new(self.workspace.clone());
+
+        let mut tools = ToolRegistry::default();
+        tools
+            .register(DeleteFileTool {
+                workspace: self.workspace.clone(),
+                backups: backups.clone(),
+            })
+            .register(DiagnosticsTool {
+                workspace: self.workspace.clone(),
+                language_servers: self.language_servers.clone(),
+            })
+            .register(DockerListTool)
+            .register(DockerLogsTool)
+            .register(EditNotebookTool {
+                workspace: self.workspace.clone(),
+                backups: backups.clone(),
+            })
+            .register(EditFileTool {
+                workspace: self.workspace.clone(),
+                backups: backups.clone(),
+            })
+            .register(EstimateTokensTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(FindReferencesTool {
+                workspace: self.workspace.clone(),
+                language_servers: self.language_servers.clone(),
+            })
+            .register(GitBranchTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(GitCommitTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(GitDiffTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(GitLogTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(GitStatusTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(GotoDefinitionTool {
+                workspace: self.workspace.clone(),
+                language_servers: self.language_servers.clone(),
+            })
+            .register(ListFilesTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(MemoryTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(MoveFileTool {
+                workspace: self.workspace.clone(),
+                backups: backups.clone(),
+            })
+            .register(ReadFileTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(ReadFilesTool {
+                workspace: self.workspace.clone(),
+                ..ReadFilesTool::default()
+            })
+            .register(ReadNotebookTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(ReadToolOutputTool)
+            .register(RunCommandTool {
+                workspace: self.workspace.clone(),
+                ..self.run_command.clone()
+            })
+            .register(RunTestsTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(SearchTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(TreeTool {
+                workspace: self.workspace.clone(),
+            })
+            .register(WriteFileTool {
+                workspace: self.workspace.clone(),
+                backups: backups.clone(),
+            });
+        // Images are only any use to a model that can see them
+        if self.provider.capabilities().supports_vision {
+            tools.register(ViewImageTool {
+                workspace: self.workspace.clone(),
+            });
+        }
+        tools.extend(&self.tools);
+        self.tool_set.apply(&mut tools);
+
+        let mut deps = Deps::with_shared_provider(
             self.provider.clone(),
             Some(tools),
-            system_prompt.to_string(),
-            max_tokens,
-            temperature,
-            stream_wrapper,
+            prompt_template::expand(&self.system_prompt, &self.workspace),
+            self.max_tokens,
+            self.temperature,
+            Some(Box::new(self.stream_wrapper.clone())),
         );
+        deps.backups = backups;
+        deps.workspace = self.workspace.clone();
+        deps.approval = Box::new(self.approval.clone());
+        deps.await_approval = self.await_approval;
+        deps.hooks = Box::new(self.hooks.clone());
+        deps.context = self
+            .repo_context
+            .and_then(|max_bytes| repo_context::gather(&self.workspace, max_bytes));
+        deps.examples = self.examples.clone();
+        deps.history_policy = self.history_policy.clone();
+        deps.validators = self.validators.clone();
+        deps.on_violation = self.on_violation;
+        deps.max_iterations = self.max_iterations;
+        deps.max_run_duration = self.max_run_duration;
+        deps.max_cost_usd = self.max_cost_usd;
+        deps.max_total_tokens = self.max_total_tokens;
+        deps.max_tool_output_bytes = self.max_tool_output_bytes;
+        for (name, node) in &self.nodes {
+            deps.add_node(name, node.clone());
+        }
+        for (from, transition, to) in &self.routes {
+            deps.route(from.clone(), transition.clone(), to.clone());
+        }
+        deps
+    }
+
+    /// Put back every file the latest run in the workspace changed, returning their paths
+    ///
+    /// Runs are kept on disk, so this works after the process that made the run has exited.
+    /// Calling it again undoes the run before.
+    pub fn undo(&self) -> Result<Vec<PathBuf>, GraphError> {
+        Backups::restore_latest(&self.workspace).map_err(GraphError::Storage)
+    }
+
+    /// Create a graph iterator whose final answer is structured data matching `T`
+    ///
+    /// The model is offered a respond tool with `T`'s JSON schema as its input and is
+    /// required to use a tool on every turn, so the run can only finish by calling it.
+    /// Once the iterator is exhausted, read the answer with
+    /// [`GraphIter::get_structured_result`].
+    pub fn respond_with_schema<T: JsonSchema + DeserializeOwned>(
+        &self,
+        user_prompt: &str,
+    ) -> Result<GraphIter<P>, GraphError> {
+        let respond_tool =
+            RespondTool::for_type::<T>().map_err(|e| GraphError::Other(anyhow::Error::new(e)))?;
+
+        let mut graph_iter = self.iter(user_prompt);
+        graph_iter
+            .deps_mut()
+            .tools
+            .get_or_insert_with(ToolRegistry::default)
+            .register(respond_tool);
+        graph_iter.deps_mut().tool_choice = Some(ToolChoice::Any);
 
-        GraphIter::new(deps, user_prompt.to_string())
+        Ok(graph_iter)
     }
 }
+
+// One agent serves many sessions at once, so it and everything a run holds must stay thread-safe,
+// including the future of each step for any provider that is
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+    fn assert_send<T: Send>(_: &T) {}
+
+    #[allow(dead_code)]
+    fn thread_safe<P: BaseProvider + Send + Sync + 'static>(graph_iter: &mut GraphIter<P>) {
+        assert_send_sync::<Agent<P>>();
+        assert_send_sync::<GraphIter<P>>();
+        assert_send_sync::<Deps<P>>();
+        assert_send_sync::<State>();
+        assert_send(&graph_iter.next());
+    }
+};
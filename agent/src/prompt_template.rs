@@ -0,0 +1,75 @@
+use crate::repo_context;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tools::Workspace;
+
+/// The placeholders expanded in the system prompt, each replaced when a run starts
+pub const PROMPT_VARIABLES: [&str; 5] = ["cwd", "os", "date", "git_branch", "tree_summary"];
+
+/// The system prompt with each `{{variable}}` of [`PROMPT_VARIABLES`] replaced by its value in
+/// the workspace
+///
+/// Only the variables the prompt uses are worked out. Any that can't be, e.g. the branch
+/// outside a git repository, become `unknown`, and other placeholders are left as they are.
+pub(crate) fn expand(template: &str, workspace: &Workspace) -> String {
+    let mut prompt = template.to_string();
+    for name in PROMPT_VARIABLES {
+        let placeholder = format!("{{{{{}}}}}", name);
+        if !prompt.contains(&placeholder) {
+            continue;
+        }
+        let value = match name {
+            "cwd" => workspace.root().ok().map(|root| root.display().to_string()),
+            "os" => Some(std::env::consts::OS.to_string()),
+            "date" => Some(today()),
+            "git_branch" => git_branch(workspace),
+            "tree_summary" => workspace
+                .root()
+                .ok()
+                .and_then(|root| repo_context::tree(&root)),
+            _ => None,
+        };
+        prompt = prompt.replace(&placeholder, value.as_deref().unwrap_or("unknown"));
+    }
+    prompt
+}
+
+/// Today's date in UTC, as YYYY-MM-DD
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / 86_400)
+        .unwrap_or_default() as i64;
+
+    // Count the days in 400 year eras from 0000-03-01, so leap days fall at the end of a year
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The branch checked out in the workspace, `None` outside a git repository
+fn git_branch(workspace: &Workspace) -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--abbrev-ref", "HEAD"])
+        .current_dir(workspace.root().ok()?)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!branch.is_empty()).then_some(branch)
+}
@@ -0,0 +1,228 @@
+use crate::graph::{Deps, GraphError, GraphIter, NodeVisit};
+use async_trait::async_trait;
+use providers::models::StreamEvent;
+use providers::{BaseProvider, Message, ProviderCapabilities, ProviderError, ToolChoice};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tools::models::{Tool, ToolContent, ToolError, ToolName, ToolResult};
+use tools::ToolRegistry;
+
+/// A recording of a run: the model's responses and the tools' results, and the nodes it went
+/// through, see [`GraphIter::record_trace`]
+///
+/// Replaying it runs the graph again with the recording standing in for the model and the
+/// tools, without calling the API or touching files, and checks it takes the same path. It's
+/// plain data, so it can be saved with the code whose behaviour it pins down.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Trace {
+    pub system_prompt: String,
+    pub user_prompt: String,
+    /// The conversation before the run
+    pub message_history: Vec<Message>,
+    /// The stream events of each model response, in the order they were requested
+    pub responses: Vec<Vec<StreamEvent>>,
+    /// What each tool call returned, in the order the results were sent to the model
+    pub tool_calls: Vec<TracedToolCall>,
+    /// The nodes the run went through
+    pub path: Vec<NodeVisit>,
+}
+
+/// A tool call of a recorded run and what it returned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TracedToolCall {
+    pub tool_name: ToolName,
+    pub input: Value,
+    pub is_mutating: bool,
+    pub is_error: bool,
+    /// The output as the model saw it, images and diffs as their text
+    pub output: String,
+}
+
+impl Trace {
+    /// Read a trace saved with [`Trace::save`]
+    pub fn load(path: &Path) -> Result<Self, GraphError> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            GraphError::Storage(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        serde_json::from_str(&contents)
+            .map_err(|e| GraphError::Storage(format!("Failed to parse {}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), GraphError> {
+        let contents =
+            serde_json::to_string_pretty(self).map_err(|e| GraphError::Storage(e.to_string()))?;
+        fs::write(path, contents)
+            .map_err(|e| GraphError::Storage(format!("Failed to write {}: {}", path.display(), e)))
+    }
+
+    /// Dependencies that answer from the recording, to adjust before [`Trace::replay`] if the
+    /// recorded run had other settings than the defaults, e.g. `max_iterations`
+    pub fn deps(&self) -> Deps<ReplayProvider> {
+        let mut tools = ToolRegistry::default();
+        let calls = Arc::new(Mutex::new(VecDeque::from(self.tool_calls.clone())));
+        let mut names: Vec<(ToolName, bool)> = Vec::new();
+        for call in &self.tool_calls {
+            if !names.iter().any(|(name, _)| *name == call.tool_name) {
+                names.push((call.tool_name.clone(), call.is_mutating));
+            }
+        }
+        for (name, is_mutating) in names {
+            tools.register(ReplayTool {
+                name,
+                is_mutating,
+                calls: calls.clone(),
+            });
+        }
+
+        Deps::new(
+            ReplayProvider {
+                responses: Arc::new(Mutex::new(VecDeque::from(self.responses.clone()))),
+            },
+            Some(tools),
+            self.system_prompt.clone(),
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// Run the graph again with `deps`, usually from [`Trace::deps`], returning the finished
+    /// iterator if it went through the same nodes as the recording, and
+    /// [`GraphError::ReplayDiverged`] at the first that differs otherwise
+    pub async fn replay<P: BaseProvider>(&self, deps: Deps<P>) -> Result<GraphIter<P>, GraphError> {
+        let mut graph_iter =
+            GraphIter::with_history(deps, self.user_prompt.clone(), self.message_history.clone());
+        // A failed node is part of the path, so errors are compared rather than returned
+        while graph_iter.next().await.is_some() {}
+
+        let replayed = &graph_iter.state().path;
+        let step = |visit: Option<&NodeVisit>| match visit {
+            Some(visit) if visit.failed => format!("{:?}, which failed", visit.node),
+            Some(visit) => format!("{:?}", visit.node),
+            None => "the end of the run".to_string(),
+        };
+        for index in 0..self.path.len().max(replayed.len()) {
+            let (expected, actual) = (self.path.get(index), replayed.get(index));
+            let same = match (expected, actual) {
+                (Some(expected), Some(actual)) => {
+                    expected.node == actual.node && expected.failed == actual.failed
+                }
+                _ => false,
+            };
+            if !same {
+                return Err(GraphError::ReplayDiverged(format!(
+                    "step {} went to {} rather than {}",
+                    index + 1,
+                    step(actual),
+                    step(expected)
+                )));
+            }
+        }
+        Ok(graph_iter)
+    }
+}
+
+/// A provider that answers each request with the next recorded response, see [`Trace::deps`]
+#[derive(Debug, Clone, Default)]
+pub struct ReplayProvider {
+    responses: Arc<Mutex<VecDeque<Vec<StreamEvent>>>>,
+}
+
+impl BaseProvider for ReplayProvider {
+    fn new(
+        _api_key: String,
+        _model: String,
+        _base_url: Option<String>,
+    ) -> Result<Self, ProviderError> {
+        Ok(ReplayProvider::default())
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            supports_tools: true,
+            supports_vision: true,
+            supports_thinking: true,
+            supports_streaming: true,
+            max_context_tokens: u32::MAX,
+        }
+    }
+
+    async fn stream(
+        &self,
+        _messages: &[Message],
+        _system_prompt: &str,
+        _tools: Option<ToolRegistry>,
+        _tool_choice: Option<ToolChoice>,
+        _max_tokens: Option<u32>,
+        _temperature: Option<f64>,
+    ) -> Result<
+        impl futures_util::Stream<Item = Result<StreamEvent, ProviderError>> + Send,
+        ProviderError,
+    > {
+        let events = self
+            .responses
+            .lock()
+            .map_err(|_| ProviderError::InvalidConfig("The replay lock is poisoned".to_string()))?
+            .pop_front()
+            .ok_or_else(|| {
+                ProviderError::InvalidRequest(
+                    "The trace has no more responses, the run made more requests than the \
+                    recording"
+                        .to_string(),
+                )
+            })?;
+        Ok(futures_util::stream::iter(events.into_iter().map(Ok)))
+    }
+}
+
+/// A tool that answers with what a recorded call to it with the same input returned
+#[derive(Debug)]
+struct ReplayTool {
+    name: ToolName,
+    is_mutating: bool,
+    /// The recorded calls of every tool not replayed yet, shared between them
+    calls: Arc<Mutex<VecDeque<TracedToolCall>>>,
+}
+
+#[async_trait]
+impl Tool for ReplayTool {
+    async fn call(&self, input: Value) -> ToolResult {
+        let Ok(mut calls) = self.calls.lock() else {
+            return ToolResult::error("The replay lock is poisoned");
+        };
+        // Calls that run at the same time may have finished in another order
+        let position = calls
+            .iter()
+            .position(|call| call.tool_name == self.name && call.input == input)
+            .or_else(|| calls.iter().position(|call| call.tool_name == self.name));
+        match position.and_then(|position| calls.remove(position)) {
+            Some(call) => ToolResult {
+                is_error: call.is_error,
+                content: ToolContent::String(call.output),
+                exit_code: None,
+                model_spend: None,
+            },
+            None => ToolResult::error(format!("The trace has no more results for {}", self.name)),
+        }
+    }
+
+    fn title(&self) -> ToolName {
+        self.name.clone()
+    }
+
+    fn is_mutating(&self) -> bool {
+        self.is_mutating
+    }
+
+    fn description(&self) -> &str {
+        "Answers with the recorded result of a call to this tool"
+    }
+
+    fn input_schema(&self) -> Result<String, ToolError> {
+        Ok(r#"{"type":"object","properties":{},"required":[]}"#.to_string())
+    }
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::Path;
+use tools::Workspace;
+
+/// The most bytes of repository context given to the model by default
+pub const DEFAULT_REPO_CONTEXT_BYTES: usize = 8_000;
+
+/// How many lines of the README are included, enough for its summary
+const README_LINES: usize = 40;
+
+/// How many lines of each manifest are included, enough for the name and dependencies
+const MANIFEST_LINES: usize = 60;
+
+/// The top-level entries listed at most, large monorepos are summarized by their first ones
+const MAX_TREE_ENTRIES: usize = 100;
+
+const READMES: [&str; 3] = ["README.md", "README", "README.rst"];
+
+const MANIFESTS: [&str; 5] = [
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "go.mod",
+    "pom.xml",
+];
+
+/// A short overview of the workspace for the model to start from: the top of the README, the
+/// top-level files and directories, and the project manifests
+///
+/// Kept to roughly `max_bytes`, so it doesn't crowd out the conversation. `None` if there's
+/// nothing to say, e.g. the workspace can't be read.
+pub(crate) fn gather(workspace: &Workspace, max_bytes: usize) -> Option<String> {
+    let root = workspace.root().ok()?;
+    let mut sections = Vec::new();
+
+    if let Some(tree) = tree(&root) {
+        sections.push(format!("Top-level files and directories:\n{}", tree));
+    }
+    if let Some((name, head)) = READMES
+        .iter()
+        .find_map(|name| Some((name, head(&root.join(name), README_LINES)?)))
+    {
+        sections.push(format!("{} (first lines):\n{}", name, head));
+    }
+    for name in MANIFESTS {
+        if let Some(head) = head(&root.join(name), MANIFEST_LINES) {
+            sections.push(format!("{}:\n{}", name, head));
+        }
+    }
+    if sections.is_empty() {
+        return None;
+    }
+
+    let mut context = format!(
+        "Context about the workspace at {}, gathered before this conversation started:\n\n{}",
+        root.display(),
+        sections.join("\n\n")
+    );
+    if context.len() > max_bytes {
+        let mut end = max_bytes;
+        while !context.is_char_boundary(end) {
+            end -= 1;
+        }
+        context.truncate(end);
+        context.push_str("\n[truncated]");
+    }
+    Some(context)
+}
+
+/// The top-level entries of the workspace, directories marked with a trailing slash and hidden
+/// ones left out
+pub(crate) fn tree(root: &Path) -> Option<String> {
+    let mut entries: Vec<String> = fs::read_dir(root)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            if name.starts_with('.') {
+                return None;
+            }
+            Some(match entry.path().is_dir() {
+                true => format!("{}/", name),
+                false => name,
+            })
+        })
+        .collect();
+    if entries.is_empty() {
+        return None;
+    }
+    entries.sort();
+
+    let total = entries.len();
+    entries.truncate(MAX_TREE_ENTRIES);
+    let mut tree = entries.join("\n");
+    if total > MAX_TREE_ENTRIES {
+        tree.push_str(&format!("\n... and {} more", total - MAX_TREE_ENTRIES));
+    }
+    Some(tree)
+}
+
+/// The first `lines` lines of a text file, if it exists
+fn head(path: &Path, lines: usize) -> Option<String> {
+    let contents = fs::read_to_string(path).ok()?;
+    let head: Vec<&str> = contents.lines().take(lines).collect();
+    if head.is_empty() {
+        return None;
+    }
+    Some(head.join("\n"))
+}
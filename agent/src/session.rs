@@ -0,0 +1,56 @@
+use crate::graph::GraphIter;
+use crate::Agent;
+use providers::{BaseProvider, Message};
+
+/// A conversation with an agent, whose turns each see the ones before
+///
+/// Start each turn with [`Session::iter`], and once it has run to the end pass it to
+/// [`Session::record`] so the next turn sees it. A turn that failed part way is best not
+/// recorded, as it may end with a tool call that was never answered. A cancelled turn can be,
+/// its tool calls are answered as cancelled.
+pub struct Session<'a, P: BaseProvider> {
+    agent: &'a Agent<P>,
+    message_history: Vec<Message>,
+}
+
+impl<'a, P: BaseProvider> Session<'a, P> {
+    pub(crate) fn new(agent: &'a Agent<P>) -> Self {
+        Session {
+            agent,
+            message_history: Vec::new(),
+        }
+    }
+
+    /// Start the next turn of the conversation
+    pub fn iter(&self, user_prompt: &str) -> GraphIter<P> {
+        GraphIter::with_history(
+            self.agent.deps(),
+            user_prompt.to_string(),
+            self.message_history.clone(),
+        )
+    }
+
+    /// Remember a finished or interrupted turn, so the turns after it can refer back to it
+    pub fn record(&mut self, graph_iter: &GraphIter<P>) {
+        self.message_history = graph_iter.state().message_history.clone();
+    }
+
+    /// Every message of the conversation so far
+    pub fn message_history(&self) -> &[Message] {
+        &self.message_history
+    }
+
+    /// A copy of the conversation so far, whose turns carry on separately, e.g. to try another
+    /// instruction without losing this one
+    pub fn fork(&self) -> Session<'a, P> {
+        Session {
+            agent: self.agent,
+            message_history: self.message_history.clone(),
+        }
+    }
+
+    /// Forget the conversation so far, starting afresh
+    pub fn clear(&mut self) {
+        self.message_history.clear();
+    }
+}
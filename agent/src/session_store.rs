@@ -0,0 +1,123 @@
+use crate::graph::{GraphError, Snapshot};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tools::workspace::create_aria_dir;
+use tools::Workspace;
+
+/// Directory that snapshots of runs are saved into, one file per session
+const SESSION_DIR: &str = ".aria/sessions";
+
+/// A saved session, as listed by [`SessionStore::list`]
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub id: String,
+    /// The prompt of its latest turn
+    pub prompt: String,
+    /// How many messages the conversation has
+    pub messages: usize,
+    /// Whether its latest run reached the end, rather than stopping part way
+    pub finished: bool,
+}
+
+/// Snapshots of runs saved in the workspace, so they can be resumed after a restart
+///
+/// Each session is saved as `.aria/sessions/<id>.json`, overwritten as the run goes on. Ids are
+/// the time the session started, so the latest sorts last. The JSON is pretty-printed so a
+/// session can be read or diffed by hand.
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    workspace: Workspace,
+}
+
+impl SessionStore {
+    pub fn new(workspace: Workspace) -> Self {
+        SessionStore { workspace }
+    }
+
+    /// An id for a session starting now
+    pub fn new_id() -> String {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Save a snapshot of a session, replacing the one saved before
+    pub fn save(&self, id: &str, snapshot: &Snapshot) -> Result<(), GraphError> {
+        let dir = self.dir()?;
+        create_aria_dir(&dir).map_err(|e| {
+            GraphError::Storage(format!(
+                "Failed to create directory '{}': {}",
+                dir.display(),
+                e
+            ))
+        })?;
+        let json = serde_json::to_string_pretty(snapshot)
+            .map_err(|e| GraphError::Storage(format!("Failed to serialize the session: {}", e)))?;
+
+        // Write alongside and rename over, so a crash mid-write leaves the last snapshot intact
+        let path = dir.join(format!("{}.json", id));
+        let partial = dir.join(format!("{}.json.partial", id));
+        fs::write(&partial, json)
+            .and_then(|_| fs::rename(&partial, &path))
+            .map_err(|e| GraphError::Storage(format!("Failed to save the session: {}", e)))
+    }
+
+    /// The last snapshot saved of a session
+    pub fn load(&self, id: &str) -> Result<Snapshot, GraphError> {
+        let path = self.dir()?.join(format!("{}.json", id));
+        let json = fs::read_to_string(&path)
+            .map_err(|e| GraphError::Storage(format!("Failed to read session {}: {}", id, e)))?;
+        serde_json::from_str(&json)
+            .map_err(|e| GraphError::Storage(format!("Failed to parse session {}: {}", id, e)))
+    }
+
+    /// The id of the session that started last, if any were saved
+    pub fn latest(&self) -> Result<Option<String>, GraphError> {
+        Ok(self.ids()?.last().map(|id| id.to_string()))
+    }
+
+    /// Every saved session, oldest first, leaving out any that can't be read
+    pub fn list(&self) -> Result<Vec<SessionInfo>, GraphError> {
+        Ok(self
+            .ids()?
+            .into_iter()
+            .filter_map(|id| {
+                let id = id.to_string();
+                let snapshot = self.load(&id).ok()?;
+                Some(SessionInfo {
+                    id,
+                    prompt: snapshot.state.current_user_prompt,
+                    messages: snapshot.state.message_history.len(),
+                    finished: snapshot.finished,
+                })
+            })
+            .collect())
+    }
+
+    /// The ids of the saved sessions, in the order they started
+    fn ids(&self) -> Result<Vec<u128>, GraphError> {
+        let mut ids: Vec<u128> = fs::read_dir(self.dir()?)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_str()?
+                    .strip_suffix(".json")?
+                    .parse::<u128>()
+                    .ok()
+            })
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    fn dir(&self) -> Result<PathBuf, GraphError> {
+        let root = self.workspace.root().map_err(GraphError::Storage)?;
+        Ok(root.join(SESSION_DIR))
+    }
+}
@@ -0,0 +1,130 @@
+use crate::graph::{Deps, GraphIter};
+use async_trait::async_trait;
+use providers::BaseProvider;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::sync::Arc;
+use tools::models::{ModelSpend, ToolContent, ToolName, ToolResult, TypedTool};
+use tools::ToolRegistry;
+
+/// The most tokens a subagent uses by default over all its model requests before it's stopped
+pub const DEFAULT_TASK_TOKEN_BUDGET: u64 = 500_000;
+
+/// The most model requests a subagent makes by default
+pub const DEFAULT_TASK_MAX_ITERATIONS: usize = 25;
+
+const TASK_SYSTEM_PROMPT: &str = "You are a subagent working on a task handed to you by another \
+agent. Use the tools to work through it, then reply with a concise final answer that contains \
+everything the other agent needs, such as file paths, line numbers and short excerpts. Only your \
+final reply is passed back.";
+
+/// Input parameters for the task tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct TaskInput {
+    /// The task, with everything needed to do it, as the subagent doesn't see this conversation
+    pub prompt: String,
+}
+
+/// Tool that hands a self-contained task, such as a broad search, to a subagent
+///
+/// The subagent works through it in a conversation of its own with only read-only tools, and
+/// just its final answer is returned, keeping the caller's conversation small. The tokens it
+/// uses and what they cost count towards the caller's run, and so towards its limits.
+pub struct TaskTool<P: BaseProvider> {
+    /// Shared with the agent that offers the tool
+    provider: Arc<P>,
+    /// The tools the subagent is offered
+    tools: ToolRegistry,
+    pub max_tokens: Option<u32>,
+    /// The subagent is stopped once it has made this many model requests
+    pub max_iterations: usize,
+    /// The subagent is stopped once it has used more than this many tokens over all its model
+    /// requests
+    pub token_budget: u64,
+}
+
+impl<P: BaseProvider> TaskTool<P> {
+    /// A task tool whose subagents use `provider`, offered the read-only tools of `tools`
+    pub fn new(provider: Arc<P>, tools: &ToolRegistry) -> Self {
+        TaskTool {
+            provider,
+            tools: tools.read_only(),
+            max_tokens: None,
+            max_iterations: DEFAULT_TASK_MAX_ITERATIONS,
+            token_budget: DEFAULT_TASK_TOKEN_BUDGET,
+        }
+    }
+}
+
+impl<P: BaseProvider> std::fmt::Debug for TaskTool<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaskTool")
+            .field("tools", &self.tools)
+            .field("max_tokens", &self.max_tokens)
+            .field("max_iterations", &self.max_iterations)
+            .field("token_budget", &self.token_budget)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<P> TypedTool for TaskTool<P>
+where
+    P: BaseProvider + Send + Sync + 'static,
+{
+    type Input = TaskInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("task")
+    }
+
+    fn description(&self) -> &'static str {
+        "Hands a self-contained task to a subagent, which works through it with the read-only \
+        tools and returns only its final answer. Use it for broad searches or investigations that \
+        would otherwise fill this conversation with file contents, e.g. finding every place a \
+        function is used and how. The subagent doesn't see this conversation, so describe the task \
+        fully and say what the answer should contain. It can't change files or run commands."
+    }
+
+    async fn run(&self, input: TaskInput) -> ToolResult {
+        let mut deps = Deps::with_shared_provider(
+            self.provider.clone(),
+            Some(self.tools.clone()),
+            TASK_SYSTEM_PROMPT.to_string(),
+            self.max_tokens,
+            None,
+            None,
+        );
+        deps.max_iterations = self.max_iterations;
+
+        let mut graph_iter = GraphIter::new(deps, input.prompt);
+        let result = loop {
+            match graph_iter.next().await {
+                Some(Err(e)) => break ToolResult::error(format!("The subagent failed: {}", e)),
+                Some(Ok(_)) if graph_iter.state().total_tokens() > self.token_budget => {
+                    break ToolResult::error(format!(
+                        "The subagent used up its budget of {} tokens before finishing, try \
+                        a narrower task",
+                        self.token_budget
+                    ));
+                }
+                Some(Ok(_)) => {}
+                None => match graph_iter.get_result() {
+                    Some(answer) => {
+                        break ToolResult::success(ToolContent::String(answer.to_string()))
+                    }
+                    None => break ToolResult::error("The subagent finished without an answer"),
+                },
+            }
+        };
+
+        let state = graph_iter.state();
+        result.with_model_spend(ModelSpend {
+            input_tokens: state.usage.input_tokens,
+            output_tokens: state.usage.output_tokens,
+            cache_creation_input_tokens: state.usage.cache_creation_input_tokens,
+            cache_read_input_tokens: state.usage.cache_read_input_tokens,
+            cost_usd: state.cost_usd,
+        })
+    }
+}
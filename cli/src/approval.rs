@@ -0,0 +1,205 @@
+use agent::{ApprovalPolicy, ToolDecision, DEFAULT_DENIAL_REASON};
+use async_trait::async_trait;
+use config::AutoApprove;
+use serde_json::Value;
+use similar::TextDiff;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use tools::models::ToolName;
+use tools::Workspace;
+
+/// How many lines of a command or diff are shown before asking, the rest are summed up
+const PREVIEW_LINES: usize = 60;
+
+/// The tools that only write, move or delete files in the workspace, run without asking from
+/// [`AutoApprove::Edits`]
+const EDIT_TOOLS: [&str; 5] = [
+    "write_file",
+    "edit_file",
+    "edit_notebook",
+    "move_file",
+    "delete_file",
+];
+
+/// The tools that run a command given in their input, which "always allow" applies to only for
+/// that same command rather than any the tool is asked to run
+const COMMAND_TOOLS: [&str; 2] = ["run_command", "docker_exec"];
+
+/// An approval policy that shows what each tool call would do, the command it runs or the
+/// change it makes to a file, and asks the user in the terminal whether to run it
+pub struct PromptApproval {
+    workspace: Workspace,
+    /// The tools that run without asking
+    auto_approve: AutoApprove,
+    /// Tools, or commands of [`COMMAND_TOOLS`], the user allowed for the rest of the session,
+    /// see [`always_key`]
+    allowed: Mutex<HashSet<String>>,
+}
+
+impl PromptApproval {
+    pub fn new(workspace: Workspace, auto_approve: AutoApprove) -> Self {
+        PromptApproval {
+            workspace,
+            auto_approve,
+            allowed: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// What the call would do, as the user would see it in a terminal or a code review
+    fn preview(&self, tool_name: &ToolName, input: &Value) -> String {
+        let text = |key: &str| input.get(key).and_then(Value::as_str);
+        let preview = match (tool_name.as_str(), text("path")) {
+            ("run_command", _) => text("cmd").map(|cmd| {
+                let args = input
+                    .get("args")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str);
+                let mut lines = vec![format!(
+                    "$ {}",
+                    std::iter::once(cmd)
+                        .chain(args)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                )];
+                // Everything else that changes what runs, so it's approved knowingly
+                if input.get("shell").and_then(Value::as_bool) == Some(true) {
+                    lines.push("  through the shell".to_string());
+                }
+                if let Some(cwd) = text("cwd") {
+                    lines.push(format!("  in {}", cwd));
+                }
+                for (name, value) in input
+                    .get("env")
+                    .and_then(Value::as_object)
+                    .into_iter()
+                    .flatten()
+                {
+                    lines.push(format!(
+                        "  with {}={}",
+                        name,
+                        value.as_str().unwrap_or_default()
+                    ));
+                }
+                if let Some(timeout_secs) = input.get("timeout_secs").and_then(Value::as_u64) {
+                    lines.push(format!("  killed after {}s", timeout_secs));
+                }
+                lines.join("\n")
+            }),
+            ("write_file", Some(path)) => text("contents").map(|contents| {
+                let original = self
+                    .workspace
+                    .resolve(path)
+                    .ok()
+                    .and_then(|file| fs::read_to_string(file).ok())
+                    .unwrap_or_default();
+                diff(path, &original, contents)
+            }),
+            ("edit_file", Some(path)) => match (text("old_string"), text("new_string")) {
+                (Some(old), Some(new)) => Some(diff(path, old, new)),
+                _ => None,
+            },
+            _ => None,
+        };
+        let preview = preview.unwrap_or_else(|| {
+            serde_json::to_string_pretty(input).unwrap_or_else(|_| input.to_string())
+        });
+
+        let lines: Vec<&str> = preview.lines().collect();
+        match lines.len() > PREVIEW_LINES {
+            true => format!(
+                "{}\n... {} more lines",
+                lines[..PREVIEW_LINES].join("\n"),
+                lines.len() - PREVIEW_LINES
+            ),
+            false => preview,
+        }
+    }
+}
+
+#[async_trait]
+impl ApprovalPolicy for PromptApproval {
+    async fn approve(&self, tool_name: &ToolName, input: &Value) -> bool {
+        matches!(self.decide(tool_name, input).await, ToolDecision::Allow)
+    }
+
+    async fn decide(&self, tool_name: &ToolName, input: &Value) -> ToolDecision {
+        let denied = || ToolDecision::Block(DEFAULT_DENIAL_REASON.to_string());
+        let Ok(mut allowed) = self.allowed.lock() else {
+            return denied();
+        };
+        let auto_approved = match self.auto_approve {
+            AutoApprove::ReadOnly => false,
+            AutoApprove::Edits => EDIT_TOOLS.contains(&tool_name.as_str()),
+            AutoApprove::All => true,
+        };
+        let key = always_key(tool_name, input);
+        if auto_approved || allowed.contains(&key) {
+            return ToolDecision::Allow;
+        }
+
+        println!("\nThe agent wants to run {}:", tool_name);
+        println!("{}", self.preview(tool_name, input));
+        let always = match COMMAND_TOOLS.contains(&tool_name.as_str()) {
+            true => "this command".to_string(),
+            false => tool_name.to_string(),
+        };
+        loop {
+            let Some(answer) = ask(&format!(
+                "[y] allow once, [a] always allow {}, [n] deny, [t] deny and tell the model why: ",
+                always
+            )) else {
+                return denied();
+            };
+            match answer.to_lowercase().as_str() {
+                "y" | "yes" => return ToolDecision::Allow,
+                "a" | "always" => {
+                    allowed.insert(key);
+                    return ToolDecision::Allow;
+                }
+                "" | "n" | "no" => return denied(),
+                "t" | "tell" => {
+                    return match ask("Why? ") {
+                        Some(reason) if !reason.is_empty() => {
+                            ToolDecision::Block(format!("The user said: {}", reason))
+                        }
+                        _ => denied(),
+                    }
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+/// What "always allow" is remembered by: the tool, or for [`COMMAND_TOOLS`] the tool with its
+/// whole input, so allowing `ls` doesn't allow `rm -rf` too
+fn always_key(tool_name: &ToolName, input: &Value) -> String {
+    match COMMAND_TOOLS.contains(&tool_name.as_str()) {
+        true => format!("{} {}", tool_name, input),
+        false => tool_name.to_string(),
+    }
+}
+
+/// The changes from `old` to `new` as a unified diff of `path`
+fn diff(path: &str, old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string()
+}
+
+/// The user's answer to a question, `None` if stdin is closed
+fn ask(question: &str) -> Option<String> {
+    print!("{}", question);
+    io::stdout().flush().ok()?;
+
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer).ok()? {
+        0 => None,
+        _ => Some(answer.trim().to_string()),
+    }
+}
@@ -0,0 +1,102 @@
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Cmd, Editor, Helper, KeyCode, KeyEvent, Modifiers};
+use std::fs;
+use std::path::PathBuf;
+
+/// How many of the prompts entered in interactive mode are kept for the arrow keys and Ctrl-R
+const PROMPT_HISTORY_SIZE: usize = 1_000;
+
+/// Opens and closes a prompt spread over several lines, e.g. to enter a code snippet
+const MULTILINE_DELIMITER: &str = "\"\"\"";
+
+/// Reads the prompts of interactive mode, with the prompts of earlier sessions to go back to
+///
+/// A prompt carries on over several lines while a `"""` block is open, after Alt+Enter, or when
+/// it's pasted, so a code snippet or a stack trace is sent as one turn.
+pub struct PromptEditor {
+    editor: Editor<PromptHelper, FileHistory>,
+    /// Where the history is saved, `None` if there's no home directory
+    history: Option<PathBuf>,
+}
+
+impl PromptEditor {
+    pub fn new() -> rustyline::Result<Self> {
+        let mut editor = Editor::with_config(
+            rustyline::Config::builder()
+                .max_history_size(PROMPT_HISTORY_SIZE)?
+                .build(),
+        )?;
+        editor.set_helper(Some(PromptHelper));
+        editor.bind_sequence(KeyEvent(KeyCode::Enter, Modifiers::ALT), Cmd::Newline);
+
+        let history = history_path();
+        if let Some(history) = &history {
+            // There's none the first time
+            let _ = editor.load_history(history);
+        }
+        Ok(PromptEditor { editor, history })
+    }
+
+    /// The next prompt, without the `"""` around it, or `None` once the user presses Ctrl-D
+    pub fn read(&mut self) -> rustyline::Result<Option<String>> {
+        let input = loop {
+            match self.editor.readline("> ") {
+                Ok(input) => break input,
+                // Like a shell, Ctrl-C drops the line being typed and Ctrl-D leaves
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        };
+
+        let input = input.trim();
+        if !input.is_empty() {
+            self.editor.add_history_entry(input)?;
+            // Saved as it goes, so closing the terminal doesn't lose it
+            if let Some(history) = &self.history {
+                let _ = self.editor.save_history(history);
+            }
+        }
+        let input = input
+            .strip_prefix(MULTILINE_DELIMITER)
+            .and_then(|input| input.strip_suffix(MULTILINE_DELIMITER))
+            .unwrap_or(input);
+        Ok(Some(input.trim().to_string()))
+    }
+}
+
+/// Where the prompts entered in interactive mode are kept, creating the directory if need be
+fn history_path() -> Option<PathBuf> {
+    let dir = dirs::home_dir()?.join(".config").join("aria");
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir.join("history"))
+}
+
+/// Keeps reading lines while a `"""` block is open
+struct PromptHelper;
+
+impl Validator for PromptHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(match ctx.input().matches(MULTILINE_DELIMITER).count() % 2 {
+            0 => ValidationResult::Valid(None),
+            _ => ValidationResult::Incomplete,
+        })
+    }
+}
+
+impl Completer for PromptHelper {
+    type Candidate = String;
+}
+
+impl Hinter for PromptHelper {
+    type Hint = String;
+}
+
+impl Highlighter for PromptHelper {}
+
+impl Helper for PromptHelper {}
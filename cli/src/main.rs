@@ -1,26 +1,72 @@
-use agent::{Agent, CurrentNode};
+use agent::{
+    Agent, AgentEvent, CurrentNode, ForbiddenPaths, GraphError, GraphIter, HistoryPolicy,
+    NeverApprove, RegexValidator, SecretDetector, Session, SessionStore, ToolSet, Trace,
+    ViolationAction, DEFAULT_REPO_CONTEXT_BYTES,
+};
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use config::{load_config_file, Config};
+use config::{load_config_file, ApprovalMode, AutoApprove, Config, HistoryMode, ViolationMode};
 use providers::{models::ContentBlock, Role};
-use providers::{BaseProvider, Provider};
+use providers::{BaseProvider, BatchRequest, Message, Provider};
+use serde::Deserialize;
 use std::convert::TryFrom;
-use std::io::{self, Write};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+use tools::{CustomTool, DockerExecTool, McpServer, RunCommandTool, ToolRegistry, Workspace};
 
 // Import the stream wrapper
 mod stream_wrapper;
 use stream_wrapper::CliStreamWrapper;
 
+mod approval;
+use approval::PromptApproval;
+
+mod editor;
+use editor::PromptEditor;
+
 // Constants for the process_input_with_graph parameters
 const DEFAULT_SYSTEM_PROMPT: &str = "You are an AI assistant helping with code editing tasks. \
 The user will provide a request, and you can use tools to help them. \
-Always explain what you're doing before using tools.";
+Always explain what you're doing before using tools. \
+You are working in {{cwd}} on {{os}}, and today is {{date}}.";
+
+/// The turns or rounds of tool calls kept as they are when pruning the history
+const DEFAULT_HISTORY_KEEP: usize = 10;
+
+/// How much of each session's prompt `aria sessions list` shows
+const SESSION_PROMPT_CHARS: usize = 60;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Always call the provider, ignoring the response cache
+    #[arg(long, global = true)]
+    no_cache: bool,
+    /// Only offer the model tools that don't change files or run commands
+    #[arg(long, global = true)]
+    read_only: bool,
+    /// Ask before running tools other than these: read-only, edits (changes to files too) or all
+    #[arg(long, global = true, value_parser = parse_auto_approve, conflicts_with = "yes")]
+    auto_approve: Option<AutoApprove>,
+    /// Run every tool without asking, the same as `--auto-approve=all`
+    #[arg(short, long, global = true)]
+    yes: bool,
+    /// Use this model instead of the one in aria.yml, e.g. a faster one for a quick question
+    #[arg(long, global = true)]
+    model: Option<String>,
+    /// Replace the system prompt for this run, e.g. "Act as a security reviewer"
+    #[arg(long, global = true, conflicts_with = "system_prompt_file")]
+    system_prompt: Option<String>,
+    /// Replace the system prompt for this run with the contents of a file
+    #[arg(long, global = true)]
+    system_prompt_file: Option<String>,
+    /// Add to the end of the system prompt for this run, keeping the rest of it
+    #[arg(long, global = true)]
+    append_system_prompt: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -33,21 +79,101 @@ enum Commands {
     },
     /// Execute a single command
     Exec {
-        /// The command to execute
+        /// The command to execute, `-` to read it from stdin
+        #[arg(
+            required_unless_present = "prompt_file",
+            conflicts_with = "prompt_file"
+        )]
+        prompt: Option<String>,
+        /// Read the command from this file, e.g. a task written in Markdown
+        #[arg(long)]
+        prompt_file: Option<String>,
+        /// The directory to work in
+        #[arg(short, long)]
+        dir: Option<String>,
+        /// Record the model's responses and the tools' results to this file, for `aria replay`
+        #[arg(long)]
+        trace: Option<String>,
+    },
+    /// Run a recorded trace again without calling the API or touching files, failing if it
+    /// doesn't go through the same nodes
+    Replay {
+        /// A trace written by `aria exec --trace`
         #[arg(required = true)]
-        prompt: String,
+        file: String,
+    },
+    /// Carry on with a saved session, running whatever was left then continuing interactively
+    Resume {
+        /// The session to resume, defaults to the latest
+        id: Option<String>,
+        /// The directory to work in
+        #[arg(short, long)]
+        dir: Option<String>,
+    },
+    /// Manage the sessions saved in .aria/sessions
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+    /// Put back the files the latest run changed, going back a further run each time
+    Undo {
         /// The directory to work in
         #[arg(short, long)]
         dir: Option<String>,
     },
+    /// Process many prompts offline through the provider's batch API
+    Batch {
+        /// A JSONL file with one `{"custom_id": "...", "prompt": "..."}` object per line
+        #[arg(required = true)]
+        file: String,
+        /// Write the JSONL results to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SessionsCommand {
+    /// List the saved sessions, the latest last, to pick one for `aria resume`
+    List {
+        /// The directory to work in
+        #[arg(short, long)]
+        dir: Option<String>,
+    },
+}
+
+/// A single line of the input file for the batch subcommand
+#[derive(Deserialize, Debug)]
+struct BatchInput {
+    custom_id: Option<String>,
+    prompt: String,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    // Diagnostics such as how long each node, model request and tool took, e.g.
+    // RUST_LOG=aria=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_writer(io::stderr)
+        .init();
+
+    // Needs neither the config nor an API key, everything comes from the trace
+    if let Some(Commands::Replay { file }) = &cli.command {
+        let trace = Trace::load(Path::new(file))?;
+        let graph_iter = trace.replay(trace.deps()).await?;
+        println!(
+            "Replayed {} steps through the same nodes as the recording",
+            graph_iter.state().path.len()
+        );
+        return Ok(());
+    }
+
     // Load config from file
-    let config = match load_config_file() {
+    let mut config = match load_config_file() {
         Ok(config) => {
             println!("Loaded configuration from file");
             config
@@ -57,11 +183,142 @@ async fn main() -> Result<()> {
         }
     };
 
+    if cli.no_cache {
+        config.cache = None;
+    }
+    if let Some(model) = &cli.model {
+        config.model = model.clone();
+    }
+    let auto_approve = match cli.yes {
+        true => Some(AutoApprove::All),
+        false => cli.auto_approve,
+    };
+    if let Some(auto_approve) = auto_approve {
+        config.approval = ApprovalMode::Ask;
+        config.auto_approve = auto_approve;
+    }
+    if let Some(path) = &cli.system_prompt_file {
+        let system_prompt = fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!("Failed to read the system prompt from {}: {}", path, e)
+        })?;
+        config.system_prompt = Some(system_prompt);
+    }
+    if let Some(system_prompt) = &cli.system_prompt {
+        config.system_prompt = Some(system_prompt.clone());
+    }
+    let append = |prompt: String| match &cli.append_system_prompt {
+        Some(addition) if prompt.is_empty() => addition.clone(),
+        Some(addition) => format!("{}\n\n{}", prompt.trim_end(), addition),
+        None => prompt,
+    };
+    let system_prompt = append(
+        config
+            .system_prompt
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string()),
+    );
+    // Batch requests have no tools, so they only get a system prompt the user gave
+    let batch_system_prompt = append(config.system_prompt.clone().unwrap_or_default());
+
     // Create provider based on config using TryFrom
     let provider = Provider::try_from(&config)?;
 
     // Create agent
-    let agent = Agent::new(provider);
+    let workspace = config
+        .workspace
+        .as_ref()
+        .map(Workspace::new)
+        .unwrap_or_default();
+    let mut tools = ToolRegistry::default();
+    for custom_tool in &config.custom_tools {
+        tools.register(CustomTool {
+            workspace: workspace.clone(),
+            ..CustomTool::try_from(custom_tool)?
+        });
+    }
+    if !config.docker_exec.containers.is_empty() {
+        tools.register(DockerExecTool::try_from(&config.docker_exec)?);
+    }
+    #[cfg(feature = "sql")]
+    for database in &config.sql_databases {
+        tools.register(tools::SqlQueryTool::try_from(database)?);
+    }
+    #[cfg(not(feature = "sql"))]
+    if !config.sql_databases.is_empty() {
+        eprintln!("Ignoring sql_databases, aria was built without the sql feature");
+    }
+    for server in &config.mcp_servers {
+        for tool in McpServer::try_from(server)?.connect().await? {
+            tools.register(tool);
+        }
+    }
+    let mut builder = Agent::builder(provider.clone())
+        .run_command(RunCommandTool::try_from(&config.run_command)?)
+        .workspace(workspace.clone())
+        .tools(&tools)
+        .system_prompt(&system_prompt)
+        .temperature(config.temperature as f64)
+        .stream_wrapper(CliStreamWrapper);
+    if let Some(max_tokens) = config.max_tokens {
+        builder = builder.max_tokens(max_tokens);
+    }
+    if let Some(max_tool_output_bytes) = config.max_tool_output_bytes {
+        builder = builder.max_tool_output_bytes(max_tool_output_bytes);
+    }
+    if let Some(max_iterations) = config.max_iterations {
+        builder = builder.max_iterations(max_iterations);
+    }
+    if let Some(max_run_seconds) = config.max_run_seconds {
+        builder = builder.max_run_duration(Duration::from_secs(max_run_seconds));
+    }
+    if let Some(max_cost_usd) = config.max_cost_usd {
+        builder = builder.max_cost_usd(max_cost_usd);
+    }
+    if let Some(max_total_tokens) = config.max_total_tokens {
+        builder = builder.max_total_tokens(max_total_tokens);
+    }
+    builder = match config.approval {
+        ApprovalMode::Always => builder,
+        ApprovalMode::Never => builder.approval(NeverApprove),
+        ApprovalMode::Ask if config.auto_approve == AutoApprove::All => builder,
+        ApprovalMode::Ask => builder.approval(PromptApproval::new(workspace, config.auto_approve)),
+    };
+    if config.context.auto_include {
+        builder = builder.repo_context(
+            config
+                .context
+                .max_bytes
+                .unwrap_or(DEFAULT_REPO_CONTEXT_BYTES),
+        );
+    }
+    let keep = config.history.keep.unwrap_or(DEFAULT_HISTORY_KEEP);
+    builder = builder.history_policy(match config.history.mode {
+        HistoryMode::KeepAll => HistoryPolicy::KeepAll,
+        HistoryMode::SlidingWindow => HistoryPolicy::SlidingWindow(keep),
+        HistoryMode::DropOldToolResults => HistoryPolicy::DropOldToolResults(keep),
+        HistoryMode::Summarize => HistoryPolicy::Summarize(keep),
+    });
+    let guardrails = &config.guardrails;
+    if guardrails.detect_secrets {
+        builder = builder.validator(SecretDetector::default());
+    }
+    if !guardrails.forbidden_paths.is_empty() {
+        builder = builder.validator(ForbiddenPaths::new(&guardrails.forbidden_paths)?);
+    }
+    for pattern in &guardrails.patterns {
+        builder = builder.validator(RegexValidator::new(&pattern.pattern, &pattern.message)?);
+    }
+    builder = builder.on_violation(match guardrails.on_violation {
+        ViolationMode::Reprompt => ViolationAction::Reprompt,
+        ViolationMode::Block => ViolationAction::Block,
+    });
+    if !config.context.examples.is_empty() {
+        builder = builder.examples(config.context.examples.clone());
+    }
+    if cli.read_only {
+        builder = builder.tool_set(ToolSet::ReadOnly);
+    }
+    let agent = builder.task_tool().build();
 
     // Handle commands
     match &cli.command {
@@ -70,61 +327,264 @@ async fn main() -> Result<()> {
                 std::env::set_current_dir(dir_path)?;
                 println!("Working directory set to: {}", dir_path);
             }
-            interactive_loop(&agent, &config).await?;
+            interactive_loop(&agent, agent.session(), SessionStore::new_id()).await?;
         }
-        Some(Commands::Exec { prompt, dir }) => {
+        Some(Commands::Exec {
+            prompt,
+            prompt_file,
+            dir,
+            trace,
+        }) => {
+            // Read before changing directory, so a relative path is where the user ran aria
+            let prompt = read_prompt(prompt.as_deref(), prompt_file.as_deref())?;
             if let Some(dir_path) = dir {
                 std::env::set_current_dir(dir_path)?;
                 println!("Working directory set to: {}", dir_path);
             }
-            execute_with_graph_iter(&agent, prompt, &config).await?;
+            execute_with_graph_iter(&agent, &prompt, trace.as_deref()).await?;
         }
+        Some(Commands::Resume { id, dir }) => {
+            if let Some(dir_path) = dir {
+                std::env::set_current_dir(dir_path)?;
+                println!("Working directory set to: {}", dir_path);
+            }
+            resume(&agent, id.clone()).await?;
+        }
+        Some(Commands::Sessions {
+            command: SessionsCommand::List { dir },
+        }) => {
+            if let Some(dir_path) = dir {
+                std::env::set_current_dir(dir_path)?;
+            }
+            list_sessions(&agent.sessions())?;
+        }
+        Some(Commands::Undo { dir }) => {
+            if let Some(dir_path) = dir {
+                std::env::set_current_dir(dir_path)?;
+                println!("Working directory set to: {}", dir_path);
+            }
+            for path in agent.undo()? {
+                println!("Restored {}", path.display());
+            }
+        }
+        Some(Commands::Batch { file, output }) => {
+            execute_batch(
+                &provider,
+                file,
+                output.as_deref(),
+                &batch_system_prompt,
+                &config,
+            )
+            .await?;
+        }
+        // Handled before the config is loaded
+        Some(Commands::Replay { .. }) => {}
         None => {
             // Default to interactive mode if no command specified
-            interactive_loop(&agent, &config).await?;
+            interactive_loop(&agent, agent.session(), SessionStore::new_id()).await?;
         }
     }
 
     Ok(())
 }
 
-async fn execute_with_graph_iter<P: BaseProvider>(
+/// The level of `--auto-approve`, named as on the command line rather than in aria.yml
+fn parse_auto_approve(level: &str) -> Result<AutoApprove, String> {
+    match level {
+        "read-only" => Ok(AutoApprove::ReadOnly),
+        "edits" => Ok(AutoApprove::Edits),
+        "all" => Ok(AutoApprove::All),
+        _ => Err("expected read-only, edits or all".to_string()),
+    }
+}
+
+/// The prompt of `aria exec`, as given, from a file, or from stdin when it's `-`
+fn read_prompt(prompt: Option<&str>, prompt_file: Option<&str>) -> Result<String> {
+    let prompt = match (prompt, prompt_file) {
+        (_, Some(path)) => fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read the prompt from {}: {}", path, e))?,
+        (Some("-"), None) => {
+            let mut prompt = String::new();
+            io::stdin().read_to_string(&mut prompt)?;
+            prompt
+        }
+        (Some(prompt), None) => prompt.to_string(),
+        (None, None) => String::new(),
+    };
+    if prompt.trim().is_empty() {
+        return Err(anyhow::anyhow!("The prompt is empty"));
+    }
+    Ok(prompt.trim().to_string())
+}
+
+async fn execute_with_graph_iter<P>(
     agent: &Agent<P>,
     input: &str,
-    config: &Config,
+    trace: Option<&str>,
 ) -> Result<()>
 where
-    P: Clone,
+    P: BaseProvider,
 {
-    let stream_wrapper = Box::new(CliStreamWrapper);
-
-    let mut graph_iter = agent.iter(
-        input,
-        DEFAULT_SYSTEM_PROMPT,
-        config.max_tokens,
-        Some(config.temperature as f64),
-        Some(stream_wrapper),
-    );
+    // Saved after every node, so the run can be resumed if it's interrupted
+    let id = SessionStore::new_id();
+    let mut graph_iter = agent.iter(input).checkpoint_to(agent.sessions(), &id);
+    if trace.is_some() {
+        graph_iter = graph_iter.record_trace();
+    }
+    let result = run_graph_iter(&mut graph_iter).await;
+    // Written however the run ended, a failed run is worth replaying too
+    if let (Some(path), Some(recorded)) = (trace, graph_iter.trace()) {
+        recorded.save(Path::new(path))?;
+        println!("Trace written to {}", path);
+    }
+    // Resuming only helps when running the failed node again may succeed
+    if let Err(e) = &result {
+        if e.downcast_ref::<GraphError>()
+            .is_some_and(GraphError::is_retryable)
+        {
+            eprintln!("Carry on where the run stopped with `aria resume {}`", id);
+        }
+    }
+    result
+}
+
+/// Print the saved sessions, the latest last, with how long ago each started
+fn list_sessions(store: &SessionStore) -> Result<()> {
+    let sessions = store.list()?;
+    if sessions.is_empty() {
+        println!("There are no saved sessions");
+        return Ok(());
+    }
+
+    let now = SessionStore::new_id().parse::<u128>().unwrap_or_default();
+    for session in sessions {
+        let age =
+            Duration::from_millis(now.saturating_sub(session.id.parse().unwrap_or(now)) as u64);
+        let prompt = session.prompt.lines().next().unwrap_or_default();
+        let prompt = match prompt.char_indices().nth(SESSION_PROMPT_CHARS) {
+            Some((end, _)) => format!("{}...", &prompt[..end]),
+            None => prompt.to_string(),
+        };
+        println!(
+            "{}  {:>9}  {:>4} messages  {:<8}  {}",
+            session.id,
+            format_age(age),
+            session.messages,
+            if session.finished {
+                "finished"
+            } else {
+                "stopped"
+            },
+            prompt
+        );
+    }
+    Ok(())
+}
+
+/// A rough time since, e.g. `5m ago`
+fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..=59 => format!("{}s ago", seconds),
+        60..=3_599 => format!("{}m ago", seconds / 60),
+        3_600..=86_399 => format!("{}h ago", seconds / 3_600),
+        _ => format!("{}d ago", seconds / 86_400),
+    }
+}
+
+/// Carry on with a saved session, then continue the conversation interactively
+async fn resume<P>(agent: &Agent<P>, id: Option<String>) -> Result<()>
+where
+    P: BaseProvider,
+{
+    let store = agent.sessions();
+    let id = match id {
+        Some(id) => id,
+        None => store
+            .latest()?
+            .ok_or_else(|| anyhow::anyhow!("There is no saved session to resume"))?,
+    };
+    let snapshot = store.load(&id)?;
+    println!("Resuming session {}", id);
+
+    let mut graph_iter = agent.resume(snapshot).checkpoint_to(store, &id);
+    run_graph_iter(&mut graph_iter).await?;
+
+    let mut session = agent.session();
+    session.record(&graph_iter);
+    interactive_loop(agent, session, id).await
+}
+
+/// Run a graph iterator to the end, printing tool calls and responses as they happen
+async fn run_graph_iter<P>(graph_iter: &mut GraphIter<P>) -> Result<()>
+where
+    P: BaseProvider,
+{
+    // Ctrl-C stops the run rather than the whole process, keeping what it had done so far
+    let cancellation = graph_iter.cancellation_token();
+    let ctrl_c = tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            cancellation.cancel();
+        }
+    });
+    let result = print_graph_iter(graph_iter).await;
+    ctrl_c.abort();
+    result
+}
+
+/// Step through a graph iterator, printing tool calls and responses as they happen
+async fn print_graph_iter<P>(graph_iter: &mut GraphIter<P>) -> Result<()>
+where
+    P: BaseProvider,
+{
+    let mut agent_events = graph_iter.subscribe();
+    let mut reported_events = 0;
+    loop {
+        // Print the run's events while the node runs, so tool calls show as they start
+        let node_result = {
+            let next = graph_iter.next();
+            tokio::pin!(next);
+            loop {
+                tokio::select! {
+                    biased;
+                    Some(event) = agent_events.recv() => print_agent_event(event),
+                    node_result = &mut next => break node_result,
+                }
+            }
+        };
+        while let Ok(event) = agent_events.try_recv() {
+            print_agent_event(event);
+        }
+        let Some(node_result) = node_result else {
+            break;
+        };
 
-    while let Some(node_result) = graph_iter.next().await {
         match node_result {
             Ok(node) => {
+                for event in &graph_iter.events()[reported_events..] {
+                    eprintln!("Warning: {}", event);
+                }
+                reported_events = graph_iter.events().len();
+
                 if matches!(node, CurrentNode::UserRequest) {
                     if let Some(last_message) = graph_iter.state().message_history.last() {
                         if last_message.role == Role::Assistant {
-                            for content_block in &last_message.content {
-                                if let ContentBlock::Text { text } = content_block {
-                                    println!("Response received: {}", text);
-                                    break;
-                                }
+                            if let Some(text) = last_message.text() {
+                                println!("Response received: {}", text);
                             }
                         }
                     }
                 }
             }
+            // Spending more is the user's call, lift the limits for the rest of the run
+            Err(GraphError::BudgetExceeded(msg)) if confirm(&format!("{}.", msg)) => {
+                graph_iter.deps_mut().max_cost_usd = None;
+                graph_iter.deps_mut().max_total_tokens = None;
+                graph_iter.retry();
+            }
             Err(e) => {
                 eprintln!("Error processing node: {:?}", e);
-                return Err(anyhow::anyhow!("Graph processing error: {:?}", e));
+                return Err(e.into());
             }
         }
     }
@@ -132,18 +592,139 @@ where
     Ok(())
 }
 
-async fn interactive_loop<P: BaseProvider>(agent: &Agent<P>, config: &Config) -> Result<()>
+/// Print what a tool call is doing, the streamed text is printed by the stream wrapper
+fn print_agent_event(event: AgentEvent) {
+    match event {
+        AgentEvent::ToolStarted {
+            tool_name, input, ..
+        } => println!("⚙ {} {}…", tool_name, summarize_input(&input)),
+        AgentEvent::ToolProgress {
+            tool_name, elapsed, ..
+        } => println!(
+            "⚙ {} still running after {}s…",
+            tool_name,
+            elapsed.as_secs()
+        ),
+        AgentEvent::ToolFinished(invocation) => {
+            if !invocation.denied {
+                println!(
+                    "{} {} in {:.1}s ({} bytes of output)",
+                    match invocation.is_error {
+                        true => "Failed",
+                        false => "Ran",
+                    },
+                    invocation.tool_name,
+                    invocation.duration.as_secs_f64(),
+                    invocation.output_bytes
+                );
+            }
+            if let Some(diff) = &invocation.diff {
+                println!("{}", diff);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Ask the user whether to carry on, no unless they say yes
+fn confirm(question: &str) -> bool {
+    print!("\n{} Continue anyway? [y/N] ", question);
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// The part of a tool's input that says what it's doing, e.g. the command being run
+fn summarize_input(input: &serde_json::Value) -> String {
+    ["command", "path", "pattern", "prompt", "src"]
+        .iter()
+        .find_map(|key| input.get(key)?.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| input.to_string())
+}
+
+async fn execute_batch(
+    provider: &Provider,
+    file: &str,
+    output: Option<&str>,
+    system_prompt: &str,
+    config: &Config,
+) -> Result<()> {
+    let contents = fs::read_to_string(file)?;
+
+    let requests = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let input: BatchInput = serde_json::from_str(line)
+                .map_err(|e| anyhow::anyhow!("Invalid batch input on line {}: {}", index + 1, e))?;
+            Ok(BatchRequest {
+                custom_id: input
+                    .custom_id
+                    .unwrap_or_else(|| format!("request-{}", index + 1)),
+                messages: vec![Message {
+                    role: Role::User,
+                    content: vec![ContentBlock::Text { text: input.prompt }],
+                }],
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    eprintln!(
+        "Submitting {} requests as a batch, this may take a while...",
+        requests.len()
+    );
+
+    let results = provider
+        .batch(
+            requests,
+            system_prompt,
+            config.max_tokens,
+            Some(config.temperature as f64),
+        )
+        .await?;
+
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(fs::File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+
+    for result in &results {
+        writeln!(writer, "{}", serde_json::to_string(result)?)?;
+    }
+
+    Ok(())
+}
+
+/// Prompt for turns of a conversation until the user leaves, saving it under `id`
+async fn interactive_loop<P>(agent: &Agent<P>, session: Session<'_, P>, id: String) -> Result<()>
 where
-    P: Clone,
+    P: BaseProvider,
 {
-    println!("Interactive mode. Enter 'exit' or 'quit' to end the session.");
+    println!(
+        "Interactive mode. Enter 'clear' to start a new conversation, 'fork' to try something \
+        else without losing this one, 'branches' to list them, 'switch <n>' to go back to one, \
+        or 'exit' or 'quit' to end the session. Wrap a prompt of several lines in \"\"\" or \
+        press Alt+Enter for a new line."
+    );
+    let store = agent.sessions();
 
-    loop {
-        print!("> ");
-        io::stdout().flush()?;
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        let input = input.trim();
+    let mut editor = PromptEditor::new()?;
+
+    // Each branch is a conversation saved under an id of its own
+    let mut branches = vec![(id, session)];
+    let mut current = 0;
+
+    while let Some(input) = editor.read()? {
+        let input = input.as_str();
 
         if input.eq_ignore_ascii_case("exit") || input.eq_ignore_ascii_case("quit") {
             break;
@@ -153,12 +734,72 @@ where
             continue;
         }
 
-        // Use the graph iterator
-        if let Err(e) = execute_with_graph_iter(agent, input, config).await {
-            eprintln!("Error: {}", e);
-            std::io::stdout().flush().expect("Failed to flush stdout");
+        if input.eq_ignore_ascii_case("clear") {
+            branches[current] = (SessionStore::new_id(), agent.session());
+            println!("Started a new conversation.");
+            continue;
+        }
+
+        if input.eq_ignore_ascii_case("fork") {
+            let fork = branches[current].1.fork();
+            branches.push((SessionStore::new_id(), fork));
+            println!(
+                "Forked branch {} into branch {}, enter 'switch {}' to go back.",
+                current + 1,
+                branches.len(),
+                current + 1
+            );
+            current = branches.len() - 1;
+            continue;
+        }
+
+        if input.eq_ignore_ascii_case("branches") {
+            for (index, (id, session)) in branches.iter().enumerate() {
+                println!(
+                    "{} {}: {} messages, session {}",
+                    if index == current { "*" } else { " " },
+                    index + 1,
+                    session.message_history().len(),
+                    id
+                );
+            }
+            continue;
+        }
+
+        if let Some(branch) = input.strip_prefix("switch ") {
+            match branch.trim().parse::<usize>() {
+                Ok(branch) if (1..=branches.len()).contains(&branch) => {
+                    current = branch - 1;
+                    println!("Switched to branch {}.", branch);
+                }
+                _ => println!("There are branches 1 to {}.", branches.len()),
+            }
+            continue;
+        }
+
+        let (id, session) = &mut branches[current];
+        let mut graph_iter = session
+            .iter(input)
+            .checkpoint_to(store.clone(), id.as_str());
+        match run_graph_iter(&mut graph_iter).await {
+            // Only finished or interrupted turns are remembered, a failed one is left out of
+            // the conversation
+            Ok(()) => session.record(&graph_iter),
+            Err(_) if graph_iter.cancellation_token().is_cancelled() => {
+                println!("\nInterrupted, the reply so far is kept. Enter a follow-up instruction.");
+                session.record(&graph_iter);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::io::stdout().flush().expect("Failed to flush stdout");
+            }
         }
     }
 
+    // Every turn was saved as it ran, so there's nothing left to save
+    let (id, session) = &branches[current];
+    if !session.message_history().is_empty() {
+        println!("Session saved, carry on with it with `aria resume {}`", id);
+    }
     Ok(())
 }
@@ -3,6 +3,7 @@ use futures_util::task::{Context, Poll};
 use futures_util::Stream;
 use pin_project_lite::pin_project;
 use providers::models::{ContentBlockStartData, ContentDelta, StreamEvent};
+use providers::ProviderError;
 use std::pin::Pin;
 
 /// A stream wrapper implementation that prints text events to the terminal
@@ -11,8 +12,8 @@ pub struct CliStreamWrapper;
 impl StreamWrapper for CliStreamWrapper {
     fn wrap<'a>(
         &'a self,
-        stream: Pin<Box<dyn Stream<Item = anyhow::Result<StreamEvent>> + Send + 'a>>,
-    ) -> Pin<Box<dyn Stream<Item = anyhow::Result<StreamEvent>> + Send + 'a>> {
+        stream: Pin<Box<dyn Stream<Item = Result<StreamEvent, ProviderError>> + Send + 'a>>,
+    ) -> Pin<Box<dyn Stream<Item = Result<StreamEvent, ProviderError>> + Send + 'a>> {
         Box::pin(CliStream { inner: stream })
     }
 }
@@ -28,9 +29,9 @@ pin_project! {
 
 impl<S> Stream for CliStream<S>
 where
-    S: Stream<Item = anyhow::Result<StreamEvent>> + Send,
+    S: Stream<Item = Result<StreamEvent, ProviderError>> + Send,
 {
-    type Item = anyhow::Result<StreamEvent>;
+    type Item = Result<StreamEvent, ProviderError>;
 
     fn poll_next(
         self: Pin<&mut Self>,
@@ -42,21 +43,19 @@ where
         match this.inner.poll_next(cx) {
             Poll::Ready(Some(Ok(event))) => {
                 match &event {
-                    StreamEvent::ContentBlockStart { content_block, .. } => {
-                        if let ContentBlockStartData::Text { text } = content_block {
-                            if !text.is_empty() {
-                                print!("{}", text);
-                                let _ = std::io::Write::flush(&mut std::io::stdout());
-                            }
-                        }
+                    StreamEvent::ContentBlockStart {
+                        content_block: ContentBlockStartData::Text { text },
+                        ..
                     }
-                    StreamEvent::ContentBlockDelta { delta, .. } => {
-                        if let ContentDelta::TextDelta { text } = delta {
-                            if !text.is_empty() {
-                                print!("{}", text);
-                                let _ = std::io::Write::flush(&mut std::io::stdout());
-                            }
-                        }
+                    | StreamEvent::ContentBlockDelta {
+                        delta: ContentDelta::TextDelta { text },
+                        ..
+                    } if !text.is_empty() => {
+                        print!("{}", text);
+                        let _ = std::io::Write::flush(&mut std::io::stdout());
+                    }
+                    StreamEvent::Reconnecting { attempt } => {
+                        println!("\n[connection lost, retrying (attempt {})...]", attempt);
                     }
                     _ => {}
                 }
@@ -2,7 +2,12 @@ mod error;
 pub mod models; // Changed to public to expose the TryFrom implementation
 
 pub use error::ConfigError;
-pub use models::Config;
+pub use models::{
+    ApprovalMode, AutoApprove, CacheConfig, Config, ContextConfig, CustomToolConfig,
+    DockerExecConfig, FallbackConfig, GuardrailPatternConfig, GuardrailsConfig, HistoryConfig,
+    HistoryMode, McpServerConfig, OAuthConfig, RateLimitConfig, RequestLogConfig, RunCommandConfig,
+    SqlDatabaseConfig, ViolationMode, WebSearchConfig,
+};
 pub use providers::ProviderType;
 
 use std::env;
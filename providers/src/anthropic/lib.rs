@@ -1,25 +1,42 @@
 use crate::{
-    models::{BaseProvider, StreamEvent},
+    cache::ResponseCache,
+    error::{ProviderError, Result},
+    logging::RequestLog,
+    models::{
+        BaseProvider, BatchRequest, BatchResult, EventStream, Pricing, ProviderCapabilities,
+        ProviderOptions, StreamEvent, ToolChoice,
+    },
+    rate_limit::RateLimiter,
     Message,
 };
-use anyhow::{Context, Result};
 use futures_util::stream::{Stream, StreamExt};
-use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
-use reqwest_eventsource::{Error as EventSourceError, EventSource};
-use std::pin::Pin;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER,
+};
+use reqwest::{RequestBuilder, StatusCode};
+use reqwest_eventsource::{retry::Never, Error as EventSourceError, EventSource};
+use std::time::{Duration, Instant};
 use tokio_stream::wrappers::UnboundedReceiverStream;
-use tools::ToolType;
+use tools::ToolRegistry;
 
-use super::models::{AnthropicMessage, AnthropicModel, AnthropicRequest, AnthropicStreamEvent};
+use super::models::{
+    web_search_tool, AnthropicBatch, AnthropicBatchRequest, AnthropicBatchRequestItem,
+    AnthropicBatchResultLine, AnthropicBatchStatus, AnthropicErrorResponse, AnthropicMessage,
+    AnthropicModel, AnthropicRequest, AnthropicStreamEvent, AnthropicToolChoice,
+};
 
 const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
-const DEFAULT_MAX_TOKENS: u32 = 4096;
+const BATCH_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const OAUTH_BETA: &str = "oauth-2025-04-20";
+const MAX_RECONNECT_ATTEMPTS: u32 = 3;
+const RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
 
 #[derive(Clone)]
 pub struct AnthropicProvider {
     api_key: String,
     model: AnthropicModel,
     base_url: String,
+    options: ProviderOptions,
 }
 
 impl BaseProvider for AnthropicProvider {
@@ -28,21 +45,274 @@ impl BaseProvider for AnthropicProvider {
             api_key,
             model: model.try_into()?,
             base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            options: ProviderOptions::default(),
         })
     }
 
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.model.capabilities()
+    }
+
+    fn pricing(&self) -> Option<Pricing> {
+        Some(self.model.pricing())
+    }
+
     async fn stream(
         &self,
-        messages: &Vec<Message>,
-        tools: Option<Vec<ToolType>>,
+        messages: &[Message],
+        system_prompt: &str,
+        tools: Option<ToolRegistry>,
+        tool_choice: Option<ToolChoice>,
         max_tokens: Option<u32>,
         temperature: Option<f64>,
     ) -> Result<impl Stream<Item = Result<StreamEvent>> + Send> {
+        self.open_stream(
+            messages,
+            system_prompt,
+            tools,
+            tool_choice,
+            max_tokens,
+            temperature,
+        )
+        .await
+    }
+}
+
+impl AnthropicProvider {
+    /// Send a streaming request to the Messages API
+    pub(crate) async fn open_stream(
+        &self,
+        messages: &[Message],
+        system_prompt: &str,
+        tools: Option<ToolRegistry>,
+        tool_choice: Option<ToolChoice>,
+        max_tokens: Option<u32>,
+        temperature: Option<f64>,
+    ) -> Result<EventStream> {
+        let request = self.build_request(
+            messages,
+            system_prompt,
+            tools,
+            tool_choice,
+            max_tokens,
+            temperature,
+            true,
+        )?;
+
+        let cache_key = match &self.options.cache {
+            Some(cache) => {
+                let key = ResponseCache::key(&request)?;
+                if let Some(stream) = cache.get(&key) {
+                    tracing::debug!(target: "aria::providers", model = %self.model, "Replaying the response from the cache");
+                    return Ok(stream);
+                }
+                Some(key)
+            }
+            None => None,
+        };
+
+        if let Some(rate_limiter) = &self.options.rate_limiter {
+            let body = serde_json::to_vec(&request)?;
+            let waiting = Instant::now();
+            rate_limiter
+                .acquire(RateLimiter::estimate_tokens(&body))
+                .await;
+            tracing::debug!(
+                target: "aria::providers",
+                waited_ms = waiting.elapsed().as_millis() as u64,
+                "Acquired rate limit capacity"
+            );
+        }
+
+        let endpoint = format!("{}/v1/messages", self.base_url);
+        let headers = self.headers().await?;
+        let log = self
+            .options
+            .logger
+            .as_ref()
+            .map(|logger| logger.request(&endpoint, &headers, &request));
+
+        tracing::debug!(
+            target: "aria::providers",
+            model = %self.model,
+            messages = messages.len(),
+            "Sending a streaming request"
+        );
+        let request = reqwest::Client::new()
+            .post(&endpoint)
+            .headers(headers)
+            .json(&request);
+
+        let mut stream = self.handle_event_stream(request, log.clone())?;
+
+        if let Some(log) = log {
+            stream = log.record(stream);
+        }
+
+        Ok(match (&self.options.cache, cache_key) {
+            (Some(cache), Some(key)) => cache.record(key, stream),
+            _ => stream,
+        })
+    }
+
+    /// Submit many prompts through the Message Batches API and wait for the results
+    ///
+    /// Batches are processed asynchronously by Anthropic and can take a while to
+    /// complete, so this polls until the batch has ended.
+    pub async fn batch(
+        &self,
+        requests: Vec<BatchRequest>,
+        system_prompt: &str,
+        max_tokens: Option<u32>,
+        temperature: Option<f64>,
+    ) -> Result<Vec<BatchResult>> {
+        let endpoint = format!("{}/v1/messages/batches", self.base_url);
+
+        let body = AnthropicBatchRequest {
+            requests: requests
+                .into_iter()
+                .map(|request| {
+                    Ok(AnthropicBatchRequestItem {
+                        params: self.build_request(
+                            &request.messages,
+                            system_prompt,
+                            None,
+                            None,
+                            max_tokens,
+                            temperature,
+                            false,
+                        )?,
+                        custom_id: request.custom_id,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        };
+
+        let headers = self.headers().await?;
+        let log = self
+            .options
+            .logger
+            .as_ref()
+            .map(|logger| logger.request(&endpoint, &headers, &body));
+
+        let results = self.run_batch(&endpoint, headers, body, log.as_ref()).await;
+
+        if let Some(log) = &log {
+            log.complete(None, results.as_ref().err());
+        }
+
+        results
+    }
+
+    /// Create a message batch, poll until it has ended and download its results
+    async fn run_batch(
+        &self,
+        endpoint: &str,
+        headers: HeaderMap,
+        body: AnthropicBatchRequest,
+        log: Option<&RequestLog>,
+    ) -> Result<Vec<BatchResult>> {
+        let client = reqwest::Client::new();
+
+        let response = client
+            .post(endpoint)
+            .headers(headers)
+            .json(&body)
+            .send()
+            .await?;
+        let mut batch: AnthropicBatch = check_status(response, log).await?.json().await?;
+
+        while batch.processing_status != AnthropicBatchStatus::Ended {
+            tokio::time::sleep(BATCH_POLL_INTERVAL).await;
+
+            let response = client
+                .get(format!("{}/{}", endpoint, batch.id))
+                .headers(self.headers().await?)
+                .send()
+                .await?;
+            batch = check_status(response, log).await?.json().await?;
+        }
+
+        let results_url = batch.results_url.ok_or_else(|| {
+            ProviderError::ParseError(format!("Message batch {} has no results", batch.id))
+        })?;
+
+        let response = client
+            .get(&results_url)
+            .headers(self.headers().await?)
+            .send()
+            .await?;
+        let results = check_status(response, log).await?.text().await?;
+
+        results
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str::<AnthropicBatchResultLine>(line)
+                    .map_err(ProviderError::from)
+                    .and_then(TryInto::try_into)
+            })
+            .collect()
+    }
+
+    /// Apply optional settings to every request this provider sends
+    pub fn with_options(self, options: ProviderOptions) -> Self {
+        AnthropicProvider { options, ..self }
+    }
+
+    /// The model this provider sends requests to
+    pub fn model(&self) -> String {
+        self.model.to_string()
+    }
+
+    async fn headers(&self) -> Result<HeaderMap> {
         let mut headers = HeaderMap::new();
-        headers.insert("x-api-key", HeaderValue::from_str(&self.api_key)?);
+        let mut beta_headers = self.options.beta_headers.clone();
+
+        match &self.options.oauth {
+            Some(oauth) => {
+                let token = oauth.access_token().await?;
+                headers.insert(
+                    AUTHORIZATION,
+                    HeaderValue::from_str(&format!("Bearer {}", token))?,
+                );
+                // Subscription tokens are only accepted with the OAuth beta enabled
+                beta_headers.push(OAUTH_BETA.to_string());
+            }
+            None => {
+                headers.insert("x-api-key", HeaderValue::from_str(&self.api_key)?);
+            }
+        }
+
         headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if !beta_headers.is_empty() {
+            headers.insert(
+                "anthropic-beta",
+                HeaderValue::from_str(&beta_headers.join(","))?,
+            );
+        }
+        for (name, value) in &self.options.extra_headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        Ok(headers)
+    }
 
+    // Mirrors the parameters of `stream`, plus whether to stream
+    #[allow(clippy::too_many_arguments)]
+    fn build_request(
+        &self,
+        messages: &[Message],
+        system_prompt: &str,
+        tools: Option<ToolRegistry>,
+        tool_choice: Option<ToolChoice>,
+        max_tokens: Option<u32>,
+        temperature: Option<f64>,
+        stream: bool,
+    ) -> Result<AnthropicRequest> {
         let messages: Vec<AnthropicMessage> = messages
             .iter()
             .map(TryInto::try_into)
@@ -51,81 +321,206 @@ impl BaseProvider for AnthropicProvider {
         let tools = tools
             .map(|tools| {
                 tools
-                    .into_iter()
+                    .iter()
                     .map(|tool| {
-                        tool.to_json_schema()
-                            .map_err(anyhow::Error::from)
-                            .and_then(|schema| {
-                                serde_json::from_str(&schema).context("Failed to parse JSON schema")
-                            })
+                        let schema = tool.to_json_schema()?;
+                        Ok(serde_json::from_str(&schema)?)
                     })
                     .collect::<Result<Vec<serde_json::Value>>>()
             })
             .transpose()?;
 
-        let request = AnthropicRequest {
-            system_prompt: String::new(),
+        // Server tools are offered alongside the client tools
+        let tools = match &self.options.web_search {
+            Some(web_search) => {
+                let mut tools = tools.unwrap_or_default();
+                tools.push(web_search_tool(web_search));
+                Some(tools)
+            }
+            None => tools,
+        };
+
+        // Parallel tool use can only be disabled through an explicit tool choice
+        let tool_choice = match tool_choice {
+            None if self.options.disable_parallel_tool_use && tools.is_some() => {
+                Some(ToolChoice::Auto)
+            }
+            tool_choice => tool_choice,
+        }
+        .map(|choice| AnthropicToolChoice::new(choice, self.options.disable_parallel_tool_use));
+
+        Ok(AnthropicRequest {
+            system_prompt: system_prompt.to_string(),
             temperature,
             model: self.model.clone(),
-            max_tokens: max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            max_tokens: max_tokens.unwrap_or_else(|| self.model.default_max_tokens()),
             messages,
             tools,
-            stream: Some(true),
-        };
-
-        let endpoint = format!("{}/v1/messages", self.base_url);
-
-        let event_source = EventSource::new(
-            reqwest::Client::new()
-                .post(&endpoint)
-                .headers(headers)
-                .json(&request),
-        )?;
-
-        Ok(self.handle_event_stream(event_source))
+            tool_choice,
+            stream: stream.then_some(true),
+        })
     }
-}
 
-impl AnthropicProvider {
+    /// Open the event stream for a request, replaying the request if the connection
+    /// drops before the response is complete
+    ///
+    /// A replayed request produces a fresh response, so a `Reconnecting` event is sent
+    /// first to tell consumers to discard whatever they received before it.
     fn handle_event_stream(
         &self,
-        event_source: EventSource,
-    ) -> Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>> {
+        request: RequestBuilder,
+        log: Option<RequestLog>,
+    ) -> Result<EventStream> {
+        let replay = request.try_clone().ok_or_else(|| {
+            ProviderError::InvalidRequest("Streaming request cannot be replayed".to_string())
+        })?;
+        let mut event_source = EventSource::new(request)
+            .map_err(|err| ProviderError::InvalidRequest(err.to_string()))?;
+        event_source.set_retry_policy(Box::new(Never));
+
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
 
         tokio::spawn(async move {
-            let mut event_source = event_source;
-
-            while let Some(event_result) = event_source.next().await {
-                let send_result = match event_result {
-                    Ok(reqwest_eventsource::Event::Open) => tx.send(Ok(StreamEvent::Ping)),
-                    Ok(reqwest_eventsource::Event::Message(message)) => {
-                        let stream_event =
-                            serde_json::from_str::<AnthropicStreamEvent>(&message.data)
-                                .context("Failed to parse Anthropic stream event")
-                                .and_then(|anthropic_event| anthropic_event.try_into());
-
-                        tx.send(stream_event)
-                    }
-                    Err(EventSourceError::StreamEnded) => {
-                        event_source.close();
-                        break;
-                    }
-                    Err(err) => {
-                        let result = tx.send(Err(anyhow::Error::new(err)));
+            let mut attempt = 0;
+
+            loop {
+                let mut response_finished = false;
+                let mut connection_lost = false;
+
+                while let Some(event_result) = event_source.next().await {
+                    let send_result = match event_result {
+                        Ok(reqwest_eventsource::Event::Open) => tx.send(Ok(StreamEvent::Ping)),
+                        Ok(reqwest_eventsource::Event::Message(message)) => {
+                            let stream_event =
+                                serde_json::from_str::<AnthropicStreamEvent>(&message.data)
+                                    .map_err(ProviderError::from)
+                                    .and_then(|anthropic_event| match anthropic_event {
+                                        // Errors sent mid-stream are surfaced like failed requests
+                                        AnthropicStreamEvent::Error { error } => Err(error.into()),
+                                        anthropic_event => anthropic_event.try_into(),
+                                    });
+
+                            match stream_event {
+                                Ok(StreamEvent::MessageStop) | Err(_) => response_finished = true,
+                                _ => {}
+                            }
+
+                            tx.send(stream_event)
+                        }
+                        Err(EventSourceError::StreamEnded) => {
+                            // The server hung up before finishing the message
+                            connection_lost = !response_finished;
+                            event_source.close();
+                            break;
+                        }
+                        Err(EventSourceError::Transport(_)) if attempt < MAX_RECONNECT_ATTEMPTS => {
+                            connection_lost = true;
+                            event_source.close();
+                            break;
+                        }
+                        Err(EventSourceError::InvalidStatusCode(status, response)) => {
+                            if let Some(log) = &log {
+                                log.response(status, response.headers());
+                            }
+                            let result = tx.send(Err(response_error(response).await));
+                            event_source.close();
+                            result
+                        }
+                        Err(EventSourceError::Transport(err)) => {
+                            let result = tx.send(Err(err.into()));
+                            event_source.close();
+                            result
+                        }
+                        Err(err) => {
+                            let result = tx.send(Err(ProviderError::ParseError(err.to_string())));
+                            event_source.close();
+                            result
+                        }
+                    };
+
+                    if send_result.is_err() {
+                        // Channel closed, receiver dropped
                         event_source.close();
-                        result
+                        return;
                     }
-                };
+                }
+
+                if !connection_lost {
+                    break;
+                }
 
-                if send_result.is_err() {
-                    // Channel closed, receiver dropped
-                    event_source.close();
+                if attempt >= MAX_RECONNECT_ATTEMPTS {
+                    let _ = tx.send(Err(ProviderError::NetworkError(format!(
+                        "Connection lost after {} reconnection attempts",
+                        attempt
+                    ))));
                     break;
                 }
+
+                attempt += 1;
+                tracing::warn!(target: "aria::providers", attempt, "Connection lost, replaying the request");
+                if tx.send(Ok(StreamEvent::Reconnecting { attempt })).is_err() {
+                    return;
+                }
+                tokio::time::sleep(RECONNECT_BACKOFF * attempt).await;
+
+                event_source = match replay.try_clone().map(EventSource::new) {
+                    Some(Ok(event_source)) => event_source,
+                    Some(Err(err)) => {
+                        let _ = tx.send(Err(ProviderError::InvalidRequest(err.to_string())));
+                        break;
+                    }
+                    None => break,
+                };
+                event_source.set_retry_policy(Box::new(Never));
             }
         });
 
-        Box::pin(UnboundedReceiverStream::new(rx))
+        Ok(Box::pin(UnboundedReceiverStream::new(rx)))
+    }
+}
+
+/// Pass successful responses through, turning anything else into a `ProviderError`
+async fn check_status(
+    response: reqwest::Response,
+    log: Option<&RequestLog>,
+) -> Result<reqwest::Response> {
+    if let Some(log) = log {
+        log.response(response.status(), response.headers());
+    }
+
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(response_error(response).await)
+    }
+}
+
+/// Classify an unsuccessful response, preferring the error payload in the body
+/// and falling back to the status code when there isn't one
+async fn response_error(response: reqwest::Response) -> ProviderError {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs);
+
+    if let Ok(body) = response.json::<AnthropicErrorResponse>().await {
+        return body.error.into_provider_error(retry_after);
+    }
+
+    let message = status.to_string();
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ProviderError::AuthFailed(message),
+        StatusCode::TOO_MANY_REQUESTS => ProviderError::RateLimited {
+            retry_after,
+            message,
+        },
+        // Anthropic uses 529 to signal that the API is overloaded
+        status if status.as_u16() == 529 => ProviderError::Overloaded(message),
+        status if status.is_server_error() => ProviderError::ServerError(message),
+        _ => ProviderError::InvalidRequest(message),
     }
 }
@@ -1,36 +1,123 @@
+use crate::error::{ProviderError, Result};
 use crate::{
     models::{
-        ContentBlock, ContentBlockStartData, ContentDelta, MessageDeltaData, MessageStartData,
-        Request as GenericRequest, Role, StreamEvent, StreamProcessor, Usage,
+        BatchOutcome, BatchResult, Citation, ContentBlock, ContentBlockStartData, ContentDelta,
+        Image, MessageDeltaData, MessageStartData, Pricing, ProviderCapabilities,
+        Request as GenericRequest, Role, StreamEvent, StreamProcessor, ToolChoice, Usage,
+        WebSearchOptions,
     },
     Message, Response, ResponseContentBlock, StopReason,
 };
-use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_with::{serde_as, DisplayFromStr, TryFromInto};
+use std::time::Duration;
 use tools::models::ToolName;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum AnthropicModel {
+    Claude3Haiku,
+    Claude3Opus,
+    Claude35Haiku,
+    Claude35Sonnet,
     Claude37Sonnet,
+    ClaudeSonnet4,
+    ClaudeOpus4,
+    ClaudeOpus41,
+}
+
+impl AnthropicModel {
+    /// What the model supports
+    pub fn capabilities(&self) -> ProviderCapabilities {
+        let supports_thinking = matches!(
+            self,
+            AnthropicModel::Claude37Sonnet
+                | AnthropicModel::ClaudeSonnet4
+                | AnthropicModel::ClaudeOpus4
+                | AnthropicModel::ClaudeOpus41
+        );
+
+        ProviderCapabilities {
+            supports_tools: true,
+            supports_vision: true,
+            supports_thinking,
+            supports_streaming: true,
+            max_context_tokens: 200_000,
+        }
+    }
+
+    /// The model's list prices, with the 5 minute prompt cache
+    pub fn pricing(&self) -> Pricing {
+        let (input, output) = match self {
+            AnthropicModel::Claude3Haiku => (0.25, 1.25),
+            AnthropicModel::Claude35Haiku => (0.80, 4.0),
+            AnthropicModel::Claude35Sonnet
+            | AnthropicModel::Claude37Sonnet
+            | AnthropicModel::ClaudeSonnet4 => (3.0, 15.0),
+            AnthropicModel::Claude3Opus
+            | AnthropicModel::ClaudeOpus4
+            | AnthropicModel::ClaudeOpus41 => (15.0, 75.0),
+        };
+
+        // Writing to the cache costs a quarter more than input, reading from it a tenth
+        Pricing {
+            input,
+            output,
+            cache_write: input * 1.25,
+            cache_read: input * 0.1,
+        }
+    }
+
+    /// The `max_tokens` sent when the caller doesn't set one, the most the model can output
+    pub fn default_max_tokens(&self) -> u32 {
+        match self {
+            AnthropicModel::Claude3Haiku | AnthropicModel::Claude3Opus => 4_096,
+            AnthropicModel::Claude35Haiku | AnthropicModel::Claude35Sonnet => 8_192,
+            AnthropicModel::Claude37Sonnet | AnthropicModel::ClaudeSonnet4 => 64_000,
+            AnthropicModel::ClaudeOpus4 | AnthropicModel::ClaudeOpus41 => 32_000,
+        }
+    }
 }
 
 impl std::fmt::Display for AnthropicModel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            AnthropicModel::Claude3Haiku => write!(f, "claude-3-haiku-20240307"),
+            AnthropicModel::Claude3Opus => write!(f, "claude-3-opus-20240229"),
+            AnthropicModel::Claude35Haiku => write!(f, "claude-3-5-haiku-20241022"),
+            AnthropicModel::Claude35Sonnet => write!(f, "claude-3-5-sonnet-20241022"),
             AnthropicModel::Claude37Sonnet => write!(f, "claude-3-7-sonnet-20250219"),
+            AnthropicModel::ClaudeSonnet4 => write!(f, "claude-sonnet-4-20250514"),
+            AnthropicModel::ClaudeOpus4 => write!(f, "claude-opus-4-20250514"),
+            AnthropicModel::ClaudeOpus41 => write!(f, "claude-opus-4-1-20250805"),
         }
     }
 }
 
 impl TryFrom<String> for AnthropicModel {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
+    /// Accepts both pinned model IDs and their aliases
     fn try_from(value: String) -> Result<Self, Self::Error> {
         match value.as_str() {
-            "claude-3-7-sonnet-20250219" => Ok(AnthropicModel::Claude37Sonnet),
-            _ => Err(anyhow::anyhow!("Unknown Anthropic model: {}", value)),
+            "claude-3-haiku-20240307" => Ok(AnthropicModel::Claude3Haiku),
+            "claude-3-opus-20240229" | "claude-3-opus-latest" => Ok(AnthropicModel::Claude3Opus),
+            "claude-3-5-haiku-20241022" | "claude-3-5-haiku-latest" => {
+                Ok(AnthropicModel::Claude35Haiku)
+            }
+            "claude-3-5-sonnet-20241022" | "claude-3-5-sonnet-latest" => {
+                Ok(AnthropicModel::Claude35Sonnet)
+            }
+            "claude-3-7-sonnet-20250219" | "claude-3-7-sonnet-latest" => {
+                Ok(AnthropicModel::Claude37Sonnet)
+            }
+            "claude-sonnet-4-20250514" | "claude-sonnet-4-0" => Ok(AnthropicModel::ClaudeSonnet4),
+            "claude-opus-4-20250514" | "claude-opus-4-0" => Ok(AnthropicModel::ClaudeOpus4),
+            "claude-opus-4-1-20250805" | "claude-opus-4-1" => Ok(AnthropicModel::ClaudeOpus41),
+            _ => Err(ProviderError::InvalidConfig(format!(
+                "Unknown Anthropic model: {}",
+                value
+            ))),
         }
     }
 }
@@ -45,7 +132,7 @@ pub enum AnthropicRole {
 }
 
 impl TryFrom<Role> for AnthropicRole {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(role: Role) -> Result<Self, Self::Error> {
         match role {
@@ -56,7 +143,7 @@ impl TryFrom<Role> for AnthropicRole {
 }
 
 impl TryFrom<AnthropicRole> for Role {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(role: AnthropicRole) -> Result<Self, Self::Error> {
         match role {
@@ -75,12 +162,16 @@ pub enum AnthropicContentBlock {
     #[serde(rename = "tool_result")]
     ToolResult {
         tool_use_id: String,
-        content: String,
+        content: AnthropicToolResultContent,
     },
 
-    /// Plain text content
+    /// Plain text content, with the sources the model cited if there are any
     #[serde(rename = "text")]
-    Text { text: String },
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        citations: Option<Vec<AnthropicCitation>>,
+    },
 
     /// A request to use a tool
     #[serde(rename = "tool_use")]
@@ -90,24 +181,164 @@ pub enum AnthropicContentBlock {
         name: ToolName,
         input: serde_json::Value,
     },
+
+    /// A tool Anthropic ran on its own servers
+    #[serde(rename = "server_tool_use")]
+    ServerToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+
+    /// The results of a server-side web search
+    #[serde(rename = "web_search_tool_result")]
+    WebSearchToolResult {
+        tool_use_id: String,
+        content: serde_json::Value,
+    },
+}
+
+/// The output of a tool, text on its own or alongside images
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AnthropicToolResultContent {
+    Text(String),
+    Blocks(Vec<AnthropicToolResultBlock>),
+}
+
+/// A part of a tool's output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum AnthropicToolResultBlock {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(rename = "image")]
+    Image { source: AnthropicImageSource },
+}
+
+/// Where an image comes from, only inline data is sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum AnthropicImageSource {
+    #[serde(rename = "base64")]
+    Base64 { media_type: String, data: String },
+}
+
+impl From<Image> for AnthropicToolResultBlock {
+    fn from(image: Image) -> Self {
+        AnthropicToolResultBlock::Image {
+            source: AnthropicImageSource::Base64 {
+                media_type: image.media_type,
+                data: image.data,
+            },
+        }
+    }
+}
+
+/// A source cited by the model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum AnthropicCitation {
+    #[serde(rename = "web_search_result_location")]
+    WebSearchResultLocation {
+        url: String,
+        title: Option<String>,
+        cited_text: String,
+        encrypted_index: String,
+    },
+}
+
+impl From<Citation> for AnthropicCitation {
+    fn from(citation: Citation) -> Self {
+        AnthropicCitation::WebSearchResultLocation {
+            url: citation.url,
+            title: citation.title,
+            cited_text: citation.cited_text,
+            encrypted_index: citation.encrypted_index,
+        }
+    }
+}
+
+impl From<AnthropicCitation> for Citation {
+    fn from(citation: AnthropicCitation) -> Self {
+        match citation {
+            AnthropicCitation::WebSearchResultLocation {
+                url,
+                title,
+                cited_text,
+                encrypted_index,
+            } => Citation {
+                url,
+                title,
+                cited_text,
+                encrypted_index,
+            },
+        }
+    }
+}
+
+/// Build the web search server tool definition sent alongside the client tools
+pub fn web_search_tool(options: &WebSearchOptions) -> Value {
+    let mut tool = serde_json::json!({
+        "type": "web_search_20250305",
+        "name": "web_search",
+    });
+    if let Some(max_uses) = options.max_uses {
+        tool["max_uses"] = max_uses.into();
+    }
+    if !options.allowed_domains.is_empty() {
+        tool["allowed_domains"] = options.allowed_domains.clone().into();
+    }
+    if !options.blocked_domains.is_empty() {
+        tool["blocked_domains"] = options.blocked_domains.clone().into();
+    }
+    tool
 }
 
 impl TryFrom<ContentBlock> for AnthropicContentBlock {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(block: ContentBlock) -> Result<Self, Self::Error> {
         match block {
             ContentBlock::ToolResult {
                 tool_use_id,
                 content,
-            } => Ok(AnthropicContentBlock::ToolResult {
-                tool_use_id,
-                content,
+                images,
+            } => {
+                let content = match images.is_empty() {
+                    true => AnthropicToolResultContent::Text(content),
+                    false => AnthropicToolResultContent::Blocks(
+                        std::iter::once(AnthropicToolResultBlock::Text { text: content })
+                            .chain(images.into_iter().map(Into::into))
+                            .collect(),
+                    ),
+                };
+                Ok(AnthropicContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                })
+            }
+            ContentBlock::Text { text } => Ok(AnthropicContentBlock::Text {
+                text,
+                citations: None,
             }),
-            ContentBlock::Text { text } => Ok(AnthropicContentBlock::Text { text }),
             ContentBlock::ToolUse { id, name, input } => {
                 Ok(AnthropicContentBlock::ToolUse { id, name, input })
             }
+            ContentBlock::CitedText { text, citations } => Ok(AnthropicContentBlock::Text {
+                text,
+                citations: Some(citations.into_iter().map(Into::into).collect()),
+            }),
+            ContentBlock::ServerToolUse { id, name, input } => {
+                Ok(AnthropicContentBlock::ServerToolUse { id, name, input })
+            }
+            ContentBlock::WebSearchToolResult {
+                tool_use_id,
+                content,
+            } => Ok(AnthropicContentBlock::WebSearchToolResult {
+                tool_use_id,
+                content,
+            }),
         }
     }
 }
@@ -119,7 +350,7 @@ pub struct AnthropicMessage {
 }
 
 impl TryFrom<Message> for AnthropicMessage {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(message: Message) -> Result<Self, Self::Error> {
         let content: Result<Vec<_>, _> = message
@@ -135,7 +366,7 @@ impl TryFrom<Message> for AnthropicMessage {
 }
 
 impl TryFrom<&Message> for AnthropicMessage {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(message: &Message) -> Result<Self> {
         let content: Result<Vec<_>, _> = message
@@ -153,7 +384,7 @@ impl TryFrom<&Message> for AnthropicMessage {
 #[serde_as]
 #[derive(Debug, Serialize)]
 pub struct AnthropicRequest {
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(rename = "system", skip_serializing_if = "String::is_empty")]
     pub system_prompt: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f64>,
@@ -164,11 +395,62 @@ pub struct AnthropicRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<Value>>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<AnthropicToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
 }
 
+/// Controls whether and which tools the model must use
+#[serde_as]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum AnthropicToolChoice {
+    #[serde(rename = "auto")]
+    Auto {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    #[serde(rename = "any")]
+    Any {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    #[serde(rename = "tool")]
+    Tool {
+        #[serde_as(as = "DisplayFromStr")]
+        name: ToolName,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+}
+
+impl AnthropicToolChoice {
+    /// Create a tool choice, optionally restricting the model to one tool call per turn
+    pub fn new(choice: ToolChoice, disable_parallel_tool_use: bool) -> Self {
+        let disable_parallel_tool_use = disable_parallel_tool_use.then_some(true);
+        match choice {
+            ToolChoice::Auto => AnthropicToolChoice::Auto {
+                disable_parallel_tool_use,
+            },
+            ToolChoice::Any => AnthropicToolChoice::Any {
+                disable_parallel_tool_use,
+            },
+            ToolChoice::Tool(name) => AnthropicToolChoice::Tool {
+                name,
+                disable_parallel_tool_use,
+            },
+        }
+    }
+}
+
+impl From<ToolChoice> for AnthropicToolChoice {
+    fn from(choice: ToolChoice) -> Self {
+        AnthropicToolChoice::new(choice, false)
+    }
+}
+
 impl TryFrom<GenericRequest> for AnthropicRequest {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(request: GenericRequest) -> Result<Self, Self::Error> {
         let messages: Result<Vec<_>, _> = request
@@ -181,13 +463,10 @@ impl TryFrom<GenericRequest> for AnthropicRequest {
             .tools
             .map(|tools| {
                 tools
-                    .into_iter()
+                    .iter()
                     .map(|tool| {
-                        tool.to_json_schema()
-                            .map_err(anyhow::Error::from)
-                            .and_then(|schema| {
-                                serde_json::from_str(&schema).context("Failed to parse JSON schema")
-                            })
+                        let schema = tool.to_json_schema()?;
+                        Ok(serde_json::from_str(&schema)?)
                     })
                     .collect::<Result<Vec<serde_json::Value>>>()
             })
@@ -195,13 +474,11 @@ impl TryFrom<GenericRequest> for AnthropicRequest {
         Ok(AnthropicRequest {
             system_prompt: request.system_prompt,
             temperature: request.temperature,
-            model: request
-                .model
-                .try_into()
-                .context("Failed to convert model string to AnthropicModel")?,
+            model: request.model.try_into()?,
             max_tokens: request.max_tokens,
             messages: messages?,
             tools,
+            tool_choice: request.tool_choice.map(Into::into),
             stream: None,
         })
     }
@@ -218,10 +495,12 @@ pub enum AnthropicStopReason {
     StopSequence,
     #[serde(rename = "tool_use")]
     ToolUse,
+    #[serde(rename = "pause_turn")]
+    PauseTurn,
 }
 
 impl TryFrom<String> for AnthropicStopReason {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
         match value.as_str() {
@@ -229,13 +508,17 @@ impl TryFrom<String> for AnthropicStopReason {
             "max_tokens" => Ok(AnthropicStopReason::MaxTokens),
             "stop_sequence" => Ok(AnthropicStopReason::StopSequence),
             "tool_use" => Ok(AnthropicStopReason::ToolUse),
-            _ => Err(anyhow::anyhow!("Unknown stop reason: {}", value)),
+            "pause_turn" => Ok(AnthropicStopReason::PauseTurn),
+            _ => Err(ProviderError::ParseError(format!(
+                "Unknown stop reason: {}",
+                value
+            ))),
         }
     }
 }
 
 impl TryFrom<StopReason> for AnthropicStopReason {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(reason: StopReason) -> Result<Self, Self::Error> {
         match reason {
@@ -243,12 +526,13 @@ impl TryFrom<StopReason> for AnthropicStopReason {
             StopReason::MaxTokens => Ok(AnthropicStopReason::MaxTokens),
             StopReason::StopSequence => Ok(AnthropicStopReason::StopSequence),
             StopReason::ToolUse => Ok(AnthropicStopReason::ToolUse),
+            StopReason::PauseTurn => Ok(AnthropicStopReason::PauseTurn),
         }
     }
 }
 
 impl TryFrom<AnthropicStopReason> for StopReason {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(reason: AnthropicStopReason) -> Result<Self, Self::Error> {
         match reason {
@@ -256,6 +540,7 @@ impl TryFrom<AnthropicStopReason> for StopReason {
             AnthropicStopReason::MaxTokens => Ok(StopReason::MaxTokens),
             AnthropicStopReason::StopSequence => Ok(StopReason::StopSequence),
             AnthropicStopReason::ToolUse => Ok(StopReason::ToolUse),
+            AnthropicStopReason::PauseTurn => Ok(StopReason::PauseTurn),
         }
     }
 }
@@ -266,7 +551,11 @@ impl TryFrom<AnthropicStopReason> for StopReason {
 #[serde(tag = "type")]
 pub enum AnthropicResponseContentBlock {
     #[serde(rename = "text")]
-    Text { text: String },
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        citations: Option<Vec<AnthropicCitation>>,
+    },
 
     #[serde(rename = "tool_use")]
     ToolUse {
@@ -275,30 +564,78 @@ pub enum AnthropicResponseContentBlock {
         name: ToolName,
         input: serde_json::Value,
     },
+
+    #[serde(rename = "server_tool_use")]
+    ServerToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+
+    #[serde(rename = "web_search_tool_result")]
+    WebSearchToolResult {
+        tool_use_id: String,
+        content: serde_json::Value,
+    },
 }
 
 impl TryFrom<ResponseContentBlock> for AnthropicResponseContentBlock {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(content: ResponseContentBlock) -> Result<Self, Self::Error> {
         match content {
-            ResponseContentBlock::Text { text } => Ok(AnthropicResponseContentBlock::Text { text }),
+            ResponseContentBlock::Text { text } => Ok(AnthropicResponseContentBlock::Text {
+                text,
+                citations: None,
+            }),
             ResponseContentBlock::ToolUse { id, name, input } => {
                 Ok(AnthropicResponseContentBlock::ToolUse { id, name, input })
             }
+            ResponseContentBlock::CitedText { text, citations } => {
+                Ok(AnthropicResponseContentBlock::Text {
+                    text,
+                    citations: Some(citations.into_iter().map(Into::into).collect()),
+                })
+            }
+            ResponseContentBlock::ServerToolUse { id, name, input } => {
+                Ok(AnthropicResponseContentBlock::ServerToolUse { id, name, input })
+            }
+            ResponseContentBlock::WebSearchToolResult {
+                tool_use_id,
+                content,
+            } => Ok(AnthropicResponseContentBlock::WebSearchToolResult {
+                tool_use_id,
+                content,
+            }),
         }
     }
 }
 
 impl TryFrom<AnthropicResponseContentBlock> for ResponseContentBlock {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(content: AnthropicResponseContentBlock) -> Result<Self, Self::Error> {
         match content {
-            AnthropicResponseContentBlock::Text { text } => Ok(ResponseContentBlock::Text { text }),
+            AnthropicResponseContentBlock::Text { text, citations } => match citations {
+                Some(citations) if !citations.is_empty() => Ok(ResponseContentBlock::CitedText {
+                    text,
+                    citations: citations.into_iter().map(Into::into).collect(),
+                }),
+                _ => Ok(ResponseContentBlock::Text { text }),
+            },
             AnthropicResponseContentBlock::ToolUse { id, name, input } => {
                 Ok(ResponseContentBlock::ToolUse { id, name, input })
             }
+            AnthropicResponseContentBlock::ServerToolUse { id, name, input } => {
+                Ok(ResponseContentBlock::ServerToolUse { id, name, input })
+            }
+            AnthropicResponseContentBlock::WebSearchToolResult {
+                tool_use_id,
+                content,
+            } => Ok(ResponseContentBlock::WebSearchToolResult {
+                tool_use_id,
+                content,
+            }),
         }
     }
 }
@@ -317,7 +654,7 @@ pub struct AnthropicUsage {
 }
 
 impl TryFrom<Usage> for AnthropicUsage {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(usage: Usage) -> Result<Self, Self::Error> {
         Ok(AnthropicUsage {
@@ -330,7 +667,7 @@ impl TryFrom<Usage> for AnthropicUsage {
 }
 
 impl TryFrom<AnthropicUsage> for Usage {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(usage: AnthropicUsage) -> Result<Self, Self::Error> {
         Ok(Usage {
@@ -358,7 +695,7 @@ pub struct AnthropicResponse {
 }
 
 impl TryFrom<AnthropicResponse> for Response {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(response: AnthropicResponse) -> Result<Self, Self::Error> {
         let content: Vec<ResponseContentBlock> = response
@@ -372,7 +709,7 @@ impl TryFrom<AnthropicResponse> for Response {
             r#type: response.r#type,
             role: response.role.try_into()?,
             model: response.model,
-            content: content,
+            content,
             stop_reason: response.stop_reason.map(|r| r.try_into()).transpose()?,
             stop_sequence: response.stop_sequence,
             usage: response.usage.map(|u| u.try_into()).transpose()?,
@@ -385,12 +722,111 @@ impl std::fmt::Display for AnthropicRequest {
         write!(
             f,
             "AnthropicRequest {{ model: {}, max_tokens: {} }}",
-            self.model.to_string(),
-            self.max_tokens
+            self.model, self.max_tokens
         )
     }
 }
 
+// Anthropic Message Batches API models
+
+/// A single request in a Message Batches submission
+#[derive(Debug, Serialize)]
+pub struct AnthropicBatchRequestItem {
+    pub custom_id: String,
+    pub params: AnthropicRequest,
+}
+
+/// The body of a Message Batches submission
+#[derive(Debug, Serialize)]
+pub struct AnthropicBatchRequest {
+    pub requests: Vec<AnthropicBatchRequestItem>,
+}
+
+/// The processing status of a message batch
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum AnthropicBatchStatus {
+    #[serde(rename = "in_progress")]
+    InProgress,
+    #[serde(rename = "canceling")]
+    Canceling,
+    #[serde(rename = "ended")]
+    Ended,
+}
+
+/// Counts of the requests in a message batch, by state
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct AnthropicBatchRequestCounts {
+    #[serde(default)]
+    pub processing: u32,
+    #[serde(default)]
+    pub succeeded: u32,
+    #[serde(default)]
+    pub errored: u32,
+    #[serde(default)]
+    pub canceled: u32,
+    #[serde(default)]
+    pub expired: u32,
+}
+
+/// A message batch as returned by the Message Batches API
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnthropicBatch {
+    pub id: String,
+    pub processing_status: AnthropicBatchStatus,
+    #[serde(default)]
+    pub request_counts: AnthropicBatchRequestCounts,
+    pub results_url: Option<String>,
+}
+
+/// The error payload of an errored batch request
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnthropicBatchError {
+    pub error: AnthropicStreamErrorData,
+}
+
+/// The result of a single batch request
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum AnthropicBatchResult {
+    #[serde(rename = "succeeded")]
+    Succeeded { message: AnthropicResponse },
+    #[serde(rename = "errored")]
+    Errored { error: AnthropicBatchError },
+    #[serde(rename = "canceled")]
+    Canceled,
+    #[serde(rename = "expired")]
+    Expired,
+}
+
+/// A line of the JSONL results file of a message batch
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnthropicBatchResultLine {
+    pub custom_id: String,
+    pub result: AnthropicBatchResult,
+}
+
+impl TryFrom<AnthropicBatchResultLine> for BatchResult {
+    type Error = ProviderError;
+
+    fn try_from(line: AnthropicBatchResultLine) -> Result<Self, Self::Error> {
+        let outcome = match line.result {
+            AnthropicBatchResult::Succeeded { message } => BatchOutcome::Succeeded {
+                response: message.try_into()?,
+            },
+            AnthropicBatchResult::Errored { error } => BatchOutcome::Errored {
+                message: format!("{}: {}", error.error.error_type, error.error.message),
+            },
+            AnthropicBatchResult::Canceled => BatchOutcome::Canceled,
+            AnthropicBatchResult::Expired => BatchOutcome::Expired,
+        };
+
+        Ok(BatchResult {
+            custom_id: line.custom_id,
+            outcome,
+        })
+    }
+}
+
 // Anthropic-specific streaming models
 
 /// Anthropic streaming content delta types
@@ -405,10 +841,12 @@ pub enum AnthropicContentDelta {
     ThinkingDelta { thinking: String },
     #[serde(rename = "signature_delta")]
     SignatureDelta { signature: String },
+    #[serde(rename = "citations_delta")]
+    CitationsDelta { citation: AnthropicCitation },
 }
 
 impl TryFrom<AnthropicContentDelta> for ContentDelta {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(delta: AnthropicContentDelta) -> Result<Self, Self::Error> {
         match delta {
@@ -422,6 +860,11 @@ impl TryFrom<AnthropicContentDelta> for ContentDelta {
             AnthropicContentDelta::SignatureDelta { signature } => {
                 Ok(ContentDelta::SignatureDelta { signature })
             }
+            AnthropicContentDelta::CitationsDelta { citation } => {
+                Ok(ContentDelta::CitationsDelta {
+                    citation: citation.into(),
+                })
+            }
         }
     }
 }
@@ -440,10 +883,21 @@ pub enum AnthropicContentBlockStartData {
     },
     #[serde(rename = "thinking")]
     Thinking { thinking: String },
+    #[serde(rename = "server_tool_use")]
+    ServerToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(rename = "web_search_tool_result")]
+    WebSearchToolResult {
+        tool_use_id: String,
+        content: serde_json::Value,
+    },
 }
 
 impl TryFrom<AnthropicContentBlockStartData> for ContentBlockStartData {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(data: AnthropicContentBlockStartData) -> Result<Self, Self::Error> {
         match data {
@@ -456,6 +910,16 @@ impl TryFrom<AnthropicContentBlockStartData> for ContentBlockStartData {
             AnthropicContentBlockStartData::Thinking { thinking } => {
                 Ok(ContentBlockStartData::Thinking { thinking })
             }
+            AnthropicContentBlockStartData::ServerToolUse { id, name, input } => {
+                Ok(ContentBlockStartData::ServerToolUse { id, name, input })
+            }
+            AnthropicContentBlockStartData::WebSearchToolResult {
+                tool_use_id,
+                content,
+            } => Ok(ContentBlockStartData::WebSearchToolResult {
+                tool_use_id,
+                content,
+            }),
         }
     }
 }
@@ -468,7 +932,7 @@ pub struct AnthropicMessageDeltaData {
 }
 
 impl TryFrom<AnthropicMessageDeltaData> for MessageDeltaData {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(data: AnthropicMessageDeltaData) -> Result<Self, Self::Error> {
         Ok(MessageDeltaData {
@@ -497,7 +961,7 @@ pub struct AnthropicMessageStartData {
 }
 
 impl TryFrom<AnthropicMessageStartData> for MessageStartData {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(data: AnthropicMessageStartData) -> Result<Self, Self::Error> {
         let content = data
@@ -527,6 +991,40 @@ pub struct AnthropicStreamErrorData {
     pub message: String,
 }
 
+impl AnthropicStreamErrorData {
+    /// Classify an Anthropic error payload, `retry_after` comes from the response
+    /// headers when the error arrived as an HTTP response
+    pub fn into_provider_error(self, retry_after: Option<Duration>) -> ProviderError {
+        match self.error_type.as_str() {
+            "authentication_error" | "permission_error" => ProviderError::AuthFailed(self.message),
+            "rate_limit_error" => ProviderError::RateLimited {
+                retry_after,
+                message: self.message,
+            },
+            "overloaded_error" => ProviderError::Overloaded(self.message),
+            "api_error" => ProviderError::ServerError(self.message),
+            "invalid_request_error" | "request_too_large"
+                if self.message.contains("prompt is too long") =>
+            {
+                ProviderError::ContextTooLong(self.message)
+            }
+            _ => ProviderError::InvalidRequest(self.message),
+        }
+    }
+}
+
+impl From<AnthropicStreamErrorData> for ProviderError {
+    fn from(error: AnthropicStreamErrorData) -> Self {
+        error.into_provider_error(None)
+    }
+}
+
+/// The body of an unsuccessful response from the Anthropic API
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnthropicErrorResponse {
+    pub error: AnthropicStreamErrorData,
+}
+
 /// Anthropic stream events
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -560,7 +1058,7 @@ pub enum AnthropicStreamEvent {
 }
 
 impl TryFrom<AnthropicStreamEvent> for StreamEvent {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
 
     fn try_from(
         event: AnthropicStreamEvent,
@@ -640,7 +1138,13 @@ impl StreamProcessor<AnthropicStreamEvent> for AnthropicStreamEvent {
                     content_block,
                 } => match content_block {
                     AnthropicContentBlockStartData::Text { text } => {
-                        content_blocks.insert(index, AnthropicResponseContentBlock::Text { text });
+                        content_blocks.insert(
+                            index,
+                            AnthropicResponseContentBlock::Text {
+                                text,
+                                citations: None,
+                            },
+                        );
                     }
                     AnthropicContentBlockStartData::ToolUse { id, name, input } => {
                         content_blocks.insert(
@@ -652,20 +1156,51 @@ impl StreamProcessor<AnthropicStreamEvent> for AnthropicStreamEvent {
                             },
                         );
                     }
+                    AnthropicContentBlockStartData::ServerToolUse { id, name, input } => {
+                        content_blocks.insert(
+                            index,
+                            AnthropicResponseContentBlock::ServerToolUse { id, name, input },
+                        );
+                    }
+                    AnthropicContentBlockStartData::WebSearchToolResult {
+                        tool_use_id,
+                        content,
+                    } => {
+                        content_blocks.insert(
+                            index,
+                            AnthropicResponseContentBlock::WebSearchToolResult {
+                                tool_use_id,
+                                content,
+                            },
+                        );
+                    }
                     _ => {} // Thinking blocks are not added to the final response
                 },
                 AnthropicStreamEvent::ContentBlockDelta { index, delta } => match delta {
                     AnthropicContentDelta::TextDelta { text } => {
                         if let Some(AnthropicResponseContentBlock::Text {
                             text: existing_text,
+                            ..
                         }) = content_blocks.get_mut(&index)
                         {
                             // Append text to existing text block
                             existing_text.push_str(&text);
                         } else {
                             // Create new text block if it doesn't exist
-                            content_blocks
-                                .insert(index, AnthropicResponseContentBlock::Text { text });
+                            content_blocks.insert(
+                                index,
+                                AnthropicResponseContentBlock::Text {
+                                    text,
+                                    citations: None,
+                                },
+                            );
+                        }
+                    }
+                    AnthropicContentDelta::CitationsDelta { citation } => {
+                        if let Some(AnthropicResponseContentBlock::Text { citations, .. }) =
+                            content_blocks.get_mut(&index)
+                        {
+                            citations.get_or_insert_with(Vec::new).push(citation);
                         }
                     }
                     AnthropicContentDelta::InputJsonDelta { partial_json } => {
@@ -680,14 +1215,19 @@ impl StreamProcessor<AnthropicStreamEvent> for AnthropicStreamEvent {
                 AnthropicStreamEvent::ContentBlockStop { index } => {
                     // If we've buffered JSON for a tool use, process it now
                     if let Some(json_string) = json_buffers.remove(&index) {
-                        if let Some(AnthropicResponseContentBlock::ToolUse { input, .. }) =
-                            content_blocks.get_mut(&index)
+                        if let Some(
+                            AnthropicResponseContentBlock::ToolUse { input, .. }
+                            | AnthropicResponseContentBlock::ServerToolUse { input, .. },
+                        ) = content_blocks.get_mut(&index)
                         {
                             // Parse the complete JSON string and update the tool use input
                             match serde_json::from_str::<serde_json::Value>(&json_string) {
                                 Ok(json_value) => *input = json_value,
                                 Err(e) => {
-                                    return Err(anyhow::anyhow!("Failed to parse JSON: {}", e))
+                                    return Err(ProviderError::ParseError(format!(
+                                        "Failed to parse JSON: {}",
+                                        e
+                                    )))
                                 }
                             }
                         }
@@ -699,8 +1239,18 @@ impl StreamProcessor<AnthropicStreamEvent> for AnthropicStreamEvent {
                 } => {
                     stop_reason = delta.stop_reason;
                     stop_sequence = delta.stop_sequence;
+                    // The delta may only count the output, keep the input counted at the start
                     if let Some(u) = delta_usage {
-                        usage = u;
+                        usage = AnthropicUsage {
+                            input_tokens: u.input_tokens.max(usage.input_tokens),
+                            output_tokens: u.output_tokens,
+                            cache_creation_input_tokens: u
+                                .cache_creation_input_tokens
+                                .max(usage.cache_creation_input_tokens),
+                            cache_read_input_tokens: u
+                                .cache_read_input_tokens
+                                .max(usage.cache_read_input_tokens),
+                        };
                     }
                 }
                 _ => {} // Ignore other events
@@ -732,7 +1282,15 @@ impl StreamProcessor<AnthropicStreamEvent> for AnthropicStreamEvent {
 
 // Add trait implementations for the generic StreamProcessor
 impl StreamProcessor<StreamEvent> for StreamEvent {
-    fn process_events(events: Vec<StreamEvent>) -> Result<Response> {
+    fn process_events(mut events: Vec<StreamEvent>) -> Result<Response> {
+        // Only the events after the last reconnection belong to the final response
+        if let Some(index) = events
+            .iter()
+            .rposition(|event| matches!(event, StreamEvent::Reconnecting { .. }))
+        {
+            events.drain(..=index);
+        }
+
         // Convert generic events to Anthropic events
         let anthropic_events: Result<Vec<AnthropicStreamEvent>> = events
             .into_iter()
@@ -776,6 +1334,16 @@ impl StreamProcessor<StreamEvent> for StreamEvent {
                         ContentBlockStartData::Thinking { thinking } => {
                             AnthropicContentBlockStartData::Thinking { thinking }
                         }
+                        ContentBlockStartData::ServerToolUse { id, name, input } => {
+                            AnthropicContentBlockStartData::ServerToolUse { id, name, input }
+                        }
+                        ContentBlockStartData::WebSearchToolResult {
+                            tool_use_id,
+                            content,
+                        } => AnthropicContentBlockStartData::WebSearchToolResult {
+                            tool_use_id,
+                            content,
+                        },
                     };
 
                     Ok(AnthropicStreamEvent::ContentBlockStart {
@@ -798,6 +1366,11 @@ impl StreamProcessor<StreamEvent> for StreamEvent {
                         ContentDelta::SignatureDelta { signature } => {
                             AnthropicContentDelta::SignatureDelta { signature }
                         }
+                        ContentDelta::CitationsDelta { citation } => {
+                            AnthropicContentDelta::CitationsDelta {
+                                citation: citation.into(),
+                            }
+                        }
                     };
 
                     Ok(AnthropicStreamEvent::ContentBlockDelta {
@@ -825,6 +1398,8 @@ impl StreamProcessor<StreamEvent> for StreamEvent {
                 }
                 StreamEvent::MessageStop => Ok(AnthropicStreamEvent::MessageStop),
                 StreamEvent::Ping => Ok(AnthropicStreamEvent::Ping),
+                // Reconnection markers carry no content
+                StreamEvent::Reconnecting { .. } => Ok(AnthropicStreamEvent::Ping),
                 StreamEvent::Error { error } => Ok(AnthropicStreamEvent::Error {
                     error: AnthropicStreamErrorData {
                         error_type: error.error_type,
@@ -0,0 +1,106 @@
+use crate::error::Result;
+use crate::models::{EventStream, StreamEvent};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tools::workspace::create_aria_dir;
+
+/// A cached response, stored as the stream events that produced it
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    created_at: u64,
+    events: Vec<StreamEvent>,
+}
+
+/// An on-disk cache of provider responses keyed on a hash of the request
+///
+/// Identical requests (same model, messages, tools and parameters) replay the
+/// cached stream events instead of calling the API.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    /// Create a cache storing entries in `dir` that expire after `ttl`
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        ResponseCache {
+            dir: dir.into(),
+            ttl,
+        }
+    }
+
+    /// Compute the cache key for a serialized request body
+    pub fn key(request: &impl Serialize) -> Result<String> {
+        let body = serde_json::to_vec(request)?;
+        Ok(format!("{:x}", Sha256::digest(&body)))
+    }
+
+    /// Replay a cached response, if there is one that hasn't expired
+    pub fn get(&self, key: &str) -> Option<EventStream> {
+        let path = self.path(key);
+        let contents = fs::read_to_string(&path).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&contents).ok()?;
+
+        if now().saturating_sub(entry.created_at) > self.ttl.as_secs() {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+
+        Some(Box::pin(stream::iter(entry.events.into_iter().map(Ok))))
+    }
+
+    /// Wrap a live stream so its events are written to the cache once it completes
+    ///
+    /// Streams that error or end before the message is complete are not cached.
+    pub fn record(&self, key: String, mut stream: EventStream) -> EventStream {
+        let cache = self.clone();
+
+        Box::pin(async_stream::stream! {
+            let mut events = Vec::new();
+            let mut failed = false;
+
+            while let Some(event) = stream.next().await {
+                match &event {
+                    Ok(event) => events.push(event.clone()),
+                    Err(_) => failed = true,
+                }
+                yield event;
+            }
+
+            let complete = events
+                .iter()
+                .any(|event| matches!(event, StreamEvent::MessageStop));
+            if complete && !failed {
+                // Failing to write the cache shouldn't fail the request
+                let _ = cache.store(&key, events);
+            }
+        })
+    }
+
+    fn store(&self, key: &str, events: Vec<StreamEvent>) -> std::io::Result<()> {
+        create_aria_dir(&self.dir)?;
+        let entry = CacheEntry {
+            created_at: now(),
+            events,
+        };
+        fs::write(self.path(key), serde_json::to_string(&entry)?)?;
+        Ok(())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,96 @@
+use std::time::Duration;
+use thiserror::Error;
+use tools::models::ToolError;
+
+/// Errors returned by providers, classified so callers can decide whether to
+/// retry, back off, or give up
+#[derive(Error, Debug)]
+pub enum ProviderError {
+    #[error("Authentication failed: {0}")]
+    AuthFailed(String),
+
+    #[error("Rate limited: {message}")]
+    RateLimited {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+
+    #[error("Provider overloaded: {0}")]
+    Overloaded(String),
+
+    #[error("Provider server error: {0}")]
+    ServerError(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Context too long: {0}")]
+    ContextTooLong(String),
+
+    #[error("Invalid provider configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Network error: {0}")]
+    NetworkError(String),
+
+    #[error("Parse error: {0}")]
+    ParseError(String),
+}
+
+impl ProviderError {
+    /// Whether the same request may succeed if it is sent again later
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ProviderError::RateLimited { .. }
+                | ProviderError::Overloaded(_)
+                | ProviderError::ServerError(_)
+                | ProviderError::NetworkError(_)
+        )
+    }
+
+    /// How long the provider asked us to wait before retrying, if it said
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ProviderError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for ProviderError {
+    fn from(err: serde_json::Error) -> Self {
+        ProviderError::ParseError(err.to_string())
+    }
+}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(err: reqwest::Error) -> Self {
+        ProviderError::NetworkError(err.to_string())
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderValue> for ProviderError {
+    fn from(err: reqwest::header::InvalidHeaderValue) -> Self {
+        ProviderError::InvalidConfig(format!("Invalid header value: {}", err))
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderName> for ProviderError {
+    fn from(err: reqwest::header::InvalidHeaderName) -> Self {
+        ProviderError::InvalidConfig(format!("Invalid header name: {}", err))
+    }
+}
+
+impl From<ToolError> for ProviderError {
+    fn from(err: ToolError) -> Self {
+        match err {
+            // The model asked for a tool we never offered
+            ToolError::InvalidToolName(_) => ProviderError::ParseError(err.to_string()),
+            _ => ProviderError::InvalidRequest(err.to_string()),
+        }
+    }
+}
+
+/// Result type used throughout the providers crate
+pub type Result<T, E = ProviderError> = std::result::Result<T, E>;
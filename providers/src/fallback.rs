@@ -0,0 +1,125 @@
+use crate::error::{ProviderError, Result};
+use crate::models::{EventStream, Message, Provider, ProviderOptions, StreamEvent, ToolChoice};
+use futures_util::stream::{self, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tools::ToolRegistry;
+
+/// A provider that tries an ordered list of providers, moving on to the next
+/// entry whenever the current one is overloaded or returns a server error
+#[derive(Clone)]
+pub struct FallbackProvider {
+    providers: Vec<Provider>,
+    served_model: Arc<Mutex<Option<String>>>,
+}
+
+impl FallbackProvider {
+    /// Create a fallback chain, the first provider is the primary
+    pub fn new(providers: Vec<Provider>) -> Result<Self> {
+        if providers.is_empty() {
+            return Err(ProviderError::InvalidConfig(
+                "A fallback chain needs at least one provider".to_string(),
+            ));
+        }
+
+        Ok(FallbackProvider {
+            providers,
+            served_model: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Apply optional settings to every provider in the chain
+    pub fn with_options(self, options: ProviderOptions) -> Self {
+        FallbackProvider {
+            providers: self
+                .providers
+                .into_iter()
+                .map(|provider| provider.with_options(options.clone()))
+                .collect(),
+            served_model: self.served_model,
+        }
+    }
+
+    /// The providers in the chain, in the order they are tried
+    pub fn providers(&self) -> &[Provider] {
+        &self.providers
+    }
+
+    /// The model that served the most recent response, if any
+    pub fn served_model(&self) -> Option<String> {
+        self.served_model
+            .lock()
+            .ok()
+            .and_then(|served_model| served_model.clone())
+    }
+
+    /// Stream a response from the first provider in the chain that is available
+    pub fn stream<'a>(
+        &'a self,
+        messages: &'a [Message],
+        system_prompt: &'a str,
+        tools: Option<ToolRegistry>,
+        tool_choice: Option<ToolChoice>,
+        max_tokens: Option<u32>,
+        temperature: Option<f64>,
+    ) -> Pin<Box<dyn Future<Output = Result<EventStream>> + Send + 'a>> {
+        Box::pin(async move {
+            let last_index = self.providers.len() - 1;
+
+            'providers: for (index, provider) in self.providers.iter().enumerate() {
+                let has_fallback = index < last_index;
+
+                let mut stream = match provider
+                    .stream(
+                        messages,
+                        system_prompt,
+                        tools.clone(),
+                        tool_choice.clone(),
+                        max_tokens,
+                        temperature,
+                    )
+                    .await
+                {
+                    Ok(stream) => stream,
+                    Err(err) if has_fallback && should_fall_back(&err) => continue,
+                    Err(err) => return Err(err),
+                };
+
+                // Buffer events until we know whether the provider accepted the request
+                let mut buffered = Vec::new();
+                loop {
+                    match stream.next().await {
+                        Some(Ok(StreamEvent::Ping)) => buffered.push(Ok(StreamEvent::Ping)),
+                        Some(Err(err)) if has_fallback && should_fall_back(&err) => {
+                            continue 'providers;
+                        }
+                        Some(event) => {
+                            buffered.push(event);
+                            break;
+                        }
+                        None => break,
+                    }
+                }
+
+                if let Ok(mut served_model) = self.served_model.lock() {
+                    *served_model = Some(provider.model());
+                }
+
+                return Ok(Box::pin(stream::iter(buffered).chain(stream)) as EventStream);
+            }
+
+            Err(ProviderError::ServerError(
+                "All providers in the fallback chain failed".to_string(),
+            ))
+        })
+    }
+}
+
+/// Whether an error means the model is unavailable and the next provider should be tried
+fn should_fall_back(err: &ProviderError) -> bool {
+    matches!(
+        err,
+        ProviderError::Overloaded(_) | ProviderError::ServerError(_)
+    )
+}
@@ -1,11 +1,25 @@
 pub mod anthropic;
+pub mod cache;
+pub mod error;
+pub mod fallback;
+pub mod logging;
 pub mod models;
+pub mod oauth;
+pub mod rate_limit;
 
 // Re-export common types and traits from models
 pub use models::{
-    BaseProvider, ContentBlock, Message, Provider, ProviderType, Request, Response,
-    ResponseContentBlock, Role, StopReason,
+    BaseProvider, BatchOutcome, BatchRequest, BatchResult, Citation, ContentBlock, Image, Message,
+    Pricing, Provider, ProviderCapabilities, ProviderOptions, ProviderType, Request, Response,
+    ResponseContentBlock, Role, StopReason, ToolChoice, WebSearchOptions,
 };
 
-// Re-export the AnthropicProvider for easier access
+pub use cache::ResponseCache;
+pub use error::ProviderError;
+pub use logging::RequestLogger;
+pub use oauth::OAuthToken;
+pub use rate_limit::RateLimiter;
+
+// Re-export the concrete providers for easier access
 pub use anthropic::AnthropicProvider;
+pub use fallback::FallbackProvider;
@@ -0,0 +1,181 @@
+use crate::error::ProviderError;
+use crate::models::{EventStream, StreamEvent, Usage};
+use futures_util::stream::StreamExt;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tools::workspace::create_aria_dir;
+
+/// Headers whose values are never written to the log
+const REDACTED_HEADERS: [&str; 2] = ["x-api-key", "authorization"];
+
+/// A line in the request log, every entry of a request shares its `id`
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum LogEntry<'a> {
+    Request {
+        id: &'a str,
+        timestamp: u128,
+        endpoint: &'a str,
+        headers: BTreeMap<String, String>,
+        body: Value,
+    },
+    Response {
+        id: &'a str,
+        status: u16,
+        headers: BTreeMap<String, String>,
+    },
+    Complete {
+        id: &'a str,
+        latency_ms: u128,
+        usage: Option<&'a Usage>,
+        error: Option<String>,
+    },
+}
+
+/// Writes the requests a provider sends, and what came back, to a JSON lines file
+///
+/// Credentials are redacted from the logged headers, so the log is safe to share
+/// when debugging malformed requests such as a bad tool schema.
+#[derive(Debug, Clone)]
+pub struct RequestLogger {
+    path: PathBuf,
+}
+
+/// A request that has been logged and is waiting for its response
+#[derive(Debug, Clone)]
+pub struct RequestLog {
+    logger: RequestLogger,
+    id: String,
+    started: Instant,
+}
+
+impl RequestLogger {
+    /// Create a logger appending to the file at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        RequestLogger { path: path.into() }
+    }
+
+    /// Log a request that is about to be sent
+    pub fn request(
+        &self,
+        endpoint: &str,
+        headers: &HeaderMap,
+        body: &impl Serialize,
+    ) -> RequestLog {
+        let log = RequestLog {
+            logger: self.clone(),
+            id: format!("{:x}", timestamp_nanos()),
+            started: Instant::now(),
+        };
+
+        self.write(&LogEntry::Request {
+            id: &log.id,
+            timestamp: timestamp_nanos() / 1_000_000,
+            endpoint,
+            headers: sanitize(headers),
+            body: serde_json::to_value(body).unwrap_or_default(),
+        });
+
+        log
+    }
+
+    fn write(&self, entry: &LogEntry) {
+        // Failing to write the log shouldn't fail the request
+        let _ = self.append(entry);
+    }
+
+    fn append(&self, entry: &LogEntry) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            create_aria_dir(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)
+    }
+}
+
+impl RequestLog {
+    /// Log the status and headers of the response
+    pub fn response(&self, status: StatusCode, headers: &HeaderMap) {
+        self.logger.write(&LogEntry::Response {
+            id: &self.id,
+            status: status.as_u16(),
+            headers: sanitize(headers),
+        });
+    }
+
+    /// Log that the request finished, successfully or not
+    pub fn complete(&self, usage: Option<&Usage>, error: Option<&ProviderError>) {
+        self.logger.write(&LogEntry::Complete {
+            id: &self.id,
+            latency_ms: self.started.elapsed().as_millis(),
+            usage,
+            error: error.map(ToString::to_string),
+        });
+    }
+
+    /// Wrap a response stream so its latency and token usage are logged when it ends
+    pub fn record(self, mut stream: EventStream) -> EventStream {
+        Box::pin(async_stream::stream! {
+            let mut usage: Option<Usage> = None;
+            let mut error = None;
+
+            while let Some(event) = stream.next().await {
+                match &event {
+                    Ok(StreamEvent::MessageStart { message }) => {
+                        usage = message.usage.clone();
+                    }
+                    Ok(StreamEvent::MessageDelta {
+                        usage: Some(delta_usage),
+                        ..
+                    }) => match &mut usage {
+                        Some(usage) => usage.output_tokens = delta_usage.output_tokens,
+                        None => usage = Some(delta_usage.clone()),
+                    },
+                    Err(err) => error = Some(err.to_string()),
+                    _ => {}
+                }
+                yield event;
+            }
+
+            self.logger.write(&LogEntry::Complete {
+                id: &self.id,
+                latency_ms: self.started.elapsed().as_millis(),
+                usage: usage.as_ref(),
+                error,
+            });
+        })
+    }
+}
+
+/// Copy headers into a map, replacing credentials with a placeholder
+fn sanitize(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+                "[REDACTED]".to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// Nanoseconds since the Unix epoch
+fn timestamp_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default()
+}
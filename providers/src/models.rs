@@ -1,8 +1,10 @@
-use anyhow::Result;
+use crate::error::{ProviderError, Result};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, TryFromInto};
+use std::collections::HashMap;
 use std::fmt;
-use tools::{models::ToolName, ToolType};
+use std::pin::Pin;
+use tools::{models::ToolName, ToolRegistry};
 
 /// Represents the role of the message sender
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -23,6 +25,9 @@ pub enum ContentBlock {
     ToolResult {
         tool_use_id: String,
         content: String,
+        /// Images the tool returned, for providers that support vision
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        images: Vec<Image>,
     },
     /// Plain text content
     #[serde(rename = "text")]
@@ -35,16 +40,67 @@ pub enum ContentBlock {
         name: ToolName,
         input: serde_json::Value,
     },
+    /// Text backed by sources the model cited
+    #[serde(rename = "cited_text")]
+    CitedText {
+        text: String,
+        citations: Vec<Citation>,
+    },
+    /// A tool the provider ran on its own servers, such as web search
+    #[serde(rename = "server_tool_use")]
+    ServerToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    /// The results of a server-side web search, sent back as-is on later turns
+    #[serde(rename = "web_search_tool_result")]
+    WebSearchToolResult {
+        tool_use_id: String,
+        content: serde_json::Value,
+    },
+}
+
+/// An image passed to the model
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Image {
+    /// e.g. `image/png`
+    pub media_type: String,
+    /// The image file, base64 encoded
+    pub data: String,
+}
+
+/// A web page the model cited in its answer
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Citation {
+    pub url: String,
+    pub title: Option<String>,
+    pub cited_text: String,
+    /// Opaque reference to the search result, required when the citation is sent back
+    pub encrypted_index: String,
 }
 
 impl TryFrom<ResponseContentBlock> for ContentBlock {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
     fn try_from(value: ResponseContentBlock) -> Result<Self, Self::Error> {
         match value {
             ResponseContentBlock::Text { text } => Ok(ContentBlock::Text { text }),
             ResponseContentBlock::ToolUse { id, name, input } => {
                 Ok(ContentBlock::ToolUse { id, name, input })
             }
+            ResponseContentBlock::CitedText { text, citations } => {
+                Ok(ContentBlock::CitedText { text, citations })
+            }
+            ResponseContentBlock::ServerToolUse { id, name, input } => {
+                Ok(ContentBlock::ServerToolUse { id, name, input })
+            }
+            ResponseContentBlock::WebSearchToolResult {
+                tool_use_id,
+                content,
+            } => Ok(ContentBlock::WebSearchToolResult {
+                tool_use_id,
+                content,
+            }),
         }
     }
 }
@@ -55,8 +111,26 @@ pub struct Message {
     pub content: Vec<ContentBlock>,
 }
 
+impl Message {
+    /// The text of the message, with cited and uncited blocks joined together
+    pub fn text(&self) -> Option<String> {
+        let blocks: Vec<&str> = self
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } | ContentBlock::CitedText { text, .. } => {
+                    Some(text.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+
+        (!blocks.is_empty()).then(|| blocks.concat())
+    }
+}
+
 impl TryFrom<Response> for Message {
-    type Error = anyhow::Error;
+    type Error = ProviderError;
     fn try_from(response: Response) -> Result<Self, Self::Error> {
         let content = response
             .content
@@ -70,6 +144,17 @@ impl TryFrom<Response> for Message {
     }
 }
 
+/// Controls whether and which tools the model must use
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ToolChoice {
+    /// The model decides whether to use tools
+    Auto,
+    /// The model must use one of the provided tools
+    Any,
+    /// The model must use the named tool
+    Tool(ToolName),
+}
+
 #[derive(Debug, Serialize)]
 pub struct Request {
     #[serde(skip_serializing_if = "String::is_empty")]
@@ -80,7 +165,9 @@ pub struct Request {
     pub max_tokens: u32,
     pub messages: Vec<Message>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tools: Option<Vec<ToolType>>,
+    pub tools: Option<ToolRegistry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
 }
 
 /// Represents the reason why the LLM stopped generating text
@@ -94,6 +181,9 @@ pub enum StopReason {
     StopSequence,
     #[serde(rename = "tool_use")]
     ToolUse,
+    /// A long-running server tool turn was paused and can be continued
+    #[serde(rename = "pause_turn")]
+    PauseTurn,
 }
 
 #[serde_as]
@@ -109,10 +199,26 @@ pub enum ResponseContentBlock {
         name: ToolName,
         input: serde_json::Value,
     },
+    #[serde(rename = "cited_text")]
+    CitedText {
+        text: String,
+        citations: Vec<Citation>,
+    },
+    #[serde(rename = "server_tool_use")]
+    ServerToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(rename = "web_search_tool_result")]
+    WebSearchToolResult {
+        tool_use_id: String,
+        content: serde_json::Value,
+    },
 }
 
 /// Represents usage statistics for the API request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Usage {
     pub input_tokens: u32,
     pub output_tokens: u32,
@@ -122,6 +228,16 @@ pub struct Usage {
     pub cache_read_input_tokens: u32,
 }
 
+impl Usage {
+    /// Add the tokens of another request, e.g. to total those of a whole run
+    pub fn accumulate(&mut self, other: &Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+}
+
 /// A generic response structure for LLM providers
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Response {
@@ -137,7 +253,35 @@ pub struct Response {
     pub usage: Option<Usage>,
 }
 
-/// Generic types for streaming events
+/// A single prompt submitted as part of a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub custom_id: String,
+    pub messages: Vec<Message>,
+}
+
+/// The outcome of a single request in a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum BatchOutcome {
+    #[serde(rename = "succeeded")]
+    Succeeded { response: Response },
+    #[serde(rename = "errored")]
+    Errored { message: String },
+    #[serde(rename = "canceled")]
+    Canceled,
+    #[serde(rename = "expired")]
+    Expired,
+}
+
+/// The result of a single request in a batch, matched to its request by `custom_id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub custom_id: String,
+    pub outcome: BatchOutcome,
+}
+
+// Generic types for streaming events
 
 /// Represents the content delta types in a streaming response
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -151,6 +295,8 @@ pub enum ContentDelta {
     ThinkingDelta { thinking: String },
     #[serde(rename = "signature_delta")]
     SignatureDelta { signature: String },
+    #[serde(rename = "citations_delta")]
+    CitationsDelta { citation: Citation },
 }
 
 /// Represents the different types of streaming events
@@ -180,6 +326,10 @@ pub enum StreamEvent {
     Ping,
     #[serde(rename = "error")]
     Error { error: StreamErrorData },
+    /// The connection dropped and the request is being replayed, any events
+    /// received before this one belong to the abandoned response
+    #[serde(rename = "reconnecting")]
+    Reconnecting { attempt: u32 },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -208,6 +358,17 @@ pub enum ContentBlockStartData {
     },
     #[serde(rename = "thinking")]
     Thinking { thinking: String },
+    #[serde(rename = "server_tool_use")]
+    ServerToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(rename = "web_search_tool_result")]
+    WebSearchToolResult {
+        tool_use_id: String,
+        content: serde_json::Value,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -224,6 +385,9 @@ pub struct MessageStartData {
     pub usage: Option<Usage>,
 }
 
+/// A boxed stream of provider events
+pub type EventStream = Pin<Box<dyn futures_util::Stream<Item = Result<StreamEvent>> + Send>>;
+
 /// A trait for implementing stream processing capability
 pub trait StreamProcessor<T>
 where
@@ -233,6 +397,73 @@ where
     fn process_events(events: Vec<T>) -> Result<Response>;
 }
 
+/// Describes what a provider and its model support, so callers can adapt
+/// their requests instead of failing at request time
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderCapabilities {
+    pub supports_tools: bool,
+    pub supports_vision: bool,
+    pub supports_thinking: bool,
+    pub supports_streaming: bool,
+    pub max_context_tokens: u32,
+}
+
+impl ProviderCapabilities {
+    /// The capabilities guaranteed by every one of `capabilities`, e.g. for a set
+    /// of providers any of which may end up serving a request
+    pub fn intersection(capabilities: impl IntoIterator<Item = ProviderCapabilities>) -> Self {
+        capabilities.into_iter().fold(
+            ProviderCapabilities {
+                supports_tools: true,
+                supports_vision: true,
+                supports_thinking: true,
+                supports_streaming: true,
+                max_context_tokens: u32::MAX,
+            },
+            |acc, caps| ProviderCapabilities {
+                supports_tools: acc.supports_tools && caps.supports_tools,
+                supports_vision: acc.supports_vision && caps.supports_vision,
+                supports_thinking: acc.supports_thinking && caps.supports_thinking,
+                supports_streaming: acc.supports_streaming && caps.supports_streaming,
+                max_context_tokens: acc.max_context_tokens.min(caps.max_context_tokens),
+            },
+        )
+    }
+}
+
+/// What a model charges, in US dollars per million tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pricing {
+    pub input: f64,
+    pub output: f64,
+    /// Input tokens written to the prompt cache
+    pub cache_write: f64,
+    /// Input tokens read from the prompt cache
+    pub cache_read: f64,
+}
+
+impl Pricing {
+    /// What a request that used `usage` cost, in US dollars
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.input_tokens as f64 * self.input
+            + usage.output_tokens as f64 * self.output
+            + usage.cache_creation_input_tokens as f64 * self.cache_write
+            + usage.cache_read_input_tokens as f64 * self.cache_read)
+            / 1_000_000.0
+    }
+
+    /// The highest price of each kind of token among `pricings`, e.g. for a set of providers
+    /// any of which may end up serving a request
+    pub fn highest(pricings: impl IntoIterator<Item = Pricing>) -> Option<Self> {
+        pricings.into_iter().reduce(|acc, pricing| Pricing {
+            input: acc.input.max(pricing.input),
+            output: acc.output.max(pricing.output),
+            cache_write: acc.cache_write.max(pricing.cache_write),
+            cache_read: acc.cache_read.max(pricing.cache_read),
+        })
+    }
+}
+
 /// A trait for LLM providers
 pub trait BaseProvider {
     /// Initialize the provider with API keys and other configuration
@@ -240,11 +471,21 @@ pub trait BaseProvider {
     where
         Self: Sized;
 
+    /// Describe what the provider and its configured model support
+    fn capabilities(&self) -> ProviderCapabilities;
+
+    /// What the configured model charges, `None` if it isn't known
+    fn pricing(&self) -> Option<Pricing> {
+        None
+    }
+
     /// Stream a response from the provider
     fn stream(
         &self,
-        messages: &Vec<Message>,
-        tools: Option<Vec<ToolType>>,
+        messages: &[Message],
+        system_prompt: &str,
+        tools: Option<ToolRegistry>,
+        tool_choice: Option<ToolChoice>,
         max_tokens: Option<u32>,
         temperature: Option<f64>,
     ) -> impl std::future::Future<
@@ -252,6 +493,38 @@ pub trait BaseProvider {
     > + Send;
 }
 
+/// Optional settings applied to every request a provider sends
+#[derive(Debug, Clone, Default)]
+pub struct ProviderOptions {
+    /// Ask the model to make at most one tool call per turn
+    pub disable_parallel_tool_use: bool,
+    /// Replay identical requests from an on-disk cache instead of calling the API
+    pub cache: Option<crate::cache::ResponseCache>,
+    /// Wait for capacity before sending requests instead of tripping the API's limits
+    pub rate_limiter: Option<crate::rate_limit::RateLimiter>,
+    /// Opt-in beta features, sent to Anthropic as `anthropic-beta` headers
+    pub beta_headers: Vec<String>,
+    /// Additional headers sent with every request, e.g. for gateways that require them
+    pub extra_headers: HashMap<String, String>,
+    /// Authenticate with a Claude subscription's OAuth token instead of the API key
+    pub oauth: Option<crate::oauth::OAuthToken>,
+    /// Write sanitized requests, responses, latency and token usage to a log file
+    pub logger: Option<crate::logging::RequestLogger>,
+    /// Let the model search the web on the provider's servers
+    pub web_search: Option<WebSearchOptions>,
+}
+
+/// Settings for the provider's server-side web search tool
+#[derive(Debug, Clone, Default)]
+pub struct WebSearchOptions {
+    /// The most searches the model may run per request, unlimited when unset
+    pub max_uses: Option<u32>,
+    /// Only search these domains, can't be combined with `blocked_domains`
+    pub allowed_domains: Vec<String>,
+    /// Never search these domains
+    pub blocked_domains: Vec<String>,
+}
+
 /// Represents the type of provider to use
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum ProviderType {
@@ -268,8 +541,11 @@ impl fmt::Display for ProviderType {
 
 /// A provider factory that creates and manages specific LLM provider implementations
 #[derive(Clone)]
+// Providers are created once per session, so the size difference doesn't matter
+#[allow(clippy::large_enum_variant)]
 pub enum Provider {
     Anthropic(crate::anthropic::AnthropicProvider),
+    Fallback(crate::fallback::FallbackProvider),
 }
 
 impl Provider {
@@ -288,18 +564,101 @@ impl Provider {
         }
     }
 
+    /// Apply optional settings to the provider, for a fallback chain they are
+    /// applied to every provider in the chain
+    pub fn with_options(self, options: ProviderOptions) -> Self {
+        match self {
+            Provider::Anthropic(provider) => Provider::Anthropic(provider.with_options(options)),
+            Provider::Fallback(provider) => Provider::Fallback(provider.with_options(options)),
+        }
+    }
+
+    /// Wrap a primary provider and its fallbacks into a single provider that
+    /// retries against the next entry when a model is overloaded or failing
+    pub fn with_fallbacks(self, fallbacks: Vec<Provider>) -> Result<Self> {
+        if fallbacks.is_empty() {
+            return Ok(self);
+        }
+
+        let mut providers = vec![self];
+        providers.extend(fallbacks);
+        Ok(Provider::Fallback(crate::fallback::FallbackProvider::new(
+            providers,
+        )?))
+    }
+
+    /// The model requests are sent to, or for a fallback chain the model that
+    /// served the most recent response (the primary model before any request)
+    pub fn model(&self) -> String {
+        match self {
+            Provider::Anthropic(provider) => provider.model(),
+            Provider::Fallback(provider) => provider.served_model().unwrap_or_else(|| {
+                provider
+                    .providers()
+                    .first()
+                    .map(Provider::model)
+                    .unwrap_or_default()
+            }),
+        }
+    }
+
+    /// Submit many prompts as a single offline batch and wait for the results,
+    /// a fallback chain submits the batch to its primary provider
+    pub async fn batch(
+        &self,
+        requests: Vec<BatchRequest>,
+        system_prompt: &str,
+        max_tokens: Option<u32>,
+        temperature: Option<f64>,
+    ) -> Result<Vec<BatchResult>> {
+        match self {
+            Provider::Anthropic(provider) => {
+                provider
+                    .batch(requests, system_prompt, max_tokens, temperature)
+                    .await
+            }
+            Provider::Fallback(provider) => {
+                let primary = provider.providers().first().ok_or_else(|| {
+                    ProviderError::InvalidConfig("Fallback chain has no providers".to_string())
+                })?;
+                Box::pin(primary.batch(requests, system_prompt, max_tokens, temperature)).await
+            }
+        }
+    }
+
     /// Stream a response from the provider
-    pub async fn stream<'a>(
-        &'a self,
-        messages: &'a Vec<Message>,
-        tools: Option<Vec<ToolType>>,
+    pub async fn stream(
+        &self,
+        messages: &[Message],
+        system_prompt: &str,
+        tools: Option<ToolRegistry>,
+        tool_choice: Option<ToolChoice>,
         max_tokens: Option<u32>,
         temperature: Option<f64>,
-    ) -> Result<impl futures_util::Stream<Item = Result<StreamEvent>> + Send + 'a> {
+    ) -> Result<EventStream> {
         match self {
             Provider::Anthropic(provider) => {
                 provider
-                    .stream(messages, tools, max_tokens, temperature)
+                    .open_stream(
+                        messages,
+                        system_prompt,
+                        tools,
+                        tool_choice,
+                        max_tokens,
+                        temperature,
+                    )
+                    .await
+            }
+            Provider::Fallback(provider) => {
+                provider
+                    .stream(
+                        messages,
+                        system_prompt,
+                        tools,
+                        tool_choice,
+                        max_tokens,
+                        temperature,
+                    )
                     .await
             }
         }
@@ -315,19 +674,48 @@ impl BaseProvider for Provider {
         Provider::new(ProviderType::Anthropic, api_key, model, base_url)
     }
 
+    fn capabilities(&self) -> ProviderCapabilities {
+        match self {
+            Provider::Anthropic(provider) => provider.capabilities(),
+            // Any provider in the chain may serve a request, so only promise what they all support
+            Provider::Fallback(provider) => ProviderCapabilities::intersection(
+                provider.providers().iter().map(BaseProvider::capabilities),
+            ),
+        }
+    }
+
+    fn pricing(&self) -> Option<Pricing> {
+        match self {
+            Provider::Anthropic(provider) => provider.pricing(),
+            // Any provider in the chain may serve a request, so assume the most expensive
+            Provider::Fallback(provider) => Pricing::highest(
+                provider
+                    .providers()
+                    .iter()
+                    .map(BaseProvider::pricing)
+                    .collect::<Option<Vec<_>>>()?,
+            ),
+        }
+    }
+
     async fn stream(
         &self,
-        messages: &Vec<Message>,
-        tools: Option<Vec<ToolType>>,
+        messages: &[Message],
+        system_prompt: &str,
+        tools: Option<ToolRegistry>,
+        tool_choice: Option<ToolChoice>,
         max_tokens: Option<u32>,
         temperature: Option<f64>,
     ) -> Result<impl futures_util::Stream<Item = Result<StreamEvent>> + Send> {
-        match self {
-            Provider::Anthropic(provider) => {
-                provider
-                    .stream(messages, tools, max_tokens, temperature)
-                    .await
-            }
-        }
+        Provider::stream(
+            self,
+            messages,
+            system_prompt,
+            tools,
+            tool_choice,
+            max_tokens,
+            temperature,
+        )
+        .await
     }
 }
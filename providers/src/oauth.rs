@@ -0,0 +1,130 @@
+use crate::error::{ProviderError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+const DEFAULT_TOKEN_URL: &str = "https://console.anthropic.com/v1/oauth/token";
+
+/// Tokens are refreshed this long before they expire, so they don't lapse mid-request
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// OAuth tokens as stored in the credentials file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthCredentials {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Seconds since the Unix epoch
+    pub expires_at: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct RefreshRequest<'a> {
+    grant_type: &'static str,
+    refresh_token: &'a str,
+    client_id: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefreshResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: u64,
+}
+
+/// Bearer-token authentication for a Claude subscription, used instead of an API key
+///
+/// The access token is refreshed when it is about to expire and the new tokens are
+/// written back to the credentials file. Clones share the same tokens.
+#[derive(Debug, Clone)]
+pub struct OAuthToken {
+    credentials_path: PathBuf,
+    client_id: String,
+    token_url: String,
+    credentials: Arc<Mutex<OAuthCredentials>>,
+}
+
+impl OAuthToken {
+    /// Load tokens from a credentials file
+    pub fn load(
+        credentials_path: impl Into<PathBuf>,
+        client_id: String,
+        token_url: Option<String>,
+    ) -> Result<Self> {
+        let credentials_path = credentials_path.into();
+        let contents = fs::read_to_string(&credentials_path).map_err(|err| {
+            ProviderError::InvalidConfig(format!(
+                "Failed to read OAuth credentials from {}: {}",
+                credentials_path.display(),
+                err
+            ))
+        })?;
+        let credentials: OAuthCredentials = serde_json::from_str(&contents)?;
+
+        Ok(OAuthToken {
+            credentials_path,
+            client_id,
+            token_url: token_url.unwrap_or_else(|| DEFAULT_TOKEN_URL.to_string()),
+            credentials: Arc::new(Mutex::new(credentials)),
+        })
+    }
+
+    /// A valid access token, refreshing it first if it is about to expire
+    pub async fn access_token(&self) -> Result<String> {
+        let mut credentials = self.credentials.lock().await;
+
+        if credentials.expires_at <= now() + REFRESH_MARGIN.as_secs() {
+            *credentials = self.refresh(&credentials.refresh_token).await?;
+
+            // Failing to persist the tokens only means refreshing again next run
+            if let Ok(contents) = serde_json::to_string_pretty(&*credentials) {
+                let _ = fs::write(&self.credentials_path, contents);
+            }
+        }
+
+        Ok(credentials.access_token.clone())
+    }
+
+    async fn refresh(&self, refresh_token: &str) -> Result<OAuthCredentials> {
+        let response = reqwest::Client::new()
+            .post(&self.token_url)
+            .json(&RefreshRequest {
+                grant_type: "refresh_token",
+                refresh_token,
+                client_id: &self.client_id,
+            })
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(if status.is_server_error() {
+                ProviderError::ServerError(format!("Failed to refresh OAuth token: {}", body))
+            } else {
+                ProviderError::AuthFailed(format!("Failed to refresh OAuth token: {}", body))
+            });
+        }
+
+        let refreshed: RefreshResponse = response.json().await?;
+
+        Ok(OAuthCredentials {
+            access_token: refreshed.access_token,
+            // The token endpoint may keep the existing refresh token
+            refresh_token: refreshed
+                .refresh_token
+                .unwrap_or_else(|| refresh_token.to_string()),
+            expires_at: now() + refreshed.expires_in,
+        })
+    }
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
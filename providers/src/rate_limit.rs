@@ -0,0 +1,118 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Rough number of characters per token, used to estimate request sizes
+const CHARS_PER_TOKEN: usize = 4;
+
+/// A token bucket that refills continuously up to a per-minute capacity
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn new(per_minute: u32) -> Self {
+        Bucket {
+            capacity: per_minute as f64,
+            available: per_minute as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let elapsed = self.last_refill.elapsed().as_secs_f64();
+        self.available = (self.available + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.last_refill = Instant::now();
+    }
+
+    /// How long until `amount` is available, requests larger than the bucket wait for a full bucket
+    fn wait_time(&self, amount: f64) -> Duration {
+        let missing = amount.min(self.capacity) - self.available;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing * 60.0 / self.capacity)
+        }
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= amount.min(self.capacity);
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+/// A client-side rate limiter for requests per minute and tokens per minute
+///
+/// Clones share the same buckets, so every provider built from the same limiter
+/// draws from the same budget.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter, a `None` limit is not enforced
+    pub fn new(requests_per_minute: Option<u32>, tokens_per_minute: Option<u32>) -> Self {
+        RateLimiter {
+            buckets: Arc::new(Mutex::new(Buckets {
+                requests: requests_per_minute.filter(|n| *n > 0).map(Bucket::new),
+                tokens: tokens_per_minute.filter(|n| *n > 0).map(Bucket::new),
+            })),
+        }
+    }
+
+    /// Wait until a request of roughly `tokens` tokens is allowed, then record it
+    pub async fn acquire(&self, tokens: u32) {
+        loop {
+            let wait = {
+                let Ok(mut buckets) = self.buckets.lock() else {
+                    return;
+                };
+                let Buckets {
+                    requests,
+                    tokens: token_bucket,
+                } = &mut *buckets;
+
+                let wait = [
+                    (requests.as_mut(), 1.0),
+                    (token_bucket.as_mut(), tokens as f64),
+                ]
+                .into_iter()
+                .filter_map(|(bucket, amount)| {
+                    bucket.map(|bucket| {
+                        bucket.refill();
+                        bucket.wait_time(amount)
+                    })
+                })
+                .max()
+                .unwrap_or_default();
+
+                if wait.is_zero() {
+                    if let Some(bucket) = requests.as_mut() {
+                        bucket.take(1.0);
+                    }
+                    if let Some(bucket) = token_bucket.as_mut() {
+                        bucket.take(tokens as f64);
+                    }
+                    return;
+                }
+
+                wait
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Estimate the number of tokens in a request body
+    pub fn estimate_tokens(body: &[u8]) -> u32 {
+        (body.len() / CHARS_PER_TOKEN) as u32
+    }
+}
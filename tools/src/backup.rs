@@ -0,0 +1,241 @@
+use crate::workspace::{create_aria_dir, Workspace};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory that files are backed up into before they are changed, one subdirectory per run
+const BACKUP_DIR: &str = ".aria/backups";
+
+/// Lists the files a run changed, and where their original contents were saved
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// A file a run changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BackupEntry {
+    /// The file's path relative to the workspace root
+    path: PathBuf,
+    /// Where its original contents were saved, relative to the run's backup directory, or
+    /// `None` if the run created it
+    backup: Option<PathBuf>,
+}
+
+/// How a run changed a file, see [`Backups::changes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+/// A file a run changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    /// The file's path relative to the workspace root
+    pub path: PathBuf,
+    pub kind: FileChangeKind,
+}
+
+/// Snapshots of the files a run changes, so everything it touched can be restored
+///
+/// Each file is saved the first time it is changed, under `.aria/backups/<run>/`, along with a
+/// manifest of every file changed. The manifest is kept on disk so a run can be undone after
+/// the process that made it has exited. Cloning is cheap, the snapshots are shared.
+#[derive(Debug, Clone)]
+pub struct Backups {
+    workspace: Workspace,
+    /// Names the run's backup directory, the time it started so the latest run sorts last
+    run: String,
+    entries: Arc<Mutex<Vec<BackupEntry>>>,
+}
+
+impl Default for Backups {
+    fn default() -> Self {
+        Backups::new(Workspace::default())
+    }
+}
+
+impl Backups {
+    /// Start backing up the files of a new run in `workspace`
+    pub fn new(workspace: Workspace) -> Self {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default();
+
+        Backups {
+            workspace,
+            run: started.to_string(),
+            entries: Arc::default(),
+        }
+    }
+
+    /// Save the original contents of a file that is about to be changed
+    ///
+    /// Only the first change in a run is saved, later ones would overwrite the original.
+    pub fn snapshot(&self, path: &Path) -> Result<(), String> {
+        let root = self.workspace.root()?;
+        let relative = path.strip_prefix(&root).unwrap_or(path).to_path_buf();
+        let run_dir = root.join(BACKUP_DIR).join(&self.run);
+
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| "The backups are unavailable".to_string())?;
+        if entries.iter().any(|entry| entry.path == relative) {
+            return Ok(());
+        }
+
+        let backup = match path.is_file() {
+            true => {
+                let backup = Path::new("files").join(&relative);
+                let destination = run_dir.join(&backup);
+                if let Some(parent) = destination.parent() {
+                    create_aria_dir(parent).map_err(|e| {
+                        format!("Failed to create directory '{}': {}", parent.display(), e)
+                    })?;
+                }
+                fs::copy(path, &destination)
+                    .map_err(|e| format!("Failed to back up '{}': {}", relative.display(), e))?;
+                Some(backup)
+            }
+            false => None,
+        };
+
+        entries.push(BackupEntry {
+            path: relative,
+            backup,
+        });
+        write_manifest(&run_dir, &entries)
+    }
+
+    /// Save the original contents of a file that is about to be changed, without blocking the
+    /// runtime, see [`Backups::snapshot`]
+    pub async fn snapshot_async(&self, path: &Path) -> Result<(), String> {
+        let backups = self.clone();
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || backups.snapshot(&path))
+            .await
+            .unwrap_or_else(|e| Err(format!("Failed to back up the file: {}", e)))
+    }
+
+    /// The files the run has changed so far, relative to the workspace root
+    pub fn modified_paths(&self) -> Vec<PathBuf> {
+        self.entries
+            .lock()
+            .map(|entries| entries.iter().map(|entry| entry.path.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// How the run has changed each file so far, comparing the originals with what's on disk now
+    ///
+    /// A file the run created and then removed again is left out, as nothing changed.
+    pub fn changes(&self) -> Vec<FileChange> {
+        let Ok(root) = self.workspace.root() else {
+            return Vec::new();
+        };
+        let Ok(entries) = self.entries.lock() else {
+            return Vec::new();
+        };
+
+        entries
+            .iter()
+            .filter_map(|entry| {
+                let exists = root.join(&entry.path).exists();
+                let kind = match (&entry.backup, exists) {
+                    (None, true) => FileChangeKind::Created,
+                    (None, false) => return None,
+                    (Some(_), true) => FileChangeKind::Modified,
+                    (Some(_), false) => FileChangeKind::Deleted,
+                };
+                Some(FileChange {
+                    path: entry.path.clone(),
+                    kind,
+                })
+            })
+            .collect()
+    }
+
+    /// Put back every file the run changed, returning their paths
+    pub fn restore(&self) -> Result<Vec<PathBuf>, String> {
+        let root = self.workspace.root()?;
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| "The backups are unavailable".to_string())?;
+
+        let restored = restore_run(&root, &root.join(BACKUP_DIR).join(&self.run), &entries)?;
+        entries.clear();
+        Ok(restored)
+    }
+
+    /// Put back every file the latest run in `workspace` changed, returning their paths
+    ///
+    /// The run's backups are removed afterwards, so undoing again goes back a further run.
+    pub fn restore_latest(workspace: &Workspace) -> Result<Vec<PathBuf>, String> {
+        let root = workspace.root()?;
+        let backup_dir = root.join(BACKUP_DIR);
+
+        let latest = fs::read_dir(&backup_dir)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().join(MANIFEST_FILE).is_file())
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<u128>().ok())
+            .max()
+            .ok_or_else(|| "There is no run to undo".to_string())?;
+        let run_dir = backup_dir.join(latest.to_string());
+
+        let manifest = fs::read_to_string(run_dir.join(MANIFEST_FILE))
+            .map_err(|e| format!("Failed to read the backup manifest: {}", e))?;
+        let entries: Vec<BackupEntry> = serde_json::from_str(&manifest)
+            .map_err(|e| format!("Failed to parse the backup manifest: {}", e))?;
+
+        restore_run(&root, &run_dir, &entries)
+    }
+}
+
+/// Put back the files of a run, then remove its backups
+fn restore_run(
+    root: &Path,
+    run_dir: &Path,
+    entries: &[BackupEntry],
+) -> Result<Vec<PathBuf>, String> {
+    for entry in entries {
+        let path = root.join(&entry.path);
+        match &entry.backup {
+            Some(backup) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).map_err(|e| {
+                        format!("Failed to create directory '{}': {}", parent.display(), e)
+                    })?;
+                }
+                fs::copy(run_dir.join(backup), &path)
+                    .map_err(|e| format!("Failed to restore '{}': {}", entry.path.display(), e))?;
+            }
+            // The run created the file, so restoring means removing it
+            None if path.is_file() => {
+                fs::remove_file(&path)
+                    .map_err(|e| format!("Failed to remove '{}': {}", entry.path.display(), e))?;
+            }
+            None => {}
+        }
+    }
+
+    if run_dir.exists() {
+        fs::remove_dir_all(run_dir)
+            .map_err(|e| format!("Failed to remove the backups of the run: {}", e))?;
+    }
+
+    Ok(entries.iter().map(|entry| entry.path.clone()).collect())
+}
+
+fn write_manifest(run_dir: &Path, entries: &[BackupEntry]) -> Result<(), String> {
+    create_aria_dir(run_dir)
+        .map_err(|e| format!("Failed to create directory '{}': {}", run_dir.display(), e))?;
+    let manifest = serde_json::to_string_pretty(entries)
+        .map_err(|e| format!("Failed to serialize the backup manifest: {}", e))?;
+    fs::write(run_dir.join(MANIFEST_FILE), manifest)
+        .map_err(|e| format!("Failed to write the backup manifest: {}", e))
+}
@@ -0,0 +1,40 @@
+use crate::process::{output_with_timeout, ProcessError};
+use std::process::Output;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// How long listing containers or reading their logs may take
+pub(crate) const DOCKER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Run the docker CLI, returning its output whether or not it succeeded
+pub(crate) async fn docker(args: &[&str], timeout: Duration) -> Result<Output, String> {
+    let mut command = Command::new("docker");
+    command.args(args);
+
+    match output_with_timeout(&mut command, timeout).await {
+        Ok(output) => Ok(output),
+        Err(ProcessError::Failed(e)) => Err(format!(
+            "Failed to run docker, check it is installed and on the PATH: {}",
+            e
+        )),
+        Err(ProcessError::TimedOut) => Err(format!(
+            "docker {} timed out after {} seconds and was killed",
+            args.first().unwrap_or(&""),
+            timeout.as_secs()
+        )),
+    }
+}
+
+/// The output of a docker command that must succeed, or why it failed
+pub(crate) async fn docker_stdout(args: &[&str]) -> Result<String, String> {
+    let output = docker(args, DOCKER_TIMEOUT).await?;
+    if !output.status.success() {
+        return Err(format!(
+            "docker {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,27 @@
+use crate::workspace::Workspace;
+use tokio::process::Command;
+
+/// Run git in the workspace root, returning its output or why it failed
+pub(crate) async fn git(workspace: &Workspace, args: &[&str]) -> Result<String, String> {
+    let root = workspace.root()?;
+
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(root)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+
+    if !output.status.success() {
+        // Hooks may report why they failed on either stream
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            [stderr.trim(), stdout.trim()].join("\n").trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -1,35 +1,57 @@
+mod backup;
+mod docker;
+mod git;
+mod lsp;
+mod mcp;
 pub mod models;
+mod notebook;
+pub mod policy;
+mod process;
+pub mod registry;
 pub mod tool_functions;
+mod walk;
+pub mod workspace;
 
-use models::ToolError;
 // Re-exports for backwards compatibility
-pub use models::{Tool, ToolContent, ToolResult};
+pub use backup::{Backups, FileChange, FileChangeKind};
+pub use lsp::LanguageServers;
+pub use mcp::{McpServer, McpTransport};
+pub use models::{Tool, ToolContent, ToolResult, TypedTool};
+pub use policy::CommandPolicy;
+pub use registry::ToolRegistry;
+pub use workspace::Workspace;
 
-use serde::Serialize;
 // Tool struct re-exports
+pub use tool_functions::custom::CustomTool;
+pub use tool_functions::delete_file::{DeleteFileInput, DeleteFileTool};
+pub use tool_functions::diagnostics::{DiagnosticsInput, DiagnosticsTool};
+pub use tool_functions::docker_exec::{DockerExecInput, DockerExecTool};
+pub use tool_functions::docker_list::{DockerListInput, DockerListTool};
+pub use tool_functions::docker_logs::{DockerLogsInput, DockerLogsTool};
+pub use tool_functions::edit_file::{EditFileInput, EditFileTool};
+pub use tool_functions::edit_notebook::{EditNotebookInput, EditNotebookTool};
+pub use tool_functions::estimate_tokens::{EstimateTokensInput, EstimateTokensTool};
+pub use tool_functions::find_references::{FindReferencesInput, FindReferencesTool};
+pub use tool_functions::git_branch::{GitBranchInput, GitBranchTool};
+pub use tool_functions::git_commit::{GitCommitInput, GitCommitTool};
+pub use tool_functions::git_diff::{GitDiffInput, GitDiffTool};
+pub use tool_functions::git_log::{GitLogInput, GitLogTool};
+pub use tool_functions::git_status::{GitStatusInput, GitStatusTool};
+pub use tool_functions::goto_definition::{GotoDefinitionInput, GotoDefinitionTool};
 pub use tool_functions::list_files::{ListFilesInput, ListFilesTool};
+pub use tool_functions::mcp::McpTool;
+pub use tool_functions::memory::{MemoryInput, MemoryTool};
+pub use tool_functions::move_file::{MoveFileInput, MoveFileTool};
 pub use tool_functions::read_file::{ReadFileInput, ReadFileTool};
+pub use tool_functions::read_files::{ReadFilesInput, ReadFilesTool};
+pub use tool_functions::read_notebook::{ReadNotebookInput, ReadNotebookTool};
+pub use tool_functions::read_tool_output::{ReadToolOutputInput, ReadToolOutputTool};
+pub use tool_functions::respond::RespondTool;
 pub use tool_functions::run_command::{RunCommandInput, RunCommandTool};
+pub use tool_functions::run_tests::{RunTestsInput, RunTestsTool};
+pub use tool_functions::search::{SearchInput, SearchTool};
+#[cfg(feature = "sql")]
+pub use tool_functions::sql_query::{SqlQueryInput, SqlQueryTool};
 pub use tool_functions::tree::{TreeInput, TreeTool};
+pub use tool_functions::view_image::{ViewImageInput, ViewImageTool};
 pub use tool_functions::write_file::{WriteFileInput, WriteFileTool};
-
-#[derive(Debug, Serialize, Clone)]
-pub enum ToolType {
-    ListFiles(ListFilesTool),
-    ReadFile(ReadFileTool),
-    RunCommand(RunCommandTool),
-    Tree(TreeTool),
-    WriteFile(WriteFileTool),
-}
-
-impl ToolType {
-    pub fn to_json_schema(&self) -> Result<std::string::String, ToolError> {
-        match self {
-            ToolType::ListFiles(tool) => tool.to_json_schema(),
-            ToolType::ReadFile(tool) => tool.to_json_schema(),
-            ToolType::RunCommand(tool) => tool.to_json_schema(),
-            ToolType::Tree(tool) => tool.to_json_schema(),
-            ToolType::WriteFile(tool) => tool.to_json_schema(),
-        }
-    }
-}
@@ -0,0 +1,492 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::{oneshot, Mutex, Notify};
+use tokio::time::Instant;
+use url::Url;
+
+/// How long to wait for the language server to answer a request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Diagnostics are collected until none have arrived for this long
+const DIAGNOSTICS_SETTLE: Duration = Duration::from_secs(3);
+
+/// The longest diagnostics are waited for, slow checks such as `cargo check` may not finish
+const DIAGNOSTICS_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A language server that can be started for a project
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Server {
+    RustAnalyzer,
+    TypeScript,
+}
+
+impl Server {
+    /// The server for a source file, and the file's language ID
+    fn for_file(path: &Path) -> Option<(Server, &'static str)> {
+        match path.extension()?.to_str()? {
+            "rs" => Some((Server::RustAnalyzer, "rust")),
+            "ts" => Some((Server::TypeScript, "typescript")),
+            "tsx" => Some((Server::TypeScript, "typescriptreact")),
+            "js" | "mjs" | "cjs" => Some((Server::TypeScript, "javascript")),
+            "jsx" => Some((Server::TypeScript, "javascriptreact")),
+            _ => None,
+        }
+    }
+
+    fn command(&self) -> Command {
+        match self {
+            Server::RustAnalyzer => Command::new("rust-analyzer"),
+            Server::TypeScript => {
+                let mut command = Command::new("typescript-language-server");
+                command.arg("--stdio");
+                command
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Server::RustAnalyzer => "rust-analyzer",
+            Server::TypeScript => "typescript-language-server",
+        }
+    }
+}
+
+/// Which server, and the project root it was started in
+type ServerKey = (Server, PathBuf);
+
+/// Language servers for the workspace, started the first time a file in their language
+/// is looked at and shared by every tool that holds a clone
+#[derive(Debug, Clone, Default)]
+pub struct LanguageServers {
+    servers: Arc<Mutex<HashMap<ServerKey, Arc<LanguageServer>>>>,
+}
+
+/// A file opened in a language server
+pub(crate) struct Document {
+    pub server: Arc<LanguageServer>,
+    pub uri: Url,
+}
+
+impl LanguageServers {
+    /// Open a file in the language server for its language, starting the server if needed
+    pub(crate) async fn open(&self, root: &Path, path: &Path) -> Result<Document, String> {
+        let (server, language_id) = Server::for_file(path).ok_or_else(|| {
+            "No language server is available for this file, only Rust, TypeScript and JavaScript \
+            files are supported"
+                .to_string()
+        })?;
+
+        let server = {
+            let mut servers = self.servers.lock().await;
+            let key = (server, root.to_path_buf());
+            // A server that has exited is replaced
+            match servers.get(&key) {
+                Some(running) if running.is_alive() => running.clone(),
+                _ => {
+                    let started = Arc::new(LanguageServer::start(server, root).await?);
+                    servers.insert(key, started.clone());
+                    started
+                }
+            }
+        };
+
+        let uri = Url::from_file_path(path)
+            .map_err(|_| format!("Invalid file path '{}'", path.display()))?;
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read file '{}': {}", path.display(), e))?;
+        server.sync_document(&uri, language_id, text).await?;
+
+        Ok(Document { server, uri })
+    }
+}
+
+/// Where the response to a request is sent
+type PendingRequest = oneshot::Sender<Result<Value, String>>;
+
+/// A running language server, talking JSON-RPC over its stdin and stdout
+pub(crate) struct LanguageServer {
+    name: &'static str,
+    child: StdMutex<Child>,
+    stdin: Arc<Mutex<ChildStdin>>,
+    next_id: AtomicU64,
+    pending: Arc<StdMutex<HashMap<u64, PendingRequest>>>,
+    diagnostics: Arc<StdMutex<Diagnostics>>,
+    diagnostics_changed: Arc<Notify>,
+    /// The version of each open document
+    documents: Mutex<HashMap<Url, i32>>,
+}
+
+/// The latest diagnostics published for each document
+#[derive(Default)]
+struct Diagnostics {
+    /// Counts every publish, so a wait can tell which arrived after it started
+    generation: u64,
+    by_uri: HashMap<Url, (u64, Vec<Value>)>,
+}
+
+impl LanguageServer {
+    async fn start(server: Server, root: &Path) -> Result<Self, String> {
+        let mut child = server
+            .command()
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                format!(
+                    "Failed to start {}, is it installed and on the PATH? {}",
+                    server.name(),
+                    e
+                )
+            })?;
+
+        let stdin = Arc::new(Mutex::new(child.stdin.take().ok_or("Missing stdin")?));
+        let stdout = child.stdout.take().ok_or("Missing stdout")?;
+
+        let language_server = LanguageServer {
+            name: server.name(),
+            child: StdMutex::new(child),
+            stdin,
+            next_id: AtomicU64::new(1),
+            pending: Arc::default(),
+            diagnostics: Arc::default(),
+            diagnostics_changed: Arc::default(),
+            documents: Mutex::default(),
+        };
+        language_server.spawn_reader(stdout);
+
+        let root_uri = Url::from_directory_path(root)
+            .map_err(|_| format!("Invalid workspace path '{}'", root.display()))?;
+        language_server
+            .request(
+                "initialize",
+                json!({
+                    "processId": std::process::id(),
+                    "rootUri": root_uri,
+                    "workspaceFolders": [{ "uri": root_uri, "name": "workspace" }],
+                    "capabilities": {
+                        "textDocument": {
+                            "synchronization": { "didSave": true },
+                            "publishDiagnostics": {},
+                            "definition": { "linkSupport": true },
+                            "references": {}
+                        }
+                    }
+                }),
+            )
+            .await
+            .map_err(|e| {
+                format!(
+                    "{}. Check {} is installed and works in '{}'",
+                    e,
+                    server.name(),
+                    root.display()
+                )
+            })?;
+        language_server.notify("initialized", json!({})).await?;
+
+        Ok(language_server)
+    }
+
+    fn is_alive(&self) -> bool {
+        self.child
+            .lock()
+            .map(|mut child| matches!(child.try_wait(), Ok(None)))
+            .unwrap_or(false)
+    }
+
+    /// Read messages from the server, resolving responses and recording diagnostics
+    fn spawn_reader(&self, stdout: ChildStdout) {
+        let stdin = self.stdin.clone();
+        let pending = self.pending.clone();
+        let diagnostics = self.diagnostics.clone();
+        let diagnostics_changed = self.diagnostics_changed.clone();
+
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stdout);
+            while let Some(message) = read_message(&mut reader).await {
+                let method = message.get("method").and_then(Value::as_str);
+                let id = message.get("id").cloned();
+
+                match (method, id) {
+                    // A response to one of our requests
+                    (None, Some(id)) => {
+                        let sender = id.as_u64().and_then(|id| pending.lock().ok()?.remove(&id));
+                        if let Some(sender) = sender {
+                            let result = match message.get("error") {
+                                Some(error) => Err(error
+                                    .get("message")
+                                    .and_then(Value::as_str)
+                                    .unwrap_or("Unknown error")
+                                    .to_string()),
+                                None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+                            };
+                            let _ = sender.send(result);
+                        }
+                    }
+                    // A request from the server, which must be answered even though
+                    // there's nothing to configure
+                    (Some(method), Some(id)) => {
+                        let result = match method {
+                            "workspace/configuration" => {
+                                let items =
+                                    message["params"]["items"].as_array().map_or(0, Vec::len);
+                                Value::Array(vec![Value::Null; items])
+                            }
+                            _ => Value::Null,
+                        };
+                        let response = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+                        let _ = write_message(&mut *stdin.lock().await, &response).await;
+                    }
+                    (Some("textDocument/publishDiagnostics"), None) => {
+                        let params = &message["params"];
+                        let uri = params["uri"].as_str().and_then(|uri| Url::parse(uri).ok());
+                        if let (Some(uri), Ok(mut diagnostics)) = (uri, diagnostics.lock()) {
+                            diagnostics.generation += 1;
+                            let generation = diagnostics.generation;
+                            let published = params["diagnostics"]
+                                .as_array()
+                                .cloned()
+                                .unwrap_or_default();
+                            diagnostics.by_uri.insert(uri, (generation, published));
+                        }
+                        diagnostics_changed.notify_waiters();
+                    }
+                    _ => {}
+                }
+            }
+
+            // The server exited, fail anything still waiting on it
+            if let Ok(mut pending) = pending.lock() {
+                for (_, sender) in pending.drain() {
+                    let _ = sender.send(Err("The language server exited".to_string()));
+                }
+            }
+        });
+    }
+
+    /// Send a request and wait for its result
+    pub(crate) async fn request(&self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = oneshot::channel();
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(id, sender);
+        }
+
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        write_message(&mut *self.stdin.lock().await, &message).await?;
+
+        match tokio::time::timeout(REQUEST_TIMEOUT, receiver).await {
+            Ok(Ok(result)) => {
+                result.map_err(|e| format!("{} failed to answer {}: {}", self.name, method, e))
+            }
+            Ok(Err(_)) => Err(format!("{} exited", self.name)),
+            Err(_) => {
+                if let Ok(mut pending) = self.pending.lock() {
+                    pending.remove(&id);
+                }
+                Err(format!(
+                    "{} didn't answer {} within {} seconds, it may still be indexing the project",
+                    self.name,
+                    method,
+                    REQUEST_TIMEOUT.as_secs()
+                ))
+            }
+        }
+    }
+
+    /// Send a notification, which has no response
+    async fn notify(&self, method: &str, params: Value) -> Result<(), String> {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        write_message(&mut *self.stdin.lock().await, &message).await
+    }
+
+    /// Tell the server about the current contents of a document, opening it if needed
+    async fn sync_document(
+        &self,
+        uri: &Url,
+        language_id: &str,
+        text: String,
+    ) -> Result<(), String> {
+        let mut documents = self.documents.lock().await;
+        match documents.get_mut(uri) {
+            Some(version) => {
+                *version += 1;
+                self.notify(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": { "uri": uri, "version": version },
+                        "contentChanges": [{ "text": text }]
+                    }),
+                )
+                .await?;
+            }
+            None => {
+                documents.insert(uri.clone(), 1);
+                self.notify(
+                    "textDocument/didOpen",
+                    json!({
+                        "textDocument": {
+                            "uri": uri,
+                            "languageId": language_id,
+                            "version": 1,
+                            "text": text
+                        }
+                    }),
+                )
+                .await?;
+            }
+        }
+
+        // Saving is what triggers checks like `cargo check`
+        self.notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await
+    }
+
+    /// Wait for the diagnostics of a document to be published, then for them to settle
+    pub(crate) async fn diagnostics(&self, uri: &Url) -> Vec<Value> {
+        let started = self.diagnostics_generation();
+        let deadline = Instant::now() + DIAGNOSTICS_TIMEOUT;
+        let mut latest: Option<(u64, Vec<Value>)> = None;
+
+        loop {
+            let changed = self.diagnostics_changed.notified();
+
+            let published = self
+                .diagnostics
+                .lock()
+                .ok()
+                .and_then(|diagnostics| diagnostics.by_uri.get(uri).cloned())
+                .filter(|(generation, _)| *generation > started);
+            let updated = match (&published, &latest) {
+                (Some((generation, _)), Some((seen, _))) => generation > seen,
+                (Some(_), None) => true,
+                _ => false,
+            };
+            if updated {
+                latest = published;
+            }
+
+            // Before anything is published wait until the deadline, after that only
+            // until the diagnostics stop changing
+            let wait_until = match latest {
+                Some(_) => (Instant::now() + DIAGNOSTICS_SETTLE).min(deadline),
+                None => deadline,
+            };
+            if tokio::time::timeout_at(wait_until, changed).await.is_err() {
+                break;
+            }
+        }
+
+        match latest {
+            Some((_, diagnostics)) => diagnostics,
+            // Nothing newer was published, the last known diagnostics are still current
+            None => self
+                .diagnostics
+                .lock()
+                .ok()
+                .and_then(|diagnostics| diagnostics.by_uri.get(uri).cloned())
+                .map(|(_, diagnostics)| diagnostics)
+                .unwrap_or_default(),
+        }
+    }
+
+    fn diagnostics_generation(&self) -> u64 {
+        self.diagnostics
+            .lock()
+            .map(|diagnostics| diagnostics.generation)
+            .unwrap_or_default()
+    }
+}
+
+impl std::fmt::Debug for LanguageServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LanguageServer")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Read one `Content-Length` framed message, or None once the server has exited
+async fn read_message(reader: &mut BufReader<ChildStdout>) -> Option<Value> {
+    loop {
+        let mut content_length = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).await.ok()? == 0 {
+                return None;
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(length) = header.strip_prefix("Content-Length:") {
+                content_length = length.trim().parse::<usize>().ok();
+            }
+        }
+
+        let Some(length) = content_length else {
+            continue;
+        };
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await.ok()?;
+
+        // Skip anything that isn't valid JSON rather than giving up on the server
+        if let Ok(message) = serde_json::from_slice(&body) {
+            return Some(message);
+        }
+    }
+}
+
+async fn write_message(stdin: &mut ChildStdin, message: &Value) -> Result<(), String> {
+    let body = message.to_string();
+    let framed = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+    stdin
+        .write_all(framed.as_bytes())
+        .await
+        .map_err(|e| format!("Failed to write to the language server: {}", e))?;
+    stdin
+        .flush()
+        .await
+        .map_err(|e| format!("Failed to write to the language server: {}", e))
+}
+
+/// Format an LSP location, which is zero-based, as `path:line:column`
+pub(crate) fn format_location(root: &Path, uri: &str, range: &Value) -> Option<String> {
+    let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+    let path = path.strip_prefix(root).unwrap_or(&path);
+    let line = range["start"]["line"].as_u64()? + 1;
+    let column = range["start"]["character"].as_u64()? + 1;
+    Some(format!("{}:{}:{}", path.display(), line, column))
+}
+
+/// Collect the locations from a definition or references response, which may be a single
+/// location, a list of locations, or a list of location links
+pub(crate) fn locations(root: &Path, result: &Value) -> Vec<String> {
+    let items = match result {
+        Value::Array(items) => items.clone(),
+        Value::Null => Vec::new(),
+        item => vec![item.clone()],
+    };
+
+    items
+        .iter()
+        .filter_map(|item| match item.get("targetUri") {
+            Some(uri) => format_location(root, uri.as_str()?, &item["targetSelectionRange"]),
+            None => format_location(root, item["uri"].as_str()?, &item["range"]),
+        })
+        .collect()
+}
@@ -0,0 +1,351 @@
+use crate::models::{ToolError, ToolName};
+use crate::tool_functions::mcp::McpTool;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{oneshot, Mutex};
+
+/// The protocol revision the client speaks
+const PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// How long to wait for a server to answer a request, tool calls may do real work
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How to reach an MCP server
+#[derive(Debug, Clone)]
+pub enum McpTransport {
+    /// Start the server as a child process and talk to it over stdin and stdout
+    Stdio {
+        command: String,
+        args: Vec<String>,
+        env: HashMap<String, String>,
+    },
+    /// Send requests to the server's streamable HTTP endpoint
+    Http {
+        url: String,
+        headers: HashMap<String, String>,
+    },
+}
+
+/// An MCP server listed in the config, whose tools are offered to the model
+#[derive(Debug, Clone)]
+pub struct McpServer {
+    /// Prefixes the server's tool names, so two servers can have tools with the same name
+    pub name: String,
+    pub transport: McpTransport,
+}
+
+impl McpServer {
+    /// Connect to the server and list its tools
+    pub async fn connect(&self) -> Result<Vec<McpTool>, ToolError> {
+        let fail = |reason: String| ToolError::McpServer(self.name.clone(), reason);
+
+        let connection = match &self.transport {
+            McpTransport::Stdio { command, args, env } => {
+                Connection::Stdio(StdioConnection::start(command, args, env).map_err(fail)?)
+            }
+            McpTransport::Http { url, headers } => {
+                Connection::Http(HttpConnection::new(url, headers).map_err(fail)?)
+            }
+        };
+        let client = Arc::new(McpClient {
+            connection,
+            next_id: AtomicU64::new(1),
+        });
+
+        client
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": { "name": "aria", "version": env!("CARGO_PKG_VERSION") }
+                }),
+            )
+            .await
+            .map_err(fail)?;
+        client
+            .notify("notifications/initialized", json!({}))
+            .await
+            .map_err(fail)?;
+
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = client.request("tools/list", params).await.map_err(fail)?;
+
+            for tool in result["tools"].as_array().into_iter().flatten() {
+                let Some(remote_name) = tool["name"].as_str() else {
+                    continue;
+                };
+                let name = format!("{}__{}", self.name, remote_name);
+                let Ok(name) = ToolName::try_from(name.clone()) else {
+                    return Err(fail(format!(
+                        "its tool '{}' can't be offered as '{}', names must be 1 to 64 letters, \
+                        numbers, '_' or '-'",
+                        remote_name, name
+                    )));
+                };
+
+                tools.push(McpTool {
+                    name,
+                    remote_name: remote_name.to_string(),
+                    description: tool["description"].as_str().unwrap_or_default().to_string(),
+                    input_schema: tool["inputSchema"].clone(),
+                    client: client.clone(),
+                });
+            }
+
+            cursor = result["nextCursor"].as_str().map(str::to_string);
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        Ok(tools)
+    }
+}
+
+/// A connection to an MCP server that has been initialized
+pub(crate) struct McpClient {
+    connection: Connection,
+    next_id: AtomicU64,
+}
+
+enum Connection {
+    Stdio(StdioConnection),
+    Http(HttpConnection),
+}
+
+impl McpClient {
+    /// Send a request and wait for its result
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+
+        let response = match &self.connection {
+            Connection::Stdio(connection) => connection.request(id, &message).await?,
+            Connection::Http(connection) => connection.request(id, &message).await?,
+        };
+
+        match response.get("error") {
+            Some(error) => Err(format!(
+                "{} failed: {}",
+                method,
+                error["message"].as_str().unwrap_or("Unknown error")
+            )),
+            None => Ok(response.get("result").cloned().unwrap_or(Value::Null)),
+        }
+    }
+
+    /// Send a notification, which has no response
+    async fn notify(&self, method: &str, params: Value) -> Result<(), String> {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        match &self.connection {
+            Connection::Stdio(connection) => connection.send(&message).await,
+            Connection::Http(connection) => connection.send(&message).await.map(|_| ()),
+        }
+    }
+}
+
+impl std::fmt::Debug for McpClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("McpClient").finish_non_exhaustive()
+    }
+}
+
+/// Where the response to a request is sent
+type PendingRequest = oneshot::Sender<Value>;
+
+/// A server running as a child process, exchanging one JSON-RPC message per line
+struct StdioConnection {
+    /// Kept so the server is killed when the connection is dropped
+    _child: Child,
+    stdin: Mutex<ChildStdin>,
+    pending: Arc<StdMutex<HashMap<u64, PendingRequest>>>,
+}
+
+impl StdioConnection {
+    fn start(
+        command: &str,
+        args: &[String],
+        env: &HashMap<String, String>,
+    ) -> Result<Self, String> {
+        let mut child = Command::new(command)
+            .args(args)
+            .envs(env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to start '{}': {}", command, e))?;
+
+        let stdin = child.stdin.take().ok_or("Missing stdin")?;
+        let stdout = child.stdout.take().ok_or("Missing stdout")?;
+        let pending: Arc<StdMutex<HashMap<u64, PendingRequest>>> = Arc::default();
+
+        let responses = pending.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                // Anything that isn't a response to one of our requests is ignored
+                let Ok(message) = serde_json::from_str::<Value>(&line) else {
+                    continue;
+                };
+                if message.get("method").is_some() {
+                    continue;
+                }
+                let sender = message["id"]
+                    .as_u64()
+                    .and_then(|id| responses.lock().ok()?.remove(&id));
+                if let Some(sender) = sender {
+                    let _ = sender.send(message);
+                }
+            }
+
+            // The server exited, which drops the senders and fails anything still waiting
+            if let Ok(mut responses) = responses.lock() {
+                responses.clear();
+            }
+        });
+
+        Ok(StdioConnection {
+            _child: child,
+            stdin: Mutex::new(stdin),
+            pending,
+        })
+    }
+
+    async fn request(&self, id: u64, message: &Value) -> Result<Value, String> {
+        let (sender, receiver) = oneshot::channel();
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(id, sender);
+        }
+        self.send(message).await?;
+
+        match tokio::time::timeout(REQUEST_TIMEOUT, receiver).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err("The server exited".to_string()),
+            Err(_) => {
+                if let Ok(mut pending) = self.pending.lock() {
+                    pending.remove(&id);
+                }
+                Err(format!(
+                    "The server didn't answer within {} seconds",
+                    REQUEST_TIMEOUT.as_secs()
+                ))
+            }
+        }
+    }
+
+    async fn send(&self, message: &Value) -> Result<(), String> {
+        let mut stdin = self.stdin.lock().await;
+        let line = format!("{}\n", message);
+        stdin
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|e| format!("Failed to write to the server: {}", e))?;
+        stdin
+            .flush()
+            .await
+            .map_err(|e| format!("Failed to write to the server: {}", e))
+    }
+}
+
+/// A server reached over HTTP, which answers each request with JSON or an event stream
+struct HttpConnection {
+    client: reqwest::Client,
+    url: String,
+    /// Assigned by the server when it initializes, and sent back with every later request
+    session_id: StdMutex<Option<String>>,
+}
+
+impl HttpConnection {
+    fn new(url: &str, headers: &HashMap<String, String>) -> Result<Self, String> {
+        let mut header_map = reqwest::header::HeaderMap::new();
+        for (name, value) in headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| format!("Invalid header name '{}': {}", name, e))?;
+            let value = reqwest::header::HeaderValue::from_str(value)
+                .map_err(|e| format!("Invalid value for header '{}': {}", name, e))?;
+            header_map.insert(name, value);
+        }
+
+        let client = reqwest::Client::builder()
+            .default_headers(header_map)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+        Ok(HttpConnection {
+            client,
+            url: url.to_string(),
+            session_id: StdMutex::new(None),
+        })
+    }
+
+    async fn request(&self, id: u64, message: &Value) -> Result<Value, String> {
+        let body = self.send(message).await?;
+
+        // An event stream may carry server requests and notifications before the response
+        let is_response = |message: &Value| message["id"].as_u64() == Some(id);
+        if let Ok(response) = serde_json::from_str::<Value>(&body) {
+            if is_response(&response) {
+                return Ok(response);
+            }
+        }
+        body.lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+            .find(is_response)
+            .ok_or_else(|| "The server's reply didn't contain a response".to_string())
+    }
+
+    /// Post a message, returning the body of the reply
+    async fn send(&self, message: &Value) -> Result<String, String> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Accept", "application/json, text/event-stream")
+            .json(message);
+        let session_id = self.session_id.lock().ok().and_then(|id| id.clone());
+        if let Some(session_id) = session_id {
+            request = request.header("Mcp-Session-Id", session_id);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Request to {} failed: {}", self.url, e))?;
+        if let Some(session_id) = response
+            .headers()
+            .get("Mcp-Session-Id")
+            .and_then(|id| id.to_str().ok())
+        {
+            if let Ok(mut id) = self.session_id.lock() {
+                *id = Some(session_id.to_string());
+            }
+        }
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read the reply from {}: {}", self.url, e))?;
+        if !status.is_success() {
+            return Err(format!("{} replied {}: {}", self.url, status, body.trim()));
+        }
+
+        Ok(body)
+    }
+}
@@ -1,12 +1,20 @@
 use async_trait::async_trait;
 use schemars::{schema_for, JsonSchema};
-use serde::{de::Error as SerdeError, Deserialize, Serialize}; // Add this import to use the custom() method
+use serde::{de::DeserializeOwned, de::Error as SerdeError, Deserialize, Serialize}; // Add this import to use the custom() method
 
 #[derive(Debug)]
 pub enum ToolError {
     InputSchemaSerializationError(serde_json::Error),
     JsonSchemaSerializationError(serde_json::Error),
     InvalidToolName(String),
+    /// A command policy pattern that isn't a valid regular expression
+    InvalidCommandPattern(String, String),
+    /// A custom tool that can't be used, and why
+    InvalidCustomTool(String, String),
+    /// An MCP server whose tools couldn't be listed, and why
+    McpServer(String, String),
+    /// A database declared for the SQL tools that can't be used, and why
+    InvalidSqlDatabase(String, String),
 }
 
 impl std::fmt::Display for ToolError {
@@ -21,32 +29,32 @@ impl std::fmt::Display for ToolError {
             Self::InvalidToolName(name) => {
                 write!(f, "Invalid tool name: {}", name)
             }
+            Self::InvalidCommandPattern(pattern, e) => {
+                write!(f, "Invalid command pattern '{}': {}", pattern, e)
+            }
+            Self::InvalidCustomTool(name, reason) => {
+                write!(f, "Invalid custom tool '{}': {}", name, reason)
+            }
+            Self::McpServer(name, reason) => {
+                write!(f, "MCP server '{}': {}", name, reason)
+            }
+            Self::InvalidSqlDatabase(name, reason) => {
+                write!(f, "Invalid SQL database '{}': {}", name, reason)
+            }
         }
     }
 }
 
 impl std::error::Error for ToolError {}
 
-/// Enum representing all available tool names
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub enum ToolName {
-    ReadFile,
-    WriteFile,
-    ListFiles,
-    Tree,
-    RunCommand,
-}
+/// The name a tool is offered to the model under, and that the model calls it by
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ToolName(String);
 
 impl ToolName {
-    /// Convert the enum variant to its string representation
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Self::ReadFile => "read_file",
-            Self::WriteFile => "write_file",
-            Self::ListFiles => "list_files",
-            Self::Tree => "tree",
-            Self::RunCommand => "run_command",
-        }
+    /// The name as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
     }
 }
 
@@ -56,17 +64,27 @@ impl std::fmt::Display for ToolName {
     }
 }
 
+/// Names of built-in tools, which are known to be valid
+impl From<&'static str> for ToolName {
+    fn from(name: &'static str) -> Self {
+        ToolName(name.to_string())
+    }
+}
+
 impl TryFrom<String> for ToolName {
     type Error = ToolError;
 
+    /// Accepts the names providers allow for tools: 1 to 64 letters, numbers, `_` or `-`
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        match value.as_str() {
-            "read_file" => Ok(Self::ReadFile),
-            "write_file" => Ok(Self::WriteFile),
-            "list_files" => Ok(Self::ListFiles),
-            "tree" => Ok(Self::Tree),
-            "run_command" => Ok(Self::RunCommand),
-            _ => Err(ToolError::InvalidToolName(value)),
+        let is_valid = (1..=64).contains(&value.len())
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+        if is_valid {
+            Ok(ToolName(value))
+        } else {
+            Err(ToolError::InvalidToolName(value))
         }
     }
 }
@@ -74,15 +92,29 @@ impl TryFrom<String> for ToolName {
 // Add the From implementation for converting ToolName to String
 impl From<ToolName> for String {
     fn from(tool_name: ToolName) -> Self {
-        tool_name.as_str().to_string()
+        tool_name.0
     }
 }
 
-/// Represents either a single string or an array of strings
-#[derive(Debug)]
+/// The output of a tool, text or machine-readable data
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
 pub enum ToolContent {
     String(String),
     StringArray(Vec<String>),
+    /// Structured data, passed to the model as compact JSON
+    Json(serde_json::Value),
+    /// A change to a file, described by a summary and a unified diff
+    Diff {
+        summary: String,
+        diff: String,
+    },
+    /// An image for the model to look at
+    Image {
+        media_type: String,
+        /// The image file, base64 encoded
+        data: String,
+    },
 }
 
 impl std::fmt::Display for ToolContent {
@@ -101,35 +133,168 @@ impl std::fmt::Display for ToolContent {
                 }
                 write!(f, "]")
             }
+            ToolContent::Json(value) => write!(f, "{}", value),
+            ToolContent::Diff { summary, diff } => write!(f, "{}\n\n{}", summary, diff),
+            ToolContent::Image { media_type, .. } => write!(f, "[Image: {}]", media_type),
         }
     }
 }
 
+/// What a tool spent on model requests of its own, e.g. a subagent's, counted towards the run
+/// that called it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ModelSpend {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub cache_creation_input_tokens: u32,
+    pub cache_read_input_tokens: u32,
+    /// What the requests cost in US dollars, zero if the provider's prices aren't known
+    pub cost_usd: f64,
+}
+
 /// A struct to represent the result of tool operations
 #[derive(Debug)]
 pub struct ToolResult {
     pub is_error: bool,
     pub content: ToolContent,
+    /// The exit code of the process the tool ran, if it ran one to the end
+    pub exit_code: Option<i32>,
+    /// What the tool spent on model requests, if it made any
+    pub model_spend: Option<ModelSpend>,
+}
+
+impl ToolResult {
+    /// A call that succeeded, with what it returned
+    pub fn success(content: ToolContent) -> Self {
+        ToolResult {
+            is_error: false,
+            content,
+            exit_code: None,
+            model_spend: None,
+        }
+    }
+
+    /// A failed call, with a message telling the model what went wrong
+    pub fn error(message: impl Into<String>) -> Self {
+        ToolResult {
+            is_error: true,
+            content: ToolContent::String(message.into()),
+            exit_code: None,
+            model_spend: None,
+        }
+    }
+
+    /// The same result, with the exit code of the process the tool ran
+    pub fn with_exit_code(self, exit_code: Option<i32>) -> Self {
+        ToolResult { exit_code, ..self }
+    }
+
+    /// The same result, with what the tool spent on model requests
+    pub fn with_model_spend(self, model_spend: ModelSpend) -> Self {
+        ToolResult {
+            model_spend: Some(model_spend),
+            ..self
+        }
+    }
 }
 
 /// Trait defining the interface for all tools
+///
+/// The input is the JSON the model gave, so tools of every kind can be kept together as
+/// `dyn Tool`. Tools with a fixed input type implement [`TypedTool`] instead, which parses
+/// the input and generates the schema from the type.
 #[async_trait]
-pub trait Tool<T: JsonSchema> {
-    /// Executes the tool with the provided input
-    async fn run(&self, input: T) -> ToolResult;
+pub trait Tool: std::fmt::Debug + Send + Sync {
+    /// Executes the tool with the input the model gave
+    async fn call(&self, input: serde_json::Value) -> ToolResult;
 
     /// Returns the title/name of the tool
     fn title(&self) -> ToolName;
 
+    /// Whether the tool changes files or runs commands, rather than only reading
+    fn is_mutating(&self) -> bool {
+        false
+    }
+
     /// Returns a description of the tool's usage, best practices, and limitations
-    fn description(&self) -> &'static str;
+    fn description(&self) -> &str;
+
+    /// Returns the OpenAPI schema for the input
+    fn input_schema(&self) -> Result<String, ToolError>;
+
+    /// Checks the model's input against the schema without running the tool, saying what's
+    /// wrong with it if it doesn't match
+    fn validate_input(&self, _input: &serde_json::Value) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Returns a JSON representation of the tool's metadata and schema
+    fn to_json_schema(&self) -> Result<String, ToolError> {
+        serde_json::to_string(&serde_json::json!({
+            "name": self.title().as_str(),
+            "description": self.description(),
+            "input_schema": serde_json::from_str::<serde_json::Value>(&self.input_schema()?).unwrap()
+        }))
+        .map_err(ToolError::JsonSchemaSerializationError)
+    }
+}
+
+/// A tool whose input is parsed into a type, which its schema is generated from
+#[async_trait]
+pub trait TypedTool: std::fmt::Debug + Send + Sync {
+    type Input: JsonSchema + DeserializeOwned + Send;
+
+    /// Executes the tool with the parsed input
+    async fn run(&self, input: Self::Input) -> ToolResult;
+
+    /// Returns the title/name of the tool
+    fn title(&self) -> ToolName;
+
+    /// Whether the tool changes files or runs commands, rather than only reading
+    fn is_mutating(&self) -> bool {
+        false
+    }
+
+    /// Returns a description of the tool's usage, best practices, and limitations
+    fn description(&self) -> &str;
+}
+
+#[async_trait]
+impl<T: TypedTool> Tool for T {
+    async fn call(&self, input: serde_json::Value) -> ToolResult {
+        match serde_json::from_value(input) {
+            Ok(input) => self.run(input).await,
+            Err(e) => ToolResult::error(format!(
+                "Invalid input for {}: {}",
+                TypedTool::title(self),
+                e
+            )),
+        }
+    }
+
+    fn title(&self) -> ToolName {
+        TypedTool::title(self)
+    }
+
+    fn is_mutating(&self) -> bool {
+        TypedTool::is_mutating(self)
+    }
+
+    fn description(&self) -> &str {
+        TypedTool::description(self)
+    }
+
+    fn validate_input(&self, input: &serde_json::Value) -> Result<(), String> {
+        serde_json::from_value::<T::Input>(input.clone())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
 
-    /// Returns the OpenAPI schema for the input type
     fn input_schema(&self) -> Result<String, ToolError> {
         // Generate the schema using schemars
-        let schema = schema_for!(T);
-        let schema_json = serde_json::to_value(&schema)
-            .map_err(|e| ToolError::InputSchemaSerializationError(e))?;
+        let schema = schema_for!(T::Input);
+        let schema_json =
+            serde_json::to_value(&schema).map_err(ToolError::InputSchemaSerializationError)?;
 
         // Extract only the required fields from the schema
         let obj = schema_json.as_object().ok_or_else(|| {
@@ -143,21 +308,10 @@ pub trait Tool<T: JsonSchema> {
             "properties": obj.get("properties").ok_or_else(|| ToolError::InputSchemaSerializationError(
             SerdeError::custom("Missing properties field")
             ))?,
-            "required": obj.get("required").ok_or_else(|| ToolError::InputSchemaSerializationError(
-            SerdeError::custom("Missing required field")
-            ))?
+            // Inputs whose fields are all optional have no required list
+            "required": obj.get("required").cloned().unwrap_or_else(|| serde_json::json!([]))
         });
 
-        serde_json::to_string(&filtered).map_err(|e| ToolError::InputSchemaSerializationError(e))
-    }
-
-    /// Returns a JSON representation of the tool's metadata and schema
-    fn to_json_schema(&self) -> Result<String, ToolError> {
-        serde_json::to_string(&serde_json::json!({
-            "name": self.title().as_str(),
-            "description": self.description(),
-            "input_schema": serde_json::from_str::<serde_json::Value>(&self.input_schema()?).unwrap()
-        }))
-        .map_err(|e| ToolError::JsonSchemaSerializationError(e))
+        serde_json::to_string(&filtered).map_err(ToolError::InputSchemaSerializationError)
     }
 }
@@ -0,0 +1,106 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+
+/// A Jupyter notebook, kept as JSON so metadata and outputs the tools don't understand
+/// are written back untouched
+pub(crate) struct Notebook {
+    json: Map<String, Value>,
+}
+
+impl Notebook {
+    /// Read a notebook, `display_path` being how the path is shown in errors
+    pub async fn load(path: &Path, display_path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .await
+            .map_err(|e| format!("Failed to read notebook '{}': {}", display_path, e))?;
+        let json: Map<String, Value> = serde_json::from_str(&contents)
+            .map_err(|e| format!("'{}' is not a valid notebook: {}", display_path, e))?;
+
+        if !json.get("cells").is_some_and(Value::is_array) {
+            return Err(format!(
+                "'{}' is not a valid notebook: it has no cells",
+                display_path
+            ));
+        }
+        if json.get("nbformat").and_then(Value::as_u64).unwrap_or(0) < 4 {
+            return Err(format!(
+                "'{}' uses a notebook format older than version 4, which isn't supported",
+                display_path
+            ));
+        }
+
+        Ok(Notebook { json })
+    }
+
+    /// Write the notebook the way Jupyter does, with one space indents
+    pub async fn save(&self, path: &Path, display_path: &str) -> Result<(), String> {
+        let mut contents = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(b" ");
+        let mut serializer = serde_json::Serializer::with_formatter(&mut contents, formatter);
+        self.json
+            .serialize(&mut serializer)
+            .map_err(|e| format!("Failed to serialize notebook '{}': {}", display_path, e))?;
+        contents.push(b'\n');
+
+        fs::write(path, contents)
+            .await
+            .map_err(|e| format!("Failed to write notebook '{}': {}", display_path, e))
+    }
+
+    pub fn cells(&self) -> &[Value] {
+        self.json["cells"].as_array().map_or(&[], Vec::as_slice)
+    }
+
+    pub fn cells_mut(&mut self) -> &mut Vec<Value> {
+        self.json
+            .entry("cells")
+            .or_insert_with(|| Value::Array(Vec::new()))
+            .as_array_mut()
+            .expect("cells were checked to be an array when loaded")
+    }
+
+    /// Create a cell, with an ID if the notebook's format requires them
+    pub fn new_cell(&self, cell_type: &str, source: &str) -> Value {
+        let mut cell = Map::new();
+        cell.insert("cell_type".to_string(), Value::from(cell_type));
+        if self.json.get("nbformat_minor").and_then(Value::as_u64) >= Some(5) {
+            cell.insert("id".to_string(), Value::from(self.new_cell_id()));
+        }
+        cell.insert("metadata".to_string(), Value::Object(Map::new()));
+        cell.insert("source".to_string(), source_lines(source));
+        if cell_type == "code" {
+            cell.insert("execution_count".to_string(), Value::Null);
+            cell.insert("outputs".to_string(), Value::Array(Vec::new()));
+        }
+        Value::Object(cell)
+    }
+
+    /// An ID that no other cell in the notebook has
+    fn new_cell_id(&self) -> String {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_nanos())
+            .unwrap_or_default();
+        (0u128..)
+            .map(|offset| format!("{:08x}", (seed + offset) as u32))
+            .find(|id| !self.cells().iter().any(|cell| cell["id"] == *id.as_str()))
+            .unwrap_or_default()
+    }
+}
+
+/// A cell's source, which notebooks store either as one string or as a list of lines
+pub(crate) fn source_text(source: &Value) -> String {
+    match source {
+        Value::String(text) => text.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+/// Split source into lines the way Jupyter stores it, each keeping its newline
+pub(crate) fn source_lines(source: &str) -> Value {
+    Value::Array(source.split_inclusive('\n').map(Value::from).collect())
+}
@@ -0,0 +1,166 @@
+use crate::models::ToolError;
+use regex::Regex;
+
+/// What separates the commands of a shell command line
+const SHELL_OPERATORS: [&str; 6] = ["&&", "||", ";", "|", "&", "\n"];
+
+/// What runs a command inside another, whose command line the policy can't see
+const SHELL_SUBSTITUTIONS: [&str; 4] = ["`", "$(", "<(", ">("];
+
+/// Rules deciding which commands run_command may execute
+///
+/// Patterns are regular expressions matched against the full command line, the command and
+/// its arguments joined by spaces. A command matching any deny pattern is refused. If there are
+/// allow patterns, a command must also match one of them.
+#[derive(Debug, Clone, Default)]
+pub struct CommandPolicy {
+    allow: Vec<Regex>,
+    deny: Vec<Regex>,
+}
+
+impl CommandPolicy {
+    /// Create a policy from allow and deny patterns
+    pub fn new(allow: &[String], deny: &[String]) -> Result<Self, ToolError> {
+        Ok(CommandPolicy {
+            allow: compile(allow)?,
+            deny: compile(deny)?,
+        })
+    }
+
+    /// Check a command line against the policy, explaining why it's refused if it is
+    pub fn check(&self, command_line: &str) -> Result<(), String> {
+        if let Some(pattern) = self.deny.iter().find(|deny| deny.is_match(command_line)) {
+            return Err(format!(
+                "Command blocked by policy: '{}' matches the deny pattern '{}'. Find another way \
+                to do this, or ask the user to run it themselves",
+                command_line, pattern
+            ));
+        }
+
+        if !self.allow.is_empty() && !self.allow.iter().any(|allow| allow.is_match(command_line)) {
+            return Err(format!(
+                "Command blocked by policy: '{}' doesn't match any allowed pattern. Use one of the \
+                allowed commands, or ask the user to run it themselves",
+                command_line
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check a command line that runs through the shell, which must pass the policy as a whole
+    /// and each command in it on its own, e.g. both `git status` and `rm -rf ~` in
+    /// `git status; rm -rf ~`
+    ///
+    /// With allow patterns, command lines with substitutions like `$(...)` are refused, as
+    /// what they run can't be checked.
+    pub fn check_shell(&self, command_line: &str) -> Result<(), String> {
+        self.check(command_line)?;
+
+        if !self.allow.is_empty()
+            && SHELL_SUBSTITUTIONS
+                .iter()
+                .any(|substitution| command_line.contains(substitution))
+        {
+            return Err(format!(
+                "Command blocked by policy: '{}' runs commands through substitutions, which \
+                can't be checked against the allowed patterns. Run each command on its own, or \
+                ask the user to run it themselves",
+                command_line
+            ));
+        }
+
+        let mut commands = vec![command_line.to_string()];
+        for operator in SHELL_OPERATORS {
+            commands = commands
+                .iter()
+                .flat_map(|command| command.split(operator))
+                .map(str::to_string)
+                .collect();
+        }
+        commands
+            .iter()
+            .map(|command| command.trim())
+            .filter(|command| !command.is_empty())
+            .try_for_each(|command| self.check(command))
+    }
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Regex>, ToolError> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern)
+                .map_err(|e| ToolError::InvalidCommandPattern(pattern.clone(), e.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> CommandPolicy {
+        let patterns =
+            |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        CommandPolicy::new(&patterns(allow), &patterns(deny)).unwrap()
+    }
+
+    #[test]
+    fn refuses_commands_matching_a_deny_pattern() {
+        let policy = policy(&[], &[r"\bgit\s+push\b"]);
+        assert!(policy.check("git push origin main").is_err());
+        assert!(policy.check("git status").is_ok());
+    }
+
+    #[test]
+    fn only_allows_commands_matching_an_allow_pattern() {
+        let policy = policy(&[r"^cargo\s", r"^git status$"], &[]);
+        assert!(policy.check("cargo test").is_ok());
+        assert!(policy.check("git status").is_ok());
+        assert!(policy.check("git push").is_err());
+    }
+
+    #[test]
+    fn deny_patterns_win_over_allow_patterns() {
+        let policy = policy(&[r"^cargo\s"], &[r"\bpublish\b"]);
+        assert!(policy.check("cargo publish").is_err());
+    }
+
+    #[test]
+    fn refuses_invalid_patterns() {
+        assert!(CommandPolicy::new(&["(".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn checks_each_command_of_a_shell_command_line() {
+        let policy = policy(&[r"^(git status|ls)\b"], &[r"\brm\b"]);
+        assert!(policy.check_shell("git status && ls -la | ls").is_ok());
+        for command in [
+            "git status; rm -rf ~",
+            "git status && curl example.com",
+            "ls || curl example.com",
+            "ls | curl example.com",
+            "ls & curl example.com",
+            "ls\ncurl example.com",
+        ] {
+            assert!(
+                policy.check_shell(command).is_err(),
+                "{} was allowed",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_substitutions_only_with_allow_patterns() {
+        let allowing = policy(&[r"^ls\b"], &[]);
+        assert!(allowing.check_shell("ls $(cat dirs)").is_err());
+        assert!(allowing.check_shell("ls `cat dirs`").is_err());
+        assert!(allowing.check_shell("ls <(cat dirs)").is_err());
+
+        let denying = policy(&[], &[r"\brm\b"]);
+        assert!(denying.check_shell("ls $(cat dirs)").is_ok());
+        assert!(denying.check_shell("ls $(rm -rf ~)").is_err());
+    }
+}
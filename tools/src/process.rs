@@ -0,0 +1,62 @@
+use std::process::{Output, Stdio};
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Why a command didn't run to completion
+pub(crate) enum ProcessError {
+    Failed(std::io::Error),
+    TimedOut,
+}
+
+/// Run a command to completion and capture its output, with no stdin
+///
+/// If it runs for longer than `timeout` it is killed, along with anything it started.
+pub(crate) async fn output_with_timeout(
+    command: &mut Command,
+    timeout: Duration,
+) -> Result<Output, ProcessError> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    // Run in its own process group, so anything it spawns can be killed with it
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let child = command.spawn().map_err(ProcessError::Failed)?;
+    let pid = child.id();
+
+    match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(output) => output.map_err(ProcessError::Failed),
+        Err(_) => {
+            // Dropping the child kills the command itself, this also kills what it spawned
+            #[cfg(unix)]
+            if let Some(pid) = pid {
+                unsafe {
+                    libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+                }
+            }
+            #[cfg(not(unix))]
+            let _ = pid;
+
+            Err(ProcessError::TimedOut)
+        }
+    }
+}
+
+/// A command that runs `command_line` through the system shell
+pub(crate) fn shell_command(command_line: &str) -> Command {
+    #[cfg(windows)]
+    {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(command_line);
+        command
+    }
+    #[cfg(not(windows))]
+    {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(command_line);
+        command
+    }
+}
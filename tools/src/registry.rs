@@ -0,0 +1,102 @@
+use crate::models::{Tool, ToolName};
+use serde::ser::{Error as _, SerializeSeq};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// The tools offered to the model, looked up by name when it calls one
+///
+/// Tools are kept in the order they were registered, and registering a tool with the name
+/// of one already in the registry replaces it. Cloning is cheap, the tools are shared.
+#[derive(Debug, Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    /// Add a tool, replacing any tool with the same name
+    pub fn register<T: Tool + 'static>(&mut self, tool: T) -> &mut Self {
+        self.register_shared(Arc::new(tool))
+    }
+
+    /// Add a tool that is already shared, replacing any tool with the same name
+    pub fn register_shared(&mut self, tool: Arc<dyn Tool>) -> &mut Self {
+        let name = tool.title();
+        match self
+            .tools
+            .iter_mut()
+            .find(|existing| existing.title() == name)
+        {
+            Some(existing) => *existing = tool,
+            None => self.tools.push(tool),
+        }
+        self
+    }
+
+    /// Add every tool in another registry, replacing tools with the same names
+    pub fn extend(&mut self, other: &ToolRegistry) -> &mut Self {
+        for tool in &other.tools {
+            self.register_shared(tool.clone());
+        }
+        self
+    }
+
+    /// Remove the tool with this name, returning it if there was one
+    pub fn remove(&mut self, name: &ToolName) -> Option<Arc<dyn Tool>> {
+        let index = self.tools.iter().position(|tool| tool.title() == *name)?;
+        Some(self.tools.remove(index))
+    }
+
+    /// Keep only the tools `keep` returns true for
+    pub fn retain<F: FnMut(&Arc<dyn Tool>) -> bool>(&mut self, keep: F) -> &mut Self {
+        self.tools.retain(keep);
+        self
+    }
+
+    /// The tools that don't change files or run commands
+    pub fn read_only(&self) -> ToolRegistry {
+        let mut read_only = self.clone();
+        read_only.retain(|tool| !tool.is_mutating());
+        read_only
+    }
+
+    /// The tool with this name
+    pub fn get(&self, name: &ToolName) -> Option<&Arc<dyn Tool>> {
+        self.tools.iter().find(|tool| tool.title() == *name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn Tool>> {
+        self.tools.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+}
+
+impl FromIterator<Box<dyn Tool>> for ToolRegistry {
+    fn from_iter<I: IntoIterator<Item = Box<dyn Tool>>>(tools: I) -> Self {
+        let mut registry = ToolRegistry::default();
+        for tool in tools {
+            registry.register_shared(Arc::from(tool));
+        }
+        registry
+    }
+}
+
+/// Serialized as the definitions offered to the model
+impl Serialize for ToolRegistry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.tools.len()))?;
+        for tool in &self.tools {
+            let schema = tool.to_json_schema().map_err(S::Error::custom)?;
+            let schema: Value = serde_json::from_str(&schema).map_err(S::Error::custom)?;
+            seq.serialize_element(&schema)?;
+        }
+        seq.end()
+    }
+}
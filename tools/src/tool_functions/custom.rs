@@ -0,0 +1,172 @@
+use crate::models::{Tool, ToolContent, ToolError, ToolName, ToolResult};
+use crate::process::{output_with_timeout, shell_command, ProcessError};
+use crate::tool_functions::run_command::DEFAULT_TIMEOUT;
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Duration;
+
+/// A tool declared in the config that runs a command line built from its input
+///
+/// Each `{{param}}` in the command is replaced by the value of that input parameter, quoted
+/// for the shell so the model can't inject commands of its own. `cmd` on Windows has no
+/// quoting that's safe for any value, so there a command can't have parameters.
+#[derive(Debug, Serialize, Clone)]
+pub struct CustomTool {
+    pub name: ToolName,
+    pub description: String,
+    /// JSON schema for the input, an object whose properties are the parameters
+    pub input_schema: Value,
+    /// The command line template, run through the system shell in the workspace root
+    pub command: String,
+    /// How long the command may run before it is killed
+    pub timeout: Duration,
+    /// The directory the command runs in
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+impl CustomTool {
+    /// Create a custom tool, checking its name is usable and its schema describes an object
+    pub fn new(
+        name: String,
+        description: String,
+        input_schema: Value,
+        command: String,
+    ) -> Result<Self, ToolError> {
+        let Ok(tool_name) = ToolName::try_from(name.clone()) else {
+            return Err(ToolError::InvalidCustomTool(
+                name,
+                "names must be 1 to 64 letters, numbers, '_' or '-'".to_string(),
+            ));
+        };
+        if cfg!(windows) && command.contains("{{") {
+            return Err(ToolError::InvalidCustomTool(
+                name,
+                "commands can't have {{param}} placeholders on Windows, where values can't be \
+                quoted safely for cmd"
+                    .to_string(),
+            ));
+        }
+        if input_schema.get("type").and_then(Value::as_str) != Some("object") {
+            return Err(ToolError::InvalidCustomTool(
+                name,
+                "input_schema must have type object".to_string(),
+            ));
+        }
+
+        Ok(CustomTool {
+            name: tool_name,
+            description,
+            input_schema,
+            command,
+            timeout: DEFAULT_TIMEOUT,
+            workspace: Workspace::default(),
+        })
+    }
+
+    /// The command line with each `{{param}}` replaced by its quoted value
+    fn command_line(&self, input: &Value) -> String {
+        let mut command_line = String::new();
+        let mut rest = self.command.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+                break;
+            };
+            command_line.push_str(&rest[..start]);
+            let param = rest[start + 2..end].trim();
+            command_line.push_str(&quote(&param_value(&input[param])));
+            rest = &rest[end + 2..];
+        }
+        command_line.push_str(rest);
+
+        command_line
+    }
+}
+
+#[async_trait]
+impl Tool for CustomTool {
+    fn title(&self) -> ToolName {
+        self.name.clone()
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn input_schema(&self) -> Result<String, ToolError> {
+        let properties = self
+            .input_schema
+            .get("properties")
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!({}));
+        let required = self
+            .input_schema
+            .get("required")
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!([]));
+        let filtered = serde_json::json!({
+            "type": "object",
+            "properties": properties,
+            "required": required
+        });
+
+        serde_json::to_string(&filtered).map_err(ToolError::InputSchemaSerializationError)
+    }
+
+    async fn call(&self, input: Value) -> ToolResult {
+        let root = match self.workspace.root() {
+            Ok(root) => root,
+            Err(message) => return ToolResult::error(message),
+        };
+
+        let mut command = shell_command(&self.command_line(&input));
+        command.current_dir(root);
+
+        let output = match output_with_timeout(&mut command, self.timeout).await {
+            Ok(output) => output,
+            Err(ProcessError::Failed(e)) => {
+                return ToolResult::error(format!("Failed to run {}: {}", self.name, e));
+            }
+            Err(ProcessError::TimedOut) => {
+                return ToolResult::error(format!(
+                    "{} timed out after {} seconds and was killed",
+                    self.name,
+                    self.timeout.as_secs()
+                ));
+            }
+        };
+
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        if output.status.success() {
+            ToolResult::success(ToolContent::String(stdout))
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            ToolResult::error(format!(
+                "{} failed: {}",
+                self.name,
+                [stderr.trim(), stdout.trim()].join("\n").trim()
+            ))
+        }
+    }
+}
+
+/// An input value as command line text, missing parameters being empty
+fn param_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Quote a value so the shell passes it through as a single argument
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
@@ -0,0 +1,149 @@
+use crate::backup::Backups;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::{create_aria_dir, Workspace};
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+
+/// Directory that trashed files are moved into, one subdirectory per session
+const TRASH_DIR: &str = ".aria/trash";
+
+/// Input parameters for the delete_file tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct DeleteFileInput {
+    /// The path of the file to delete
+    pub path: String,
+    /// Remove the file for good instead of moving it to the trash, defaults to false
+    pub permanent: Option<bool>,
+}
+
+/// Tool for deleting files
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct DeleteFileTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+    /// Where the original contents of deleted files are saved
+    #[serde(skip)]
+    pub backups: Backups,
+}
+
+#[async_trait]
+impl TypedTool for DeleteFileTool {
+    type Input = DeleteFileInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("delete_file")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Deletes the file at the specified path. By default the file is moved to a trash directory \
+        for this session, so the deletion can be undone by moving it back; the result says where it \
+        went. Set `permanent` only when the file must not be kept, for example if it holds secrets. \
+        Directories can't be deleted with this tool."
+    }
+
+    async fn run(&self, input: DeleteFileInput) -> ToolResult {
+        let path = match self.workspace.resolve(&input.path) {
+            Ok(path) => path,
+            Err(message) => return ToolResult::error(message),
+        };
+
+        match fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_dir() => {
+                return ToolResult::error(format!(
+                    "'{}' is a directory, only files can be deleted",
+                    input.path
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return ToolResult::error(format!("Failed to delete file '{}': {}", input.path, e))
+            }
+        }
+
+        // Kept even when the file is moved to the trash, so undoing the run doesn't depend on it
+        if let Err(message) = self.backups.snapshot_async(&path).await {
+            return ToolResult::error(message);
+        }
+
+        if input.permanent.unwrap_or(false) {
+            return match fs::remove_file(&path).await {
+                Ok(_) => ToolResult::success(ToolContent::String(format!(
+                    "Successfully deleted file '{}'",
+                    input.path
+                ))),
+                Err(e) => {
+                    ToolResult::error(format!("Failed to delete file '{}': {}", input.path, e))
+                }
+            };
+        }
+
+        let destination = match trash_path(&self.workspace, &path) {
+            Ok(destination) => destination,
+            Err(message) => return ToolResult::error(message),
+        };
+        if let Some(parent) = destination.parent() {
+            if let Err(e) = create_aria_dir(parent) {
+                return ToolResult::error(format!(
+                    "Failed to create directory '{}': {}",
+                    parent.display(),
+                    e
+                ));
+            }
+        }
+
+        match fs::rename(&path, &destination).await {
+            Ok(_) => ToolResult::success(ToolContent::String(format!(
+                "Successfully moved file '{}' to the trash at '{}'",
+                input.path,
+                destination.display()
+            ))),
+            Err(e) => ToolResult::error(format!(
+                "Failed to move file '{}' to the trash: {}",
+                input.path, e
+            )),
+        }
+    }
+}
+
+/// Where a file is moved to in the trash, mirroring its path in the workspace so it's clear
+/// where to restore it
+fn trash_path(workspace: &Workspace, path: &Path) -> Result<PathBuf, String> {
+    let root = workspace.root()?;
+    let relative = path.strip_prefix(&root).unwrap_or(path);
+
+    let original = root.join(session_trash_dir()).join(relative);
+
+    // Deleting the same path twice in a session keeps both copies
+    let mut destination = original.clone();
+    let mut copy = 1;
+    while destination.exists() {
+        copy += 1;
+        let mut name = original.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}", copy));
+        destination.set_file_name(name);
+    }
+
+    Ok(destination)
+}
+
+/// The trash directory for this session, named after when it started
+fn session_trash_dir() -> &'static Path {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        Path::new(TRASH_DIR).join(started.to_string())
+    })
+}
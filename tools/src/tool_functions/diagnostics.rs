@@ -0,0 +1,81 @@
+use crate::lsp::{format_location, LanguageServers};
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Input parameters for the diagnostics tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct DiagnosticsInput {
+    /// The path of the source file to check
+    pub path: String,
+}
+
+/// Tool for getting compiler errors and warnings for a file from a language server
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct DiagnosticsTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+    /// The language servers shared with the other code navigation tools
+    #[serde(skip)]
+    pub language_servers: LanguageServers,
+}
+
+#[async_trait]
+impl TypedTool for DiagnosticsTool {
+    type Input = DiagnosticsInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("diagnostics")
+    }
+
+    fn description(&self) -> &'static str {
+        "Returns the errors and warnings a language server reports for a Rust, TypeScript or JavaScript \
+        file, one per line as `path:line:column: severity: message`. Use this after editing a file to check \
+        it compiles, which is much faster than building the whole project. The first call for a project \
+        starts the language server, which can take a while to index it."
+    }
+
+    async fn run(&self, input: DiagnosticsInput) -> ToolResult {
+        let root = match self.workspace.root() {
+            Ok(root) => root,
+            Err(message) => return ToolResult::error(message),
+        };
+        let path = match self.workspace.resolve(&input.path) {
+            Ok(path) => path,
+            Err(message) => return ToolResult::error(message),
+        };
+
+        let document = match self.language_servers.open(&root, &path).await {
+            Ok(document) => document,
+            Err(message) => return ToolResult::error(message),
+        };
+        let diagnostics = document.server.diagnostics(&document.uri).await;
+
+        if diagnostics.is_empty() {
+            return ToolResult::success(ToolContent::String(format!(
+                "No problems found in '{}'",
+                input.path
+            )));
+        }
+
+        let lines: Vec<String> = diagnostics
+            .iter()
+            .filter_map(|diagnostic| {
+                let location = format_location(&root, document.uri.as_str(), &diagnostic["range"])?;
+                let severity = match diagnostic["severity"].as_u64() {
+                    Some(1) => "error",
+                    Some(2) => "warning",
+                    Some(3) => "info",
+                    _ => "hint",
+                };
+                let message = diagnostic["message"].as_str()?;
+                Some(format!("{}: {}: {}", location, severity, message))
+            })
+            .collect();
+
+        ToolResult::success(ToolContent::String(lines.join("\n")))
+    }
+}
@@ -0,0 +1,109 @@
+use crate::docker::docker;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::policy::CommandPolicy;
+use crate::tool_functions::run_command::DEFAULT_TIMEOUT;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Input parameters for the docker_exec tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct DockerExecInput {
+    /// The name of the container, which must be one the user allows
+    pub container: String,
+    /// The command to run in the container
+    pub cmd: String,
+    /// The arguments to pass to the command
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Kill the command if it runs for longer than this many seconds, defaults to the configured
+    /// timeout
+    pub timeout_secs: Option<u64>,
+}
+
+/// Tool for running commands inside docker containers the user allows
+#[derive(Debug, Serialize, Clone)]
+pub struct DockerExecTool {
+    /// The names of the containers commands may run in
+    pub containers: Vec<String>,
+    /// Which commands may run, any command may when it's empty
+    #[serde(skip)]
+    pub policy: CommandPolicy,
+    /// How long a command may run when its input doesn't set a timeout
+    pub default_timeout: Duration,
+}
+
+impl DockerExecTool {
+    /// Allow commands to run in the named containers
+    pub fn new(containers: Vec<String>) -> Self {
+        DockerExecTool {
+            containers,
+            policy: CommandPolicy::default(),
+            default_timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+#[async_trait]
+impl TypedTool for DockerExecTool {
+    type Input = DockerExecInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("docker_exec")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Runs a command inside a running docker container, like `docker exec`, and returns its output. \
+        Only the containers the user allows can be used, and commands may be refused by the user's \
+        command policy; if so, don't try to work around it. The command runs without a shell, so set \
+        `cmd` to `sh` with the arguments `-c` and a command line when it needs pipes or redirects. \
+        Commands are killed after the configured timeout unless `timeout_secs` is set."
+    }
+
+    async fn run(&self, input: DockerExecInput) -> ToolResult {
+        if !self.containers.contains(&input.container) {
+            return ToolResult::error(format!(
+                "Commands can't run in '{}', the containers allowed are: {}",
+                input.container,
+                self.containers.join(", ")
+            ));
+        }
+
+        let command_line = std::iter::once(input.cmd.as_str())
+            .chain(input.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let Err(message) = self.policy.check(&command_line) {
+            return ToolResult::error(message);
+        }
+
+        let timeout = input
+            .timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(self.default_timeout);
+        let mut args = vec!["exec", "--", &input.container, &input.cmd];
+        args.extend(input.args.iter().map(String::as_str));
+
+        let output = match docker(&args, timeout).await {
+            Ok(output) => output,
+            Err(message) => return ToolResult::error(message),
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let result = if output.status.success() {
+            ToolResult::success(ToolContent::String(stdout))
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            ToolResult::error(format!(
+                "Command failed with {}: {}",
+                output.status,
+                [stderr.trim(), stdout.trim()].join("\n").trim()
+            ))
+        };
+        result.with_exit_code(output.status.code())
+    }
+}
@@ -0,0 +1,104 @@
+use crate::docker::docker_stdout;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Input parameters for the docker_list tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct DockerListInput {
+    /// What to list: `containers` or `images`
+    pub kind: String,
+    /// Include stopped containers, defaults to only running ones
+    pub all: Option<bool>,
+}
+
+/// A container as docker reports it
+#[derive(Debug, Deserialize, Serialize)]
+struct Container {
+    #[serde(rename(deserialize = "ID"))]
+    id: String,
+    #[serde(rename(deserialize = "Names"))]
+    name: String,
+    #[serde(rename(deserialize = "Image"))]
+    image: String,
+    /// E.g. running or exited
+    #[serde(rename(deserialize = "State"), default)]
+    state: String,
+    /// E.g. "Up 2 hours"
+    #[serde(rename(deserialize = "Status"))]
+    status: String,
+    #[serde(rename(deserialize = "Ports"))]
+    ports: String,
+}
+
+/// An image as docker reports it
+#[derive(Debug, Deserialize, Serialize)]
+struct Image {
+    #[serde(rename(deserialize = "ID"))]
+    id: String,
+    #[serde(rename(deserialize = "Repository"))]
+    repository: String,
+    #[serde(rename(deserialize = "Tag"))]
+    tag: String,
+    #[serde(rename(deserialize = "Size"))]
+    size: String,
+    #[serde(rename(deserialize = "CreatedSince"))]
+    created: String,
+}
+
+/// Tool for listing docker containers and images
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct DockerListTool;
+
+#[async_trait]
+impl TypedTool for DockerListTool {
+    type Input = DockerListInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("docker_list")
+    }
+
+    fn description(&self) -> &'static str {
+        "Lists docker containers, with their id, name, image, state, status and ports, or images, with \
+        their id, repository, tag, size and age, as JSON. Only running containers are listed unless \
+        `all` is set. Use it to find the name of a container before reading its logs."
+    }
+
+    async fn run(&self, input: DockerListInput) -> ToolResult {
+        let format = "--format={{json .}}";
+        let listed = match input.kind.as_str() {
+            "containers" => {
+                let mut args = vec!["ps", format];
+                if input.all.unwrap_or(false) {
+                    args.push("--all");
+                }
+                list::<Container>(&args).await
+            }
+            "images" => list::<Image>(&["images", format]).await,
+            kind => Err(format!(
+                "Unknown kind '{}', expected containers or images",
+                kind
+            )),
+        };
+
+        match listed {
+            Ok(json) => ToolResult::success(ToolContent::Json(json)),
+            Err(message) => ToolResult::error(message),
+        }
+    }
+}
+
+/// Run a docker listing that prints one JSON object per line, keeping the fields of `T`
+async fn list<T: for<'de> Deserialize<'de> + Serialize>(args: &[&str]) -> Result<Value, String> {
+    let output = docker_stdout(args).await?;
+    let items = output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str::<T>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to parse the output of docker: {}", e))?;
+
+    serde_json::to_value(items).map_err(|e| format!("Failed to serialize the listing: {}", e))
+}
@@ -0,0 +1,71 @@
+use crate::docker::{docker, DOCKER_TIMEOUT};
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_TAIL: u32 = 200;
+
+/// Input parameters for the docker_logs tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct DockerLogsInput {
+    /// The name or id of the container
+    pub container: String,
+    /// How many lines from the end of the logs to show, defaults to 200
+    pub tail: Option<u32>,
+    /// Only show logs since this time, a timestamp like `2024-01-02T13:00:00` or a duration
+    /// like `10m`
+    pub since: Option<String>,
+}
+
+/// Tool for reading the logs of a docker container
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct DockerLogsTool;
+
+#[async_trait]
+impl TypedTool for DockerLogsTool {
+    type Input = DockerLogsInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("docker_logs")
+    }
+
+    fn description(&self) -> &'static str {
+        "Shows the end of a docker container's logs, both its standard output and error, with a \
+        timestamp on each line. Narrow them with `since` when looking for something recent rather than \
+        asking for a larger tail."
+    }
+
+    async fn run(&self, input: DockerLogsInput) -> ToolResult {
+        let tail = input.tail.unwrap_or(DEFAULT_TAIL).to_string();
+        let mut args = vec!["logs", "--timestamps", "--tail", &tail];
+        if let Some(since) = &input.since {
+            args.extend(["--since", since]);
+        }
+        // Ends the options, so a container name can't be taken for one
+        args.extend(["--", &input.container]);
+
+        let output = match docker(&args, DOCKER_TIMEOUT).await {
+            Ok(output) => output,
+            Err(message) => return ToolResult::error(message),
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            return ToolResult::error(format!("docker logs failed: {}", stderr.trim()));
+        }
+
+        // The container's two streams are passed through separately, so they can't be interleaved
+        ToolResult::success(ToolContent::String(match (stdout.trim(), stderr.trim()) {
+            ("", "") => "The container has no logs in that range".to_string(),
+            (stdout, "") => stdout.to_string(),
+            ("", stderr) => stderr.to_string(),
+            (stdout, stderr) => {
+                format!(
+                    "Standard output:\n{}\n\nStandard error:\n{}",
+                    stdout, stderr
+                )
+            }
+        }))
+    }
+}
@@ -0,0 +1,104 @@
+use crate::backup::Backups;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// Input parameters for the edit_file tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct EditFileInput {
+    /// The path of the file to edit
+    pub path: String,
+    /// The exact text to replace, which must appear exactly once in the file
+    pub old_string: String,
+    /// The text to replace it with
+    pub new_string: String,
+}
+
+/// Tool for making targeted edits to files
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct EditFileTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+    /// Where the original contents of edited files are saved
+    #[serde(skip)]
+    pub backups: Backups,
+}
+
+#[async_trait]
+impl TypedTool for EditFileTool {
+    type Input = EditFileInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("edit_file")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Replaces one occurrence of `old_string` with `new_string` in the file at the specified path, \
+        leaving the rest of the file untouched. Prefer this over write_file when changing part of an \
+        existing file. `old_string` must match the file exactly, including whitespace and indentation, \
+        and must be unique within the file; include surrounding lines to disambiguate it."
+    }
+
+    async fn run(&self, input: EditFileInput) -> ToolResult {
+        if input.old_string.is_empty() {
+            return ToolResult::error(
+                "old_string must not be empty, use write_file to create a file",
+            );
+        }
+
+        if input.old_string == input.new_string {
+            return ToolResult::error(
+                "old_string and new_string are identical, there is nothing to edit",
+            );
+        }
+
+        let path = match self.workspace.resolve(&input.path) {
+            Ok(path) => path,
+            Err(message) => return ToolResult::error(message),
+        };
+
+        let contents = match fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(e) => {
+                return ToolResult::error(format!("Failed to read file '{}': {}", input.path, e))
+            }
+        };
+
+        match contents.matches(&input.old_string).count() {
+            0 => ToolResult::error(format!(
+                "old_string was not found in '{}'. Read the file to check the exact text, \
+                including whitespace and indentation",
+                input.path
+            )),
+            1 => {
+                let updated = contents.replacen(&input.old_string, &input.new_string, 1);
+                if let Err(message) = self.backups.snapshot_async(&path).await {
+                    return ToolResult::error(message);
+                }
+                match fs::write(&path, updated).await {
+                    Ok(_) => ToolResult::success(ToolContent::String(format!(
+                        "Successfully edited file '{}'",
+                        input.path
+                    ))),
+                    Err(e) => ToolResult::error(format!(
+                        "Failed to write to file '{}': {}",
+                        input.path, e
+                    )),
+                }
+            }
+            count => ToolResult::error(format!(
+                "old_string appears {} times in '{}'. Include more surrounding lines so it \
+                matches exactly once",
+                count, input.path
+            )),
+        }
+    }
+}
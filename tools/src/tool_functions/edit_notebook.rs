@@ -0,0 +1,147 @@
+use crate::backup::Backups;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::notebook::{source_lines, Notebook};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Input parameters for the edit_notebook tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct EditNotebookInput {
+    /// The path of the `.ipynb` file to edit
+    pub path: String,
+    /// What to do to the cell: `replace`, `insert` or `delete`
+    pub action: String,
+    /// The index of the cell to replace or delete, or where to insert the new cell, starting from 0
+    pub index: usize,
+    /// The new source of the cell, required for `replace` and `insert`
+    pub source: Option<String>,
+    /// `code` or `markdown`, defaults to `code` for a new cell and the existing type when replacing
+    pub cell_type: Option<String>,
+}
+
+/// Tool for editing Jupyter notebooks cell by cell
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct EditNotebookTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+    /// Where the original contents of edited notebooks are saved
+    #[serde(skip)]
+    pub backups: Backups,
+}
+
+#[async_trait]
+impl TypedTool for EditNotebookTool {
+    type Input = EditNotebookInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("edit_notebook")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Edits one cell of a Jupyter notebook (`.ipynb`), keeping the outputs and metadata of the rest \
+        intact. `replace` sets the source of the cell at `index`, `insert` adds a new cell before \
+        `index` (use the number of cells to append), and `delete` removes the cell at `index`. Read the \
+        notebook with read_notebook first to find the cell indexes. Never edit notebooks with write_file \
+        or edit_file."
+    }
+
+    async fn run(&self, input: EditNotebookInput) -> ToolResult {
+        if let Some(cell_type) = &input.cell_type {
+            if !["code", "markdown", "raw"].contains(&cell_type.as_str()) {
+                return ToolResult::error(format!(
+                    "Invalid cell_type '{}', expected code, markdown or raw",
+                    cell_type
+                ));
+            }
+        }
+
+        let path = match self.workspace.resolve(&input.path) {
+            Ok(path) => path,
+            Err(message) => return ToolResult::error(message),
+        };
+        let mut notebook = match Notebook::load(&path, &input.path).await {
+            Ok(notebook) => notebook,
+            Err(message) => return ToolResult::error(message),
+        };
+        let count = notebook.cells().len();
+
+        let message = match input.action.as_str() {
+            "replace" | "delete" if input.index >= count => {
+                return ToolResult::error(format!(
+                    "There is no cell {} in '{}', which has {} cells",
+                    input.index, input.path, count
+                ));
+            }
+            "insert" if input.index > count => {
+                return ToolResult::error(format!(
+                    "Can't insert at {} in '{}', which has {} cells, use {} to append",
+                    input.index, input.path, count, count
+                ));
+            }
+            "replace" => {
+                let Some(source) = &input.source else {
+                    return ToolResult::error("source is required to replace a cell");
+                };
+                let cell = &mut notebook.cells_mut()[input.index];
+                cell["source"] = source_lines(source);
+                if let Some(cell_type) = &input.cell_type {
+                    set_cell_type(cell, cell_type);
+                }
+                format!("Replaced cell {} in '{}'", input.index, input.path)
+            }
+            "insert" => {
+                let Some(source) = &input.source else {
+                    return ToolResult::error("source is required to insert a cell");
+                };
+                let cell = notebook.new_cell(input.cell_type.as_deref().unwrap_or("code"), source);
+                notebook.cells_mut().insert(input.index, cell);
+                format!("Inserted cell {} in '{}'", input.index, input.path)
+            }
+            "delete" => {
+                notebook.cells_mut().remove(input.index);
+                format!("Deleted cell {} from '{}'", input.index, input.path)
+            }
+            action => {
+                return ToolResult::error(format!(
+                    "Invalid action '{}', expected replace, insert or delete",
+                    action
+                ));
+            }
+        };
+
+        if let Err(message) = self.backups.snapshot_async(&path).await {
+            return ToolResult::error(message);
+        }
+        match notebook.save(&path, &input.path).await {
+            Ok(()) => ToolResult::success(ToolContent::String(message)),
+            Err(message) => ToolResult::error(message),
+        }
+    }
+}
+
+/// Change a cell's type, adding or removing the fields only code cells have
+fn set_cell_type(cell: &mut Value, cell_type: &str) {
+    let Some(cell) = cell.as_object_mut() else {
+        return;
+    };
+    if cell.get("cell_type").and_then(Value::as_str) == Some(cell_type) {
+        return;
+    }
+
+    cell.insert("cell_type".to_string(), Value::from(cell_type));
+    if cell_type == "code" {
+        cell.insert("execution_count".to_string(), Value::Null);
+        cell.insert("outputs".to_string(), Value::Array(Vec::new()));
+    } else {
+        cell.shift_remove("execution_count");
+        cell.shift_remove("outputs");
+    }
+}
@@ -0,0 +1,151 @@
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use ignore::WalkBuilder;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Roughly how many bytes of source code make up a token, across the common tokenizers
+const BYTES_PER_TOKEN: u64 = 4;
+
+/// The most files of a directory listed individually, the largest first
+const MAX_FILES_LISTED: usize = 20;
+
+/// Input parameters for the estimate_tokens tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct EstimateTokensInput {
+    /// The file or directory to estimate
+    pub path: String,
+}
+
+/// The estimate for a file or directory
+#[derive(Debug, Serialize)]
+struct Estimate {
+    path: String,
+    /// How many files were counted, for a directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<usize>,
+    bytes: u64,
+    tokens: u64,
+    /// The largest files in a directory
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    largest: Vec<FileEstimate>,
+}
+
+#[derive(Debug, Serialize)]
+struct FileEstimate {
+    path: String,
+    bytes: u64,
+    tokens: u64,
+}
+
+/// Tool for estimating how many tokens reading a file or directory would take
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct EstimateTokensTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl TypedTool for EstimateTokensTool {
+    type Input = EstimateTokensInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("estimate_tokens")
+    }
+
+    fn description(&self) -> &'static str {
+        "Estimates how many tokens a file, or all the files in a directory, would take to read, from \
+        their size at about 4 bytes per token. Use it before reading something that may be large to \
+        decide whether to read it whole or a range of lines at a time with read_file. For a directory \
+        the total is returned along with the largest files. Files ignored by .gitignore and hidden \
+        files are skipped."
+    }
+
+    async fn run(&self, input: EstimateTokensInput) -> ToolResult {
+        let resolved = match self.workspace.resolve(&input.path) {
+            Ok(resolved) => resolved,
+            Err(message) => {
+                return ToolResult::error(message);
+            }
+        };
+
+        let estimate = match tokio::fs::metadata(&resolved).await {
+            Ok(metadata) if metadata.is_dir() => {
+                // Walking blocks, so it's kept off the async runtime
+                let path = input.path.clone();
+                match tokio::task::spawn_blocking(move || estimate_dir(&path, &resolved)).await {
+                    Ok(estimate) => estimate,
+                    Err(e) => {
+                        return ToolResult::error(format!(
+                            "Failed to read '{}': {}",
+                            input.path, e
+                        ));
+                    }
+                }
+            }
+            Ok(metadata) => Estimate {
+                path: input.path,
+                files: None,
+                bytes: metadata.len(),
+                tokens: tokens(metadata.len()),
+                largest: Vec::new(),
+            },
+            Err(e) => {
+                return ToolResult::error(format!("Failed to read '{}': {}", input.path, e));
+            }
+        };
+
+        match serde_json::to_value(estimate) {
+            Ok(value) => ToolResult::success(ToolContent::Json(value)),
+            Err(e) => ToolResult::error(format!("Failed to serialize the estimate: {}", e)),
+        }
+    }
+}
+
+fn estimate_dir(path: &str, dir: &Path) -> Estimate {
+    let mut files: Vec<FileEstimate> = WalkBuilder::new(dir)
+        // Respect .gitignore even when the directory isn't a git repository
+        .require_git(false)
+        .build()
+        // Entries that can't be read, e.g. for lack of permission, are skipped
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file())
+        })
+        .filter_map(|entry| {
+            let bytes = entry.metadata().ok()?.len();
+            // Show paths the way they were asked for, rather than resolved
+            let relative = entry.path().strip_prefix(dir).ok()?;
+            Some(FileEstimate {
+                path: Path::new(path)
+                    .join(relative)
+                    .to_string_lossy()
+                    .into_owned(),
+                bytes,
+                tokens: tokens(bytes),
+            })
+        })
+        .collect();
+
+    let bytes = files.iter().map(|file| file.bytes).sum();
+    let count = files.len();
+    files.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+    files.truncate(MAX_FILES_LISTED);
+
+    Estimate {
+        path: path.to_string(),
+        files: Some(count),
+        bytes,
+        tokens: tokens(bytes),
+        largest: files,
+    }
+}
+
+fn tokens(bytes: u64) -> u64 {
+    bytes.div_ceil(BYTES_PER_TOKEN)
+}
@@ -0,0 +1,93 @@
+use crate::lsp::{locations, LanguageServers};
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Input parameters for the find_references tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct FindReferencesInput {
+    /// The path of the source file containing the symbol
+    pub path: String,
+    /// The line the symbol is on, starting from 1
+    pub line: u32,
+    /// The column of any character in the symbol, starting from 1
+    pub column: u32,
+    /// Include the symbol's own definition in the results, defaults to true
+    pub include_declaration: Option<bool>,
+}
+
+/// Tool for finding everywhere a symbol is used with a language server
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct FindReferencesTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+    /// The language servers shared with the other code navigation tools
+    #[serde(skip)]
+    pub language_servers: LanguageServers,
+}
+
+#[async_trait]
+impl TypedTool for FindReferencesTool {
+    type Input = FindReferencesInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("find_references")
+    }
+
+    fn description(&self) -> &'static str {
+        "Finds every use of the symbol at a position in a Rust, TypeScript or JavaScript file, using a \
+        language server, and returns each as `path:line:column`. Lines and columns start from 1, as shown \
+        by read_file. Use this before renaming or changing the signature of a function or type to find \
+        everything that needs updating."
+    }
+
+    async fn run(&self, input: FindReferencesInput) -> ToolResult {
+        let root = match self.workspace.root() {
+            Ok(root) => root,
+            Err(message) => return ToolResult::error(message),
+        };
+        let path = match self.workspace.resolve(&input.path) {
+            Ok(path) => path,
+            Err(message) => return ToolResult::error(message),
+        };
+        if input.line == 0 || input.column == 0 {
+            return ToolResult::error("line and column start from 1");
+        }
+
+        let document = match self.language_servers.open(&root, &path).await {
+            Ok(document) => document,
+            Err(message) => return ToolResult::error(message),
+        };
+        let result = document
+            .server
+            .request(
+                "textDocument/references",
+                json!({
+                    "textDocument": { "uri": document.uri },
+                    "position": { "line": input.line - 1, "character": input.column - 1 },
+                    "context": { "includeDeclaration": input.include_declaration.unwrap_or(true) }
+                }),
+            )
+            .await;
+
+        match result {
+            Ok(result) => {
+                let references = locations(&root, &result);
+                let content = if references.is_empty() {
+                    format!(
+                        "No references found for the symbol at {}:{}:{}",
+                        input.path, input.line, input.column
+                    )
+                } else {
+                    references.join("\n")
+                };
+                ToolResult::success(ToolContent::String(content))
+            }
+            Err(message) => ToolResult::error(message),
+        }
+    }
+}
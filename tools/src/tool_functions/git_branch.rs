@@ -0,0 +1,98 @@
+use crate::git::git;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Separates the fields of a branch in git's output, it can't appear in them
+const FIELD_SEPARATOR: char = '\u{1f}';
+
+/// Input parameters for the git_branch tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct GitBranchInput {
+    /// Also list remote-tracking branches, defaults to false
+    pub include_remote: Option<bool>,
+}
+
+/// The branches in the repository
+#[derive(Debug, Serialize)]
+struct GitBranches {
+    /// None when HEAD is detached
+    current: Option<String>,
+    branches: Vec<Branch>,
+}
+
+/// A branch and the branch it tracks
+#[derive(Debug, Serialize)]
+struct Branch {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream: Option<String>,
+}
+
+/// Tool for listing git branches
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct GitBranchTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl TypedTool for GitBranchTool {
+    type Input = GitBranchInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("git_branch")
+    }
+
+    fn description(&self) -> &'static str {
+        "Lists the branches of the git repository in the workspace as JSON, with the current branch and \
+        the upstream each branch tracks. This tool is read-only, it can't create or switch branches. \
+        Prefer this over running git branch with run_command."
+    }
+
+    async fn run(&self, input: GitBranchInput) -> ToolResult {
+        let mut args = vec![
+            "branch",
+            "--format=%(HEAD)%1f%(refname:short)%1f%(upstream:short)",
+        ];
+        if input.include_remote.unwrap_or(false) {
+            args.push("--all");
+        }
+
+        let output = match git(&self.workspace, &args).await {
+            Ok(output) => output,
+            Err(message) => return ToolResult::error(message),
+        };
+
+        let mut current = None;
+        let mut branches = Vec::new();
+        for line in output.lines() {
+            let mut fields = line.splitn(3, FIELD_SEPARATOR);
+            let (Some(head), Some(name)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            // A detached HEAD is listed as a pseudo-branch
+            if name.starts_with('(') {
+                continue;
+            }
+            if head == "*" {
+                current = Some(name.to_string());
+            }
+            branches.push(Branch {
+                name: name.to_string(),
+                upstream: fields
+                    .next()
+                    .filter(|upstream| !upstream.is_empty())
+                    .map(str::to_string),
+            });
+        }
+
+        match serde_json::to_value(&GitBranches { current, branches }) {
+            Ok(json) => ToolResult::success(ToolContent::Json(json)),
+            Err(e) => ToolResult::error(format!("Failed to serialize git branches: {}", e)),
+        }
+    }
+}
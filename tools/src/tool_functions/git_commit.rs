@@ -0,0 +1,87 @@
+use crate::git::git;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Input parameters for the git_commit tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct GitCommitInput {
+    /// The commit message, a short summary line optionally followed by a blank line and details
+    pub message: String,
+    /// Files to stage before committing, already staged changes are committed too
+    #[serde(default)]
+    pub paths: Vec<String>,
+}
+
+/// Tool for staging files and committing them
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct GitCommitTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl TypedTool for GitCommitTool {
+    type Input = GitCommitInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("git_commit")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Stages the given files and creates a commit in the git repository in the workspace, returning the \
+        new commit's hash. Check what will be committed with git_status and git_diff first. Existing \
+        commits are never amended and hooks always run; if a pre-commit hook fails, nothing is committed, \
+        so fix what it reports and try again."
+    }
+
+    async fn run(&self, input: GitCommitInput) -> ToolResult {
+        if input.message.trim().is_empty() {
+            return ToolResult::error("A commit message is required");
+        }
+
+        let mut paths = Vec::new();
+        for path in &input.paths {
+            match self.workspace.resolve(path) {
+                Ok(path) => paths.push(path.to_string_lossy().into_owned()),
+                Err(message) => return ToolResult::error(message),
+            }
+        }
+
+        if !paths.is_empty() {
+            let mut args = vec!["add", "--"];
+            args.extend(paths.iter().map(String::as_str));
+            if let Err(message) = git(&self.workspace, &args).await {
+                return ToolResult::error(message);
+            }
+        }
+
+        // `git diff --cached --quiet` fails when something is staged
+        if git(&self.workspace, &["diff", "--cached", "--quiet"])
+            .await
+            .is_ok()
+        {
+            return ToolResult::error(
+                "Nothing is staged to commit, pass the files to commit in paths",
+            );
+        }
+
+        if let Err(message) = git(&self.workspace, &["commit", "--message", &input.message]).await {
+            return ToolResult::error(format!("Nothing was committed. {}", message));
+        }
+
+        match git(&self.workspace, &["log", "-1", "--format=%H %s"]).await {
+            Ok(commit) => {
+                ToolResult::success(ToolContent::String(format!("Committed {}", commit.trim())))
+            }
+            Err(message) => ToolResult::error(message),
+        }
+    }
+}
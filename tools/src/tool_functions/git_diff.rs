@@ -0,0 +1,117 @@
+use crate::git::git;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Input parameters for the git_diff tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct GitDiffInput {
+    /// Show staged changes instead of unstaged ones, defaults to false
+    pub staged: Option<bool>,
+    /// Compare the working tree against this commit or branch instead, e.g. `main` or `HEAD~3`
+    pub base: Option<String>,
+    /// Only show changes under this path, defaults to the whole repository
+    pub path: Option<String>,
+}
+
+/// The changes, summarized per file and in full
+#[derive(Debug, Serialize)]
+struct GitDiff {
+    files: Vec<FileStat>,
+    diff: String,
+}
+
+/// How many lines changed in a file, binary files have no counts
+#[derive(Debug, Serialize)]
+struct FileStat {
+    path: String,
+    additions: Option<u32>,
+    deletions: Option<u32>,
+}
+
+/// Tool for showing changes in the git repository
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct GitDiffTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl TypedTool for GitDiffTool {
+    type Input = GitDiffInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("git_diff")
+    }
+
+    fn description(&self) -> &'static str {
+        "Shows changes in the git repository in the workspace as JSON: lines added and deleted per file, \
+        and the full unified diff. By default shows unstaged changes; set `staged` for what is about to be \
+        committed, or `base` to compare against a commit or branch. Prefer this over running git diff \
+        with run_command."
+    }
+
+    async fn run(&self, input: GitDiffInput) -> ToolResult {
+        if let Some(base) = &input.base {
+            if base.starts_with('-') {
+                return ToolResult::error(format!("Invalid base '{}'", base));
+            }
+        }
+
+        let path = match input
+            .path
+            .as_deref()
+            .map(|path| self.workspace.resolve(path))
+        {
+            Some(Ok(path)) => Some(path.to_string_lossy().into_owned()),
+            Some(Err(message)) => return ToolResult::error(message),
+            None => None,
+        };
+
+        let mut args = vec!["diff", "--no-color", "--no-ext-diff"];
+        if input.staged.unwrap_or(false) {
+            args.push("--cached");
+        }
+        if let Some(base) = &input.base {
+            args.push(base);
+        }
+        args.push("--");
+        if let Some(path) = &path {
+            args.push(path);
+        }
+
+        let mut numstat_args = args.clone();
+        numstat_args.insert(1, "--numstat");
+
+        let numstat = match git(&self.workspace, &numstat_args).await {
+            Ok(output) => output,
+            Err(message) => return ToolResult::error(message),
+        };
+        let diff = match git(&self.workspace, &args).await {
+            Ok(output) => output,
+            Err(message) => return ToolResult::error(message),
+        };
+
+        let files = numstat
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let additions = fields.next()?.parse().ok();
+                let deletions = fields.next()?.parse().ok();
+                Some(FileStat {
+                    path: fields.next()?.to_string(),
+                    additions,
+                    deletions,
+                })
+            })
+            .collect();
+
+        match serde_json::to_value(&GitDiff { files, diff }) {
+            Ok(json) => ToolResult::success(ToolContent::Json(json)),
+            Err(e) => ToolResult::error(format!("Failed to serialize git diff: {}", e)),
+        }
+    }
+}
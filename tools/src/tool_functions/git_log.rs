@@ -0,0 +1,98 @@
+use crate::git::git;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MAX_COUNT: u32 = 20;
+
+/// Separates the fields of a commit in git's output, it can't appear in them
+const FIELD_SEPARATOR: char = '\u{1f}';
+
+/// Input parameters for the git_log tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct GitLogInput {
+    /// The most commits to show, defaults to 20
+    pub max_count: Option<u32>,
+    /// Only show commits that touched this path, defaults to the whole repository
+    pub path: Option<String>,
+}
+
+/// A commit in the history
+#[derive(Debug, Serialize)]
+struct Commit {
+    hash: String,
+    author: String,
+    /// ISO 8601
+    date: String,
+    subject: String,
+}
+
+/// Tool for showing the git commit history
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct GitLogTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl TypedTool for GitLogTool {
+    type Input = GitLogInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("git_log")
+    }
+
+    fn description(&self) -> &'static str {
+        "Shows recent commits on the current branch of the git repository in the workspace as JSON, newest \
+        first, with each commit's hash, author, date and subject. Prefer this over running git log with \
+        run_command."
+    }
+
+    async fn run(&self, input: GitLogInput) -> ToolResult {
+        let path = match input
+            .path
+            .as_deref()
+            .map(|path| self.workspace.resolve(path))
+        {
+            Some(Ok(path)) => Some(path.to_string_lossy().into_owned()),
+            Some(Err(message)) => return ToolResult::error(message),
+            None => None,
+        };
+
+        let max_count = format!(
+            "--max-count={}",
+            input.max_count.unwrap_or(DEFAULT_MAX_COUNT)
+        );
+        let format = "--format=%H%x1f%an%x1f%aI%x1f%s";
+        let mut args = vec!["log", &max_count, format, "--"];
+        if let Some(path) = &path {
+            args.push(path);
+        }
+
+        let output = match git(&self.workspace, &args).await {
+            Ok(output) => output,
+            Err(message) => return ToolResult::error(message),
+        };
+
+        let commits: Vec<Commit> = output
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, FIELD_SEPARATOR);
+                Some(Commit {
+                    hash: fields.next()?.to_string(),
+                    author: fields.next()?.to_string(),
+                    date: fields.next()?.to_string(),
+                    subject: fields.next()?.to_string(),
+                })
+            })
+            .collect();
+
+        match serde_json::to_value(&commits) {
+            Ok(json) => ToolResult::success(ToolContent::Json(json)),
+            Err(e) => ToolResult::error(format!("Failed to serialize git log: {}", e)),
+        }
+    }
+}
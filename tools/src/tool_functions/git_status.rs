@@ -0,0 +1,173 @@
+use crate::git::git;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Input parameters for the git_status tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct GitStatusInput {
+    /// Only report changes under this path, defaults to the whole repository
+    pub path: Option<String>,
+}
+
+/// The state of the working tree
+#[derive(Debug, Serialize, Default)]
+struct GitStatus {
+    branch: Option<String>,
+    upstream: Option<String>,
+    ahead: u32,
+    behind: u32,
+    staged: Vec<FileChange>,
+    unstaged: Vec<FileChange>,
+    untracked: Vec<String>,
+    conflicted: Vec<String>,
+}
+
+/// A changed file and how it changed
+#[derive(Debug, Serialize)]
+struct FileChange {
+    path: String,
+    status: &'static str,
+    /// The path before a rename or copy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+}
+
+/// Tool for reporting the state of the git working tree
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct GitStatusTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl TypedTool for GitStatusTool {
+    type Input = GitStatusInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("git_status")
+    }
+
+    fn description(&self) -> &'static str {
+        "Reports the state of the git repository in the workspace as JSON: the current branch, how far it \
+        is ahead of or behind its upstream, and which files are staged, unstaged, untracked or in conflict. \
+        Prefer this over running git status with run_command."
+    }
+
+    async fn run(&self, input: GitStatusInput) -> ToolResult {
+        let path = match input
+            .path
+            .as_deref()
+            .map(|path| self.workspace.resolve(path))
+        {
+            Some(Ok(path)) => Some(path.to_string_lossy().into_owned()),
+            Some(Err(message)) => return ToolResult::error(message),
+            None => None,
+        };
+
+        let mut args = vec!["status", "--porcelain=v1", "--branch"];
+        if let Some(path) = &path {
+            args.extend(["--", path]);
+        }
+
+        match git(&self.workspace, &args).await {
+            Ok(output) => match serde_json::to_value(parse_status(&output)) {
+                Ok(json) => ToolResult::success(ToolContent::Json(json)),
+                Err(e) => ToolResult::error(format!("Failed to serialize git status: {}", e)),
+            },
+            Err(message) => ToolResult::error(message),
+        }
+    }
+}
+
+/// Parse the output of `git status --porcelain=v1 --branch`
+fn parse_status(output: &str) -> GitStatus {
+    let mut status = GitStatus::default();
+
+    for line in output.lines() {
+        if let Some(header) = line.strip_prefix("## ") {
+            parse_branch(header, &mut status);
+            continue;
+        }
+
+        if line.len() < 4 {
+            continue;
+        }
+        let (codes, path) = line.split_at(3);
+        let mut codes = codes.chars();
+        let (index, worktree) = (codes.next().unwrap_or(' '), codes.next().unwrap_or(' '));
+
+        match (index, worktree) {
+            ('?', '?') => status.untracked.push(path.to_string()),
+            ('U', _) | (_, 'U') | ('A', 'A') | ('D', 'D') => {
+                status.conflicted.push(path.to_string())
+            }
+            _ => {
+                // Renames and copies are shown as `from -> to`
+                let (from, path) = match path.split_once(" -> ") {
+                    Some((from, to)) => (Some(from.to_string()), to.to_string()),
+                    None => (None, path.to_string()),
+                };
+                if let Some(change) = describe(index) {
+                    status.staged.push(FileChange {
+                        path: path.clone(),
+                        status: change,
+                        from: from.clone(),
+                    });
+                }
+                if let Some(change) = describe(worktree) {
+                    status.unstaged.push(FileChange {
+                        path,
+                        status: change,
+                        from,
+                    });
+                }
+            }
+        }
+    }
+
+    status
+}
+
+/// Parse the branch header, e.g. `main...origin/main [ahead 1, behind 2]`
+fn parse_branch(header: &str, status: &mut GitStatus) {
+    let (branches, tracking) = match header.split_once(" [") {
+        Some((branches, tracking)) => (branches, tracking.trim_end_matches(']')),
+        None => (header, ""),
+    };
+
+    match branches.split_once("...") {
+        Some((branch, upstream)) => {
+            status.branch = Some(branch.to_string());
+            status.upstream = Some(upstream.to_string());
+        }
+        None => {
+            let branch = branches.trim_start_matches("No commits yet on ");
+            status.branch = Some(branch.to_string());
+        }
+    }
+
+    for part in tracking.split(", ") {
+        if let Some(count) = part.strip_prefix("ahead ") {
+            status.ahead = count.parse().unwrap_or_default();
+        } else if let Some(count) = part.strip_prefix("behind ") {
+            status.behind = count.parse().unwrap_or_default();
+        }
+    }
+}
+
+/// Describe a porcelain status code
+fn describe(code: char) -> Option<&'static str> {
+    match code {
+        'M' => Some("modified"),
+        'T' => Some("type_changed"),
+        'A' => Some("added"),
+        'D' => Some("deleted"),
+        'R' => Some("renamed"),
+        'C' => Some("copied"),
+        _ => None,
+    }
+}
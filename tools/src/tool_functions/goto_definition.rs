@@ -0,0 +1,90 @@
+use crate::lsp::{locations, LanguageServers};
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Input parameters for the goto_definition tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct GotoDefinitionInput {
+    /// The path of the source file containing the symbol
+    pub path: String,
+    /// The line the symbol is on, starting from 1
+    pub line: u32,
+    /// The column of any character in the symbol, starting from 1
+    pub column: u32,
+}
+
+/// Tool for finding where a symbol is defined using a language server
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct GotoDefinitionTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+    /// The language servers shared with the other code navigation tools
+    #[serde(skip)]
+    pub language_servers: LanguageServers,
+}
+
+#[async_trait]
+impl TypedTool for GotoDefinitionTool {
+    type Input = GotoDefinitionInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("goto_definition")
+    }
+
+    fn description(&self) -> &'static str {
+        "Finds where the symbol at a position in a Rust, TypeScript or JavaScript file is defined, using a \
+        language server, and returns each definition as `path:line:column`. Lines and columns start from 1, \
+        as shown by read_file. More precise than searching for the name, since it understands imports, \
+        methods and shadowing."
+    }
+
+    async fn run(&self, input: GotoDefinitionInput) -> ToolResult {
+        let root = match self.workspace.root() {
+            Ok(root) => root,
+            Err(message) => return ToolResult::error(message),
+        };
+        let path = match self.workspace.resolve(&input.path) {
+            Ok(path) => path,
+            Err(message) => return ToolResult::error(message),
+        };
+        if input.line == 0 || input.column == 0 {
+            return ToolResult::error("line and column start from 1");
+        }
+
+        let document = match self.language_servers.open(&root, &path).await {
+            Ok(document) => document,
+            Err(message) => return ToolResult::error(message),
+        };
+        let result = document
+            .server
+            .request(
+                "textDocument/definition",
+                json!({
+                    "textDocument": { "uri": document.uri },
+                    "position": { "line": input.line - 1, "character": input.column - 1 }
+                }),
+            )
+            .await;
+
+        match result {
+            Ok(result) => {
+                let definitions = locations(&root, &result);
+                let content = if definitions.is_empty() {
+                    format!(
+                        "No definition found for the symbol at {}:{}:{}",
+                        input.path, input.line, input.column
+                    )
+                } else {
+                    definitions.join("\n")
+                };
+                ToolResult::success(ToolContent::String(content))
+            }
+            Err(message) => ToolResult::error(message),
+        }
+    }
+}
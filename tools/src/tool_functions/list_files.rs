@@ -1,67 +1,135 @@
-use crate::models::{Tool, ToolContent, ToolName, ToolResult};
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
 use async_trait::async_trait;
+use globset::Glob;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tokio::fs;
 
 /// Input parameters for the list_files tool
 #[derive(Deserialize, JsonSchema, Debug)]
 pub struct ListFilesInput {
     /// The directory path to list files from
     pub dir: String,
+    /// Only list entries whose name matches this glob, e.g. `*.rs`
+    pub pattern: Option<String>,
+    /// Include each entry's size, modification time and whether it's a directory, defaults to false
+    pub include_metadata: Option<bool>,
+}
+
+/// An entry in the listing
+#[derive(Debug, Serialize)]
+struct FileEntry {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    is_dir: Option<bool>,
+    /// Size in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    /// Last modified, in seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<u64>,
 }
 
 /// Tool for listing all files in a directory
-#[derive(Debug, Serialize, Clone)]
-pub struct ListFilesTool;
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ListFilesTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
 
 #[async_trait]
-impl Tool<ListFilesInput> for ListFilesTool {
+impl TypedTool for ListFilesTool {
+    type Input = ListFilesInput;
+
     fn title(&self) -> ToolName {
-        ToolName::ListFiles
+        ToolName::from("list_files")
     }
 
     fn description(&self) -> &'static str {
-        "Lists all files in the specified directory. Best practice is to provide an absolute path to avoid \
-        ambiguity. This tool does not recursively list subdirectories - use the tree tool for that purpose. \
-        Verify the directory exists before calling this tool."
+        "Lists all files in the specified directory as a JSON array of entries. Best practice is to provide \
+        an absolute path to avoid ambiguity. This tool does not recursively list subdirectories - use the \
+        tree tool for that purpose. Verify the directory exists before calling this tool. Use `pattern` to \
+        filter by name, and `include_metadata` to get each entry's size in bytes, modification time in \
+        seconds since the Unix epoch and whether it is a directory."
     }
 
     async fn run(&self, input: ListFilesInput) -> ToolResult {
-        match fs::read_dir(&input.dir) {
-            Ok(entries) => {
+        let matcher = match input.pattern.as_deref().map(Glob::new).transpose() {
+            Ok(glob) => glob.map(|glob| glob.compile_matcher()),
+            Err(e) => {
+                return ToolResult::error(format!(
+                    "Invalid pattern '{}': {}",
+                    input.pattern.unwrap_or_default(),
+                    e
+                ));
+            }
+        };
+        let include_metadata = input.include_metadata.unwrap_or(false);
+
+        let dir = match self.workspace.resolve(&input.dir) {
+            Ok(dir) => dir,
+            Err(message) => {
+                return ToolResult::error(message);
+            }
+        };
+
+        match fs::read_dir(&dir).await {
+            Ok(mut entries) => {
                 let mut files = Vec::new();
-                for entry in entries {
-                    match entry {
-                        Ok(entry) => {
-                            let path = entry.path();
-                            if let Some(path_str) = path.to_str() {
-                                files.push(path_str.to_owned());
+                loop {
+                    match entries.next_entry().await {
+                        Ok(Some(entry)) => {
+                            if let Some(matcher) = &matcher {
+                                if !matcher.is_match(entry.file_name()) {
+                                    continue;
+                                }
                             }
+
+                            // Show paths the way they were asked for, rather than resolved
+                            let path = Path::new(&input.dir).join(entry.file_name());
+                            let Some(path_str) = path.to_str() else {
+                                continue;
+                            };
+
+                            let metadata = match include_metadata {
+                                true => entry.metadata().await.ok(),
+                                false => None,
+                            };
+                            files.push(FileEntry {
+                                path: path_str.to_owned(),
+                                is_dir: metadata.as_ref().map(|metadata| metadata.is_dir()),
+                                size: metadata.as_ref().map(|metadata| metadata.len()),
+                                modified: metadata
+                                    .as_ref()
+                                    .and_then(|metadata| metadata.modified().ok())
+                                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                                    .map(|modified| modified.as_secs()),
+                            });
                         }
+                        Ok(None) => break,
                         Err(e) => {
-                            return ToolResult {
-                                is_error: true,
-                                content: ToolContent::String(format!(
-                                    "Failed to read directory entry: {}",
-                                    e
-                                )),
-                            };
+                            return ToolResult::error(format!(
+                                "Failed to read directory entry: {}",
+                                e
+                            ));
                         }
                     }
                 }
-                ToolResult {
-                    is_error: false,
-                    content: ToolContent::StringArray(files),
+
+                files.sort_by(|a, b| a.path.cmp(&b.path));
+
+                match serde_json::to_value(&files) {
+                    Ok(json) => ToolResult::success(ToolContent::Json(json)),
+                    Err(e) => {
+                        ToolResult::error(format!("Failed to serialize directory listing: {}", e))
+                    }
                 }
             }
-            Err(e) => ToolResult {
-                is_error: true,
-                content: ToolContent::String(format!(
-                    "Failed to read directory '{}': {}",
-                    input.dir, e
-                )),
-            },
+            Err(e) => ToolResult::error(format!("Failed to read directory '{}': {}", input.dir, e)),
         }
     }
 }
@@ -0,0 +1,94 @@
+use crate::mcp::McpClient;
+use crate::models::{Tool, ToolContent, ToolError, ToolName, ToolResult};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// A tool provided by an MCP server, called through the server's connection
+#[derive(Debug, Serialize, Clone)]
+pub struct McpTool {
+    /// The name offered to the model, the server's name and the tool's joined by `__`
+    pub name: ToolName,
+    /// The tool's name on its server
+    pub remote_name: String,
+    pub description: String,
+    /// JSON schema for the input, as the server gave it
+    pub input_schema: Value,
+    #[serde(skip)]
+    pub(crate) client: Arc<McpClient>,
+}
+
+#[async_trait]
+impl Tool for McpTool {
+    fn title(&self) -> ToolName {
+        self.name.clone()
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn input_schema(&self) -> Result<String, ToolError> {
+        let properties = self
+            .input_schema
+            .get("properties")
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+        let required = self
+            .input_schema
+            .get("required")
+            .cloned()
+            .unwrap_or_else(|| json!([]));
+        let filtered = json!({
+            "type": "object",
+            "properties": properties,
+            "required": required
+        });
+
+        serde_json::to_string(&filtered).map_err(ToolError::InputSchemaSerializationError)
+    }
+
+    async fn call(&self, input: Value) -> ToolResult {
+        let result = self
+            .client
+            .request(
+                "tools/call",
+                json!({ "name": self.remote_name, "arguments": input }),
+            )
+            .await;
+
+        match result {
+            Ok(result) => ToolResult {
+                is_error: result["isError"].as_bool().unwrap_or(false),
+                content: ToolContent::String(content_text(&result["content"])),
+                exit_code: None,
+                model_spend: None,
+            },
+            Err(message) => ToolResult::error(format!("{}: {}", self.name, message)),
+        }
+    }
+}
+
+/// Render the content of a tool call result as text, listing anything else by type
+fn content_text(content: &Value) -> String {
+    content
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|item| match item["type"].as_str() {
+            Some("text") => item["text"].as_str().unwrap_or_default().to_string(),
+            Some("resource") => match item["resource"]["text"].as_str() {
+                Some(text) => text.to_string(),
+                None => format!("({} resource)", item["resource"]["uri"]),
+            },
+            Some(kind) => format!("({} content)", kind),
+            None => "(unknown content)".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
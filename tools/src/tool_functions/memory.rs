@@ -0,0 +1,156 @@
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::{create_aria_dir, Workspace};
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use tokio::fs;
+
+/// Directory that notes are stored in, one Markdown file per note
+const MEMORY_DIR: &str = ".aria/memory";
+
+/// Input parameters for the memory tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct MemoryInput {
+    /// What to do: `list` the saved notes, `read` a note, `save` a note, replacing any with the same
+    /// name, or `delete` a note
+    pub action: String,
+    /// The name of the note, made of letters, numbers, `-` and `_`, required except for `list`
+    pub name: Option<String>,
+    /// The text of the note, required for `save`
+    pub content: Option<String>,
+}
+
+/// Tool for keeping notes about the project that last between sessions
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct MemoryTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl TypedTool for MemoryTool {
+    type Input = MemoryInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("memory")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Stores and retrieves named notes about this project, which are kept in `.aria/memory` and last \
+        across turns and sessions. List the notes at the start of a task and read any that look relevant. \
+        Save a note when you discover something worth remembering next time, such as how to build and \
+        test the project, its conventions, or where important code lives. Keep notes short and update \
+        them rather than saving near-duplicates."
+    }
+
+    async fn run(&self, input: MemoryInput) -> ToolResult {
+        let dir = match self.workspace.resolve(MEMORY_DIR) {
+            Ok(dir) => dir,
+            Err(message) => return ToolResult::error(message),
+        };
+
+        if input.action == "list" {
+            return list(dir).await;
+        }
+
+        let name = match input.name.as_deref() {
+            Some(name) if is_valid_name(name) => name,
+            Some(name) => {
+                return ToolResult::error(format!(
+                    "Invalid note name '{}', use only letters, numbers, '-' and '_'",
+                    name
+                ));
+            }
+            None => {
+                return ToolResult::error(format!("name is required to {} a note", input.action))
+            }
+        };
+        let path = dir.join(format!("{}.md", name));
+
+        match input.action.as_str() {
+            "read" => match fs::read_to_string(&path).await {
+                Ok(content) => ToolResult::success(ToolContent::String(content)),
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    ToolResult::error(format!("There is no note named '{}'", name))
+                }
+                Err(e) => ToolResult::error(format!("Failed to read note '{}': {}", name, e)),
+            },
+            "save" => {
+                let Some(content) = &input.content else {
+                    return ToolResult::error("content is required to save a note");
+                };
+                if let Err(e) = create_aria_dir(&dir) {
+                    return ToolResult::error(format!(
+                        "Failed to create directory '{}': {}",
+                        MEMORY_DIR, e
+                    ));
+                }
+                match fs::write(&path, content).await {
+                    Ok(_) => {
+                        ToolResult::success(ToolContent::String(format!("Saved note '{}'", name)))
+                    }
+                    Err(e) => ToolResult::error(format!("Failed to save note '{}': {}", name, e)),
+                }
+            }
+            "delete" => match fs::remove_file(&path).await {
+                Ok(_) => {
+                    ToolResult::success(ToolContent::String(format!("Deleted note '{}'", name)))
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    ToolResult::error(format!("There is no note named '{}'", name))
+                }
+                Err(e) => ToolResult::error(format!("Failed to delete note '{}': {}", name, e)),
+            },
+            action => ToolResult::error(format!(
+                "Invalid action '{}', expected list, read, save or delete",
+                action
+            )),
+        }
+    }
+}
+
+/// The names of the saved notes, one per line
+async fn list(dir: PathBuf) -> ToolResult {
+    let mut entries = match fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return ToolResult::success(ToolContent::String(
+                "No notes have been saved yet".to_string(),
+            ));
+        }
+        Err(e) => return ToolResult::error(format!("Failed to list notes: {}", e)),
+    };
+
+    let mut names = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let name = path.file_stem().and_then(|name| name.to_str());
+        if let (Some(name), Some("md")) = (name, path.extension().and_then(|ext| ext.to_str())) {
+            if is_valid_name(name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+
+    ToolResult::success(ToolContent::String(if names.is_empty() {
+        "No notes have been saved yet".to_string()
+    } else {
+        names.join("\n")
+    }))
+}
+
+/// Names are used as file names, so they can't contain separators or dots
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
@@ -1,5 +1,33 @@
+pub mod custom;
+pub mod delete_file;
+pub mod diagnostics;
+pub mod docker_exec;
+pub mod docker_list;
+pub mod docker_logs;
+pub mod edit_file;
+pub mod edit_notebook;
+pub mod estimate_tokens;
+pub mod find_references;
+pub mod git_branch;
+pub mod git_commit;
+pub mod git_diff;
+pub mod git_log;
+pub mod git_status;
+pub mod goto_definition;
 pub mod list_files;
+pub mod mcp;
+pub mod memory;
+pub mod move_file;
 pub mod read_file;
+pub mod read_files;
+pub mod read_notebook;
+pub mod read_tool_output;
+pub mod respond;
 pub mod run_command;
+pub mod run_tests;
+pub mod search;
+#[cfg(feature = "sql")]
+pub mod sql_query;
 pub mod tree;
+pub mod view_image;
 pub mod write_file;
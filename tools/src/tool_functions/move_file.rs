@@ -0,0 +1,112 @@
+use crate::backup::Backups;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// Input parameters for the move_file tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct MoveFileInput {
+    /// The path of the file or directory to move
+    pub src: String,
+    /// The path to move it to
+    pub dest: String,
+    /// Replace the destination if it already exists, defaults to false
+    pub overwrite: Option<bool>,
+}
+
+/// Tool for moving and renaming files
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct MoveFileTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+    /// Where the original contents of moved and overwritten files are saved
+    #[serde(skip)]
+    pub backups: Backups,
+}
+
+#[async_trait]
+impl TypedTool for MoveFileTool {
+    type Input = MoveFileInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("move_file")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Moves or renames a file or directory from `src` to `dest`, creating any parent directories of \
+        `dest` that don't exist. File permissions are preserved. Use this instead of reading, writing and \
+        deleting a file to rename it. Fails if `dest` already exists unless `overwrite` is set, and a \
+        directory is never overwritten."
+    }
+
+    async fn run(&self, input: MoveFileInput) -> ToolResult {
+        let src = match self.workspace.resolve(&input.src) {
+            Ok(src) => src,
+            Err(message) => return ToolResult::error(message),
+        };
+        let dest = match self.workspace.resolve(&input.dest) {
+            Ok(dest) => dest,
+            Err(message) => return ToolResult::error(message),
+        };
+
+        if let Err(e) = fs::metadata(&src).await {
+            return ToolResult::error(format!("Failed to move '{}': {}", input.src, e));
+        }
+
+        if let Ok(metadata) = fs::metadata(&dest).await {
+            if metadata.is_dir() {
+                return ToolResult::error(format!(
+                    "'{}' is a directory, give the full destination path including the file name",
+                    input.dest
+                ));
+            }
+            if !input.overwrite.unwrap_or(false) {
+                return ToolResult::error(format!(
+                    "'{}' already exists, set overwrite to replace it",
+                    input.dest
+                ));
+            }
+        }
+
+        if let Some(parent) = dest.parent() {
+            if let Err(e) = fs::create_dir_all(parent).await {
+                return ToolResult::error(format!(
+                    "Failed to create directory '{}': {}",
+                    parent.display(),
+                    e
+                ));
+            }
+        }
+
+        // Directories aren't backed up, only the files moved on their own can be undone
+        if fs::metadata(&src)
+            .await
+            .is_ok_and(|metadata| metadata.is_file())
+        {
+            for path in [&src, &dest] {
+                if let Err(message) = self.backups.snapshot_async(path).await {
+                    return ToolResult::error(message);
+                }
+            }
+        }
+
+        match fs::rename(&src, &dest).await {
+            Ok(_) => ToolResult::success(ToolContent::String(format!(
+                "Successfully moved '{}' to '{}'",
+                input.src, input.dest
+            ))),
+            Err(e) => ToolResult::error(format!(
+                "Failed to move '{}' to '{}': {}",
+                input.src, input.dest, e
+            )),
+        }
+    }
+}
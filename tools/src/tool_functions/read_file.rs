@@ -1,45 +1,87 @@
-use crate::models::{Tool, ToolContent, ToolName, ToolResult};
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
 use async_trait::async_trait;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use tokio::fs;
 
 /// Input parameters for the read_file tool
 #[derive(Deserialize, JsonSchema, Debug)]
 pub struct ReadFileInput {
     /// The path of the file to read
     pub path: String,
+    /// The first line to read, numbered from 1, defaults to the start of the file
+    pub start_line: Option<usize>,
+    /// The last line to read, inclusive, defaults to the end of the file
+    pub end_line: Option<usize>,
 }
 
 /// Tool for reading file contents
-#[derive(Debug, Serialize, Clone)]
-pub struct ReadFileTool;
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ReadFileTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
 
 #[async_trait]
-impl Tool<ReadFileInput> for ReadFileTool {
+impl TypedTool for ReadFileTool {
+    type Input = ReadFileInput;
+
     fn title(&self) -> ToolName {
-        ToolName::ReadFile
+        ToolName::from("read_file")
     }
 
     fn description(&self) -> &'static str {
         "Reads the content of a file at the specified path. Use absolute paths when possible to avoid \
         ambiguity. Always verify that the file exists before trying to read it. This tool is best used \
-        for text files - binary files may not render correctly."
+        for text files - binary files may not render correctly. Each line is prefixed with its line \
+        number and a tab, which are not part of the file. For large files, read a region at a time \
+        with `start_line` and `end_line`."
     }
 
     async fn run(&self, input: ReadFileInput) -> ToolResult {
-        match fs::read_to_string(&input.path) {
-            Ok(contents) => ToolResult {
-                is_error: false,
-                content: ToolContent::String(contents),
-            },
-            Err(e) => ToolResult {
-                is_error: true,
-                content: ToolContent::String(format!(
-                    "Failed to read file '{}': {}",
-                    input.path, e
-                )),
-            },
+        let path = match self.workspace.resolve(&input.path) {
+            Ok(path) => path,
+            Err(message) => {
+                return ToolResult::error(message);
+            }
+        };
+
+        let contents = match fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(e) => {
+                return ToolResult::error(format!("Failed to read file '{}': {}", input.path, e));
+            }
+        };
+
+        let lines: Vec<&str> = contents.lines().collect();
+        let total = lines.len();
+        let start = input.start_line.unwrap_or(1).max(1);
+        let end = input.end_line.unwrap_or(total).min(total);
+
+        if total > 0 && (start > total || start > end) {
+            return ToolResult::error(format!(
+                "Invalid line range {}-{} for '{}', which has {} lines",
+                start,
+                input.end_line.unwrap_or(total),
+                input.path,
+                total
+            ));
         }
+
+        let mut numbered: Vec<String> = lines
+            .iter()
+            .enumerate()
+            .take(end)
+            .skip(start - 1)
+            .map(|(index, line)| format!("{:>6}\t{}", index + 1, line))
+            .collect();
+
+        if start > 1 || end < total {
+            numbered.push(format!("(Showing lines {}-{} of {})", start, end, total));
+        }
+
+        ToolResult::success(ToolContent::String(numbered.join("\n")))
     }
 }
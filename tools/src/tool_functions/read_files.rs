@@ -0,0 +1,132 @@
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// The most of a single file shown by default
+pub const DEFAULT_MAX_FILE_BYTES: usize = 20_000;
+
+/// The most of all the files together shown by default
+pub const DEFAULT_MAX_TOTAL_BYTES: usize = 60_000;
+
+/// Input parameters for the read_files tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct ReadFilesInput {
+    /// The paths of the files to read
+    pub paths: Vec<String>,
+}
+
+/// Tool for reading several files in one call
+#[derive(Debug, Serialize, Clone)]
+pub struct ReadFilesTool {
+    /// A file longer than this is cut short
+    pub max_file_bytes: usize,
+    /// Files after this much has been read are skipped
+    pub max_total_bytes: usize,
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+impl Default for ReadFilesTool {
+    fn default() -> Self {
+        ReadFilesTool {
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+            max_total_bytes: DEFAULT_MAX_TOTAL_BYTES,
+            workspace: Workspace::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl TypedTool for ReadFilesTool {
+    type Input = ReadFilesInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("read_files")
+    }
+
+    fn description(&self) -> &'static str {
+        "Reads several files in one call, e.g. a handful of related modules, which is quicker than \
+        reading them one at a time. Each file is shown under a header with its path, with every line \
+        prefixed by its line number and a tab like read_file. Long files are cut short and files past \
+        the total size limit are skipped, read those with read_file and its line range."
+    }
+
+    async fn run(&self, input: ReadFilesInput) -> ToolResult {
+        if input.paths.is_empty() {
+            return ToolResult::error("No paths given");
+        }
+
+        let mut sections = Vec::new();
+        let mut total = 0;
+        let mut failed = 0;
+        for path in &input.paths {
+            if total >= self.max_total_bytes {
+                sections.push(format!(
+                    "==> {} <==\n(Skipped, the total size limit of {} bytes was reached)",
+                    path, self.max_total_bytes
+                ));
+                continue;
+            }
+
+            let contents = match self.workspace.resolve(path) {
+                Ok(resolved) => fs::read_to_string(resolved)
+                    .await
+                    .map_err(|e| format!("Failed to read file: {}", e)),
+                Err(message) => Err(message),
+            };
+            let contents = match contents {
+                Ok(contents) => contents,
+                Err(message) => {
+                    failed += 1;
+                    sections.push(format!("==> {} <==\n{}", path, message));
+                    continue;
+                }
+            };
+
+            let limit = self.max_file_bytes.min(self.max_total_bytes - total);
+            let (section, shown) = numbered(&contents, limit);
+            total += shown;
+            sections.push(format!("==> {} <==\n{}", path, section));
+        }
+
+        ToolResult {
+            // Only an error when nothing could be read
+            is_error: failed == input.paths.len(),
+            content: ToolContent::String(sections.join("\n\n")),
+            exit_code: None,
+            model_spend: None,
+        }
+    }
+}
+
+/// The file's lines numbered like read_file, as many whole lines as fit in `limit` bytes,
+/// and how many bytes of the file were shown
+fn numbered(contents: &str, limit: usize) -> (String, usize) {
+    let total_lines = contents.lines().count();
+    let mut shown = 0;
+    let mut numbered = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        if shown + line.len() + 1 > limit {
+            break;
+        }
+        shown += line.len() + 1;
+        numbered.push(format!("{:>6}\t{}", index + 1, line));
+    }
+
+    if numbered.is_empty() && total_lines > 0 {
+        numbered.push("(Not shown, it's over the size limit, read it with read_file)".to_string());
+    } else if numbered.len() < total_lines {
+        numbered.push(format!(
+            "(Showing lines 1-{} of {}, read the rest with read_file)",
+            numbered.len(),
+            total_lines
+        ));
+    }
+
+    (numbered.join("\n"), shown)
+}
@@ -0,0 +1,118 @@
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::notebook::{source_text, Notebook};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Each cell's outputs are cut off after this many characters
+const MAX_OUTPUT_CHARS: usize = 2_000;
+
+/// Input parameters for the read_notebook tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct ReadNotebookInput {
+    /// The path of the `.ipynb` file to read
+    pub path: String,
+    /// Include the outputs of code cells, defaults to true
+    pub include_outputs: Option<bool>,
+}
+
+/// Tool for reading Jupyter notebooks cell by cell
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ReadNotebookTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl TypedTool for ReadNotebookTool {
+    type Input = ReadNotebookInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("read_notebook")
+    }
+
+    fn description(&self) -> &'static str {
+        "Reads a Jupyter notebook (`.ipynb`) and returns its cells in order, each headed by its index and \
+        type, followed by the outputs of code cells as text. Images and other rich outputs are listed by \
+        type only. Use this instead of read_file for notebooks, and use the cell indexes with \
+        edit_notebook."
+    }
+
+    async fn run(&self, input: ReadNotebookInput) -> ToolResult {
+        let path = match self.workspace.resolve(&input.path) {
+            Ok(path) => path,
+            Err(message) => return ToolResult::error(message),
+        };
+        let notebook = match Notebook::load(&path, &input.path).await {
+            Ok(notebook) => notebook,
+            Err(message) => return ToolResult::error(message),
+        };
+        let include_outputs = input.include_outputs.unwrap_or(true);
+
+        if notebook.cells().is_empty() {
+            return ToolResult::success(ToolContent::String(format!(
+                "'{}' has no cells",
+                input.path
+            )));
+        }
+
+        let mut sections = Vec::new();
+        for (index, cell) in notebook.cells().iter().enumerate() {
+            let cell_type = cell["cell_type"].as_str().unwrap_or("unknown");
+            let header = match cell["execution_count"].as_u64() {
+                Some(count) => format!("[{}] {} (execution_count {})", index, cell_type, count),
+                None => format!("[{}] {}", index, cell_type),
+            };
+            sections.push(format!("{}\n{}", header, source_text(&cell["source"])));
+
+            let outputs = cell["outputs"]
+                .as_array()
+                .filter(|outputs| !outputs.is_empty());
+            if let (true, Some(outputs)) = (include_outputs, outputs) {
+                let text: Vec<String> = outputs
+                    .iter()
+                    .map(|output| output_text(output).trim_end().to_string())
+                    .collect();
+                sections.push(format!("[{}] output\n{}", index, truncate(text.join("\n"))));
+            }
+        }
+
+        ToolResult::success(ToolContent::String(sections.join("\n\n")))
+    }
+}
+
+/// Render a cell output as text
+fn output_text(output: &Value) -> String {
+    match output["output_type"].as_str() {
+        Some("stream") => source_text(&output["text"]),
+        Some("error") => format!(
+            "{}: {}",
+            output["ename"].as_str().unwrap_or("Error"),
+            output["evalue"].as_str().unwrap_or_default()
+        ),
+        Some("execute_result") | Some("display_data") => {
+            let data = &output["data"];
+            match data.get("text/plain") {
+                Some(text) => source_text(text),
+                None => {
+                    let types: Vec<&str> = data
+                        .as_object()
+                        .map(|data| data.keys().map(String::as_str).collect())
+                        .unwrap_or_default();
+                    format!("({} output)", types.join(", "))
+                }
+            }
+        }
+        _ => "(unknown output)".to_string(),
+    }
+}
+
+fn truncate(text: String) -> String {
+    match text.char_indices().nth(MAX_OUTPUT_CHARS) {
+        Some((end, _)) => format!("{}\n(output truncated)", &text[..end]),
+        None => text,
+    }
+}
@@ -0,0 +1,42 @@
+use crate::models::{ToolName, ToolResult, TypedTool};
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Input parameters for the read_tool_output tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct ReadToolOutputInput {
+    /// The id given in the truncation notice of the output to read
+    pub id: String,
+    /// The byte to start reading from, as given in the truncation notice
+    pub offset: Option<usize>,
+}
+
+/// Tool for reading the rest of a tool output that was too long to show in full
+///
+/// The outputs are kept by the agent, which answers calls to this tool itself. Running the
+/// tool directly only reports that.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ReadToolOutputTool;
+
+#[async_trait]
+impl TypedTool for ReadToolOutputTool {
+    type Input = ReadToolOutputInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("read_tool_output")
+    }
+
+    fn description(&self) -> &'static str {
+        "Reads the next page of a tool output that was truncated. Pass the id and offset from the \
+        truncation notice, and keep following the notices until you have what you need. Prefer narrowing \
+        the original call when only part of the output is relevant."
+    }
+
+    async fn run(&self, input: ReadToolOutputInput) -> ToolResult {
+        ToolResult::error(format!(
+            "No output with id {} is available, outputs are only kept while the agent runs",
+            input.id
+        ))
+    }
+}
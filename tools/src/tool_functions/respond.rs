@@ -0,0 +1,68 @@
+use crate::models::{Tool, ToolContent, ToolError, ToolName, ToolResult};
+use async_trait::async_trait;
+use schemars::{schema_for, JsonSchema};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Tool the model calls to deliver its final answer as structured data
+///
+/// Unlike the other tools the input schema is not fixed, it is generated from
+/// the type the caller wants the response deserialized into.
+#[derive(Debug, Serialize, Clone)]
+pub struct RespondTool {
+    schema: serde_json::Value,
+    /// Checks the input can be deserialized into the type the schema describes
+    #[serde(skip)]
+    validate: fn(serde_json::Value) -> Result<(), String>,
+}
+
+impl RespondTool {
+    /// Create a respond tool whose input schema describes `T`
+    pub fn for_type<T: JsonSchema + DeserializeOwned>() -> Result<Self, ToolError> {
+        let mut schema = serde_json::to_value(schema_for!(T))
+            .map_err(ToolError::InputSchemaSerializationError)?;
+
+        // The root metadata is noise to the model, the definitions are kept so `$ref`s resolve
+        if let Some(obj) = schema.as_object_mut() {
+            obj.remove("$schema");
+            obj.remove("title");
+        }
+
+        Ok(RespondTool {
+            schema,
+            validate: |input| {
+                serde_json::from_value::<T>(input)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            },
+        })
+    }
+}
+
+#[async_trait]
+impl Tool for RespondTool {
+    fn title(&self) -> ToolName {
+        ToolName::from("respond")
+    }
+
+    fn description(&self) -> &'static str {
+        "Delivers your final answer to the user as structured data. Call this tool exactly once, when you \
+        have gathered everything you need, with an input that conforms to the schema. Do not reply with \
+        plain text, the answer is only read from the input of this tool."
+    }
+
+    fn input_schema(&self) -> Result<String, ToolError> {
+        serde_json::to_string(&self.schema).map_err(ToolError::InputSchemaSerializationError)
+    }
+
+    async fn call(&self, input: serde_json::Value) -> ToolResult {
+        match (self.validate)(input) {
+            Ok(()) => ToolResult::success(ToolContent::String("Response recorded".to_string())),
+            Err(e) => ToolResult::error(format!(
+                "Invalid response, it doesn't match the schema: {}. Call respond again with \
+                    a corrected input",
+                e
+            )),
+        }
+    }
+}
@@ -1,76 +1,164 @@
-use crate::models::{Tool, ToolContent, ToolName, ToolResult};
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::policy::CommandPolicy;
+use crate::process::{output_with_timeout, shell_command, ProcessError};
+use crate::workspace::Workspace;
 use async_trait::async_trait;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// How long a command may run before it is killed, when neither the input nor the
+/// tool sets a timeout
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
 
 /// Input parameters for the run_command tool
 #[derive(Deserialize, JsonSchema, Debug)]
 pub struct RunCommandInput {
-    /// The command to run
+    /// The command to run, or the whole command line when `shell` is set
     pub cmd: String,
     /// The arguments to pass to the command
+    #[serde(default)]
     pub args: Vec<String>,
+    /// Run `cmd` through the system shell, so it can use pipes, redirects and `&&`, defaults to false
+    pub shell: Option<bool>,
+    /// Kill the command if it runs for longer than this many seconds, defaults to 120
+    pub timeout_secs: Option<u64>,
+    /// The directory to run the command in, which must be inside the workspace, defaults to the
+    /// workspace root
+    pub cwd: Option<String>,
+    /// Environment variables to set for the command, in addition to the inherited ones
+    pub env: Option<HashMap<String, String>>,
 }
 
 /// Tool for executing shell commands
 #[derive(Debug, Serialize, Clone)]
-pub struct RunCommandTool;
+pub struct RunCommandTool {
+    /// How long a command may run when its input doesn't set a timeout
+    pub default_timeout: Duration,
+    /// Which commands may run, any command may when it's empty
+    #[serde(skip)]
+    pub policy: CommandPolicy,
+    /// The directory commands run in, and that `cwd` is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+impl Default for RunCommandTool {
+    fn default() -> Self {
+        RunCommandTool {
+            default_timeout: DEFAULT_TIMEOUT,
+            policy: CommandPolicy::default(),
+            workspace: Workspace::default(),
+        }
+    }
+}
 
 #[async_trait]
-impl Tool<RunCommandInput> for RunCommandTool {
+impl TypedTool for RunCommandTool {
+    type Input = RunCommandInput;
+
     fn title(&self) -> ToolName {
-        ToolName::RunCommand
+        ToolName::from("run_command")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
     }
 
     fn description(&self) -> &'static str {
         "Executes a shell command with the specified arguments. The 'cmd' parameter is a string (like 'ls' or 'git'), \
         and 'args' is a list of strings for the command arguments (like ['-l', '/tmp']). Use with caution as shell \
         commands can be potentially dangerous. Always validate and sanitize inputs before passing them to this tool. \
-        Avoid commands that require interactive input as this tool doesn't handle stdin interactions."
+        Avoid commands that require interactive input as this tool doesn't handle stdin interactions. \
+        Commands are killed after a timeout, 120 seconds unless configured otherwise; set 'timeout_secs' \
+        for commands expected to take longer, like a full test suite. Use 'cwd' to run in a subdirectory \
+        of the workspace rather than prefixing the command with cd, and 'env' to set environment variables. \
+        Set 'shell' to run 'cmd' as a full command line through sh (cmd on Windows) when it needs pipes, \
+        redirects or chaining, e.g. 'cargo test 2>&1 | tail -n 50'; any 'args' are appended to the \
+        command line as is. Commands may be refused by the user's command policy; if so, don't try to \
+        work around it."
     }
 
     async fn run(&self, input: RunCommandInput) -> ToolResult {
-        let output = match Command::new(&input.cmd).args(&input.args).output() {
+        let timeout = input
+            .timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(self.default_timeout);
+
+        let command_line = std::iter::once(input.cmd.as_str())
+            .chain(input.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let checked = match input.shell.unwrap_or(false) {
+            true => self.policy.check_shell(&command_line),
+            false => self.policy.check(&command_line),
+        };
+        if let Err(message) = checked {
+            return ToolResult::error(message);
+        }
+
+        let mut command = if input.shell.unwrap_or(false) {
+            shell_command(&command_line)
+        } else {
+            let mut command = Command::new(&input.cmd);
+            command.args(&input.args);
+            command
+        };
+        let cwd = match &input.cwd {
+            Some(cwd) => self.workspace.resolve(cwd),
+            None => self.workspace.root(),
+        };
+        match cwd {
+            Ok(dir) if dir.is_dir() => command.current_dir(dir),
+            Ok(dir) => {
+                return ToolResult::error(format!(
+                    "Working directory '{}' is not a directory",
+                    dir.display()
+                ));
+            }
+            Err(message) => {
+                return ToolResult::error(message);
+            }
+        };
+        if let Some(env) = &input.env {
+            command.envs(env);
+        }
+
+        let output = match output_with_timeout(&mut command, timeout).await {
             Ok(output) => output,
-            Err(e) => {
-                return ToolResult {
-                    is_error: true,
-                    content: ToolContent::String(format!("Failed to execute command: {}", e)),
-                };
+            Err(ProcessError::Failed(e)) => {
+                return ToolResult::error(format!("Failed to execute command: {}", e));
+            }
+            Err(ProcessError::TimedOut) => {
+                return ToolResult::error(format!(
+                    "Command timed out after {} seconds and was killed. If it needs longer, \
+                        run it again with a larger timeout_secs",
+                    timeout.as_secs()
+                ));
             }
         };
 
         let stdout = match String::from_utf8(output.stdout) {
             Ok(stdout) => stdout,
             Err(e) => {
-                return ToolResult {
-                    is_error: true,
-                    content: ToolContent::String(format!("Failed to parse command output: {}", e)),
-                };
+                return ToolResult::error(format!("Failed to parse command output: {}", e));
             }
         };
 
         let stderr = match String::from_utf8(output.stderr) {
             Ok(stderr) => stderr,
             Err(e) => {
-                return ToolResult {
-                    is_error: true,
-                    content: ToolContent::String(format!("Failed to parse error output: {}", e)),
-                };
+                return ToolResult::error(format!("Failed to parse error output: {}", e));
             }
         };
 
-        if output.status.success() {
-            ToolResult {
-                is_error: false,
-                content: ToolContent::String(stdout),
-            }
+        let result = if output.status.success() {
+            ToolResult::success(ToolContent::String(stdout))
         } else {
-            ToolResult {
-                is_error: true,
-                content: ToolContent::String(format!("Command failed: {}", stderr)),
-            }
-        }
+            ToolResult::error(format!("Command failed: {}", stderr))
+        };
+        result.with_exit_code(output.status.code())
     }
 }
@@ -0,0 +1,367 @@
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::process::{output_with_timeout, ProcessError};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Test suites are killed after this long unless the input asks for longer
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How many lines from the end of the output are included in the result
+const OUTPUT_TAIL_LINES: usize = 50;
+
+/// Input parameters for the run_tests tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct RunTestsInput {
+    /// Only run tests whose names match this filter, defaults to running the whole suite
+    pub filter: Option<String>,
+    /// Kill the test run if it takes longer than this many seconds, defaults to 600
+    pub timeout_secs: Option<u64>,
+}
+
+/// The test runners the tool can detect
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Runner {
+    Cargo,
+    Npm,
+    Pytest,
+}
+
+/// The outcome of a test run
+#[derive(Debug, Serialize, Default)]
+struct TestSummary {
+    passed: u32,
+    failed: u32,
+    ignored: u32,
+    /// Names of the tests that failed
+    failures: Vec<String>,
+}
+
+/// What is returned to the model
+#[derive(Debug, Serialize)]
+struct TestReport {
+    runner: Runner,
+    command: String,
+    success: bool,
+    #[serde(flatten)]
+    summary: TestSummary,
+    /// The end of the output, where failure details are reported
+    output_tail: String,
+}
+
+/// Tool for running the project's test suite
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct RunTestsTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl TypedTool for RunTestsTool {
+    type Input = RunTestsInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("run_tests")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Runs the test suite of the project in the workspace, detecting whether it uses cargo, npm or \
+        pytest, and returns JSON with pass, fail and ignored counts, the names of failing tests and the end \
+        of the output. Use `filter` to rerun only the failing tests while fixing them. Prefer this over \
+        running tests with run_command."
+    }
+
+    async fn run(&self, input: RunTestsInput) -> ToolResult {
+        // A filter is passed as an argument, so one that looks like a flag would be taken as one
+        if let Some(filter) = &input.filter {
+            if filter.starts_with('-') {
+                return ToolResult::error(format!("Invalid filter '{}'", filter));
+            }
+        }
+
+        let root = match self.workspace.root() {
+            Ok(root) => root,
+            Err(message) => return ToolResult::error(message),
+        };
+
+        let Some(runner) = detect(&root) else {
+            return ToolResult::error(
+                "Couldn't detect a test runner, expected Cargo.toml, package.json, or a pytest \
+                configuration in the workspace root. Run the tests with run_command instead",
+            );
+        };
+
+        let (program, mut args) = match runner {
+            Runner::Cargo => ("cargo", vec!["test"]),
+            Runner::Npm => ("npm", vec!["test", "--"]),
+            Runner::Pytest => ("pytest", vec!["-rf"]),
+        };
+        if let Some(filter) = &input.filter {
+            match runner {
+                Runner::Cargo => args.push(filter),
+                Runner::Npm => args.extend(["-t", filter]),
+                Runner::Pytest => args.extend(["-k", filter]),
+            }
+        }
+        let command_line = std::iter::once(program)
+            .chain(args.iter().copied())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut command = Command::new(program);
+        command.args(&args).current_dir(&root);
+        let timeout = input
+            .timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TIMEOUT);
+
+        let output = match output_with_timeout(&mut command, timeout).await {
+            Ok(output) => output,
+            Err(ProcessError::Failed(e)) => {
+                return ToolResult::error(format!("Failed to run '{}': {}", command_line, e));
+            }
+            Err(ProcessError::TimedOut) => {
+                return ToolResult::error(format!(
+                    "'{}' timed out after {} seconds and was killed. Run fewer tests with filter, \
+                    or a larger timeout_secs",
+                    command_line,
+                    timeout.as_secs()
+                ));
+            }
+        };
+
+        let output_text = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let summary = match runner {
+            Runner::Cargo => parse_cargo(&output_text),
+            Runner::Npm => parse_jest(&output_text),
+            Runner::Pytest => parse_pytest(&output_text),
+        };
+        let lines: Vec<&str> = output_text.lines().collect();
+        let output_tail = lines[lines.len().saturating_sub(OUTPUT_TAIL_LINES)..].join("\n");
+
+        let report = TestReport {
+            runner,
+            command: command_line,
+            success: output.status.success(),
+            summary,
+            output_tail,
+        };
+
+        match serde_json::to_value(&report) {
+            Ok(json) => ToolResult {
+                is_error: !report.success,
+                content: ToolContent::Json(json),
+                exit_code: output.status.code(),
+                model_spend: None,
+            },
+            Err(e) => ToolResult::error(format!("Failed to serialize test results: {}", e)),
+        }
+    }
+}
+
+/// Work out which test runner a project uses from the files in its root
+fn detect(root: &Path) -> Option<Runner> {
+    if root.join("Cargo.toml").is_file() {
+        Some(Runner::Cargo)
+    } else if root.join("package.json").is_file() {
+        Some(Runner::Npm)
+    } else if [
+        "pytest.ini",
+        "pyproject.toml",
+        "setup.cfg",
+        "tox.ini",
+        "conftest.py",
+    ]
+    .iter()
+    .any(|file| root.join(file).is_file())
+    {
+        Some(Runner::Pytest)
+    } else {
+        None
+    }
+}
+
+/// Parse `cargo test` output, which has a result line per test binary, e.g.
+/// `test result: FAILED. 8 passed; 1 failed; 0 ignored; ...`
+fn parse_cargo(output: &str) -> TestSummary {
+    let mut summary = TestSummary::default();
+
+    for line in output.lines() {
+        if let Some(counts) = line.strip_prefix("test result: ") {
+            for part in counts.split([';', '.']) {
+                let mut words = part.split_whitespace();
+                let (Some(count), Some(kind)) = (words.next(), words.next()) else {
+                    continue;
+                };
+                let Ok(count) = count.parse::<u32>() else {
+                    continue;
+                };
+                match kind {
+                    "passed" => summary.passed += count,
+                    "failed" => summary.failed += count,
+                    "ignored" => summary.ignored += count,
+                    _ => {}
+                }
+            }
+        } else if let Some(name) = line
+            .strip_prefix("test ")
+            .and_then(|rest| rest.strip_suffix(" ... FAILED"))
+        {
+            summary.failures.push(name.to_string());
+        }
+    }
+
+    summary
+}
+
+/// Parse pytest output, which ends with a summary like `2 failed, 10 passed, 1 skipped in 0.52s`,
+/// with `-rf` listing failures as `FAILED tests/test_x.py::test_name - reason`
+fn parse_pytest(output: &str) -> TestSummary {
+    let mut summary = TestSummary::default();
+
+    for line in output.lines() {
+        if let Some(failure) = line.strip_prefix("FAILED ") {
+            let name = failure.split(" - ").next().unwrap_or(failure);
+            summary.failures.push(name.to_string());
+        } else if line.contains(" in ") && (line.contains("passed") || line.contains("failed")) {
+            let counts = line.trim_matches(|c: char| c == '=' || c.is_whitespace());
+            let counts = counts.split(" in ").next().unwrap_or(counts);
+            for part in counts.split(", ") {
+                let mut words = part.split_whitespace();
+                let (Some(count), Some(kind)) = (words.next(), words.next()) else {
+                    continue;
+                };
+                let Ok(count) = count.parse::<u32>() else {
+                    continue;
+                };
+                match kind {
+                    "passed" => summary.passed = count,
+                    "failed" | "error" | "errors" => summary.failed += count,
+                    "skipped" | "xfailed" => summary.ignored += count,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    summary
+}
+
+/// Parse Jest output, the usual runner behind `npm test`, which ends with a summary like
+/// `Tests:       1 failed, 2 skipped, 5 passed, 8 total` and marks failing tests with `●`
+fn parse_jest(output: &str) -> TestSummary {
+    let mut summary = TestSummary::default();
+
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(counts) = line.strip_prefix("Tests:") {
+            for part in counts.split(',') {
+                let mut words = part.split_whitespace();
+                let (Some(count), Some(kind)) = (words.next(), words.next()) else {
+                    continue;
+                };
+                let Ok(count) = count.parse::<u32>() else {
+                    continue;
+                };
+                match kind {
+                    "passed" => summary.passed = count,
+                    "failed" => summary.failed = count,
+                    "skipped" | "todo" => summary.ignored += count,
+                    _ => {}
+                }
+            }
+        } else if let Some(name) = line.strip_prefix("● ") {
+            // Suites that fail to run are reported the same way
+            if !name.starts_with("Test suite failed to run")
+                && !summary.failures.iter().any(|failure| failure == name)
+            {
+                summary.failures.push(name.to_string());
+            }
+        }
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cargo_output() {
+        let output = "\
+running 3 tests
+test parser::tests::reads_numbers ... ok
+test parser::tests::reads_strings ... FAILED
+test parser::tests::slow ... ignored
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.01s
+
+running 2 tests
+test api::tests::lists ... ok
+test api::tests::gets ... ok
+
+test result: ok. 2 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+";
+        let summary = parse_cargo(output);
+        assert_eq!((summary.passed, summary.failed, summary.ignored), (3, 1, 1));
+        assert_eq!(summary.failures, ["parser::tests::reads_strings"]);
+    }
+
+    #[test]
+    fn parses_pytest_output() {
+        let output = "\
+tests/test_api.py ..F.s
+tests/test_db.py E
+=========================== short test summary info ============================
+FAILED tests/test_api.py::test_gets - assert 1 == 2
+==================== 1 failed, 3 passed, 1 skipped, 1 error in 0.52s ====================
+";
+        let summary = parse_pytest(output);
+        assert_eq!((summary.passed, summary.failed, summary.ignored), (3, 2, 1));
+        assert_eq!(summary.failures, ["tests/test_api.py::test_gets"]);
+    }
+
+    #[test]
+    fn parses_jest_output() {
+        let output = "\
+ FAIL  src/sum.test.js
+  ● sum › adds negative numbers
+
+    expect(received).toBe(expected)
+
+  ● sum › adds negative numbers
+
+Test Suites: 1 failed, 1 passed, 2 total
+Tests:       1 failed, 2 skipped, 5 passed, 8 total
+";
+        let summary = parse_jest(output);
+        assert_eq!((summary.passed, summary.failed, summary.ignored), (5, 1, 2));
+        assert_eq!(summary.failures, ["sum › adds negative numbers"]);
+    }
+
+    #[tokio::test]
+    async fn refuses_filters_that_look_like_flags() {
+        let result = RunTestsTool::default()
+            .run(RunTestsInput {
+                filter: Some("--no-run".to_string()),
+                timeout_secs: None,
+            })
+            .await;
+        assert!(result.is_error);
+    }
+}
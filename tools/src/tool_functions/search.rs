@@ -0,0 +1,225 @@
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::walk::walk_parallel;
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use ignore::WalkBuilder;
+use regex::{Regex, RegexBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const DEFAULT_CONTEXT_LINES: usize = 2;
+const DEFAULT_MAX_MATCHES: usize = 100;
+
+/// Input parameters for the search tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct SearchInput {
+    /// The regular expression to search for
+    pub pattern: String,
+    /// The file or directory to search, defaults to the current directory
+    pub path: Option<String>,
+    /// Lines of context to show before and after each match, defaults to 2
+    pub context_lines: Option<usize>,
+    /// The most matches to return, defaults to 100
+    pub max_matches: Option<usize>,
+    /// Match regardless of case, defaults to false
+    pub case_insensitive: Option<bool>,
+}
+
+/// A line matching the pattern, as returned to the model
+#[derive(Debug, Serialize)]
+struct Match {
+    path: String,
+    /// The line number, counting from 1
+    line: usize,
+    text: String,
+    /// The lines just before the match
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    before: Vec<String>,
+    /// The lines just after the match
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    after: Vec<String>,
+}
+
+/// Tool for searching file contents with a regular expression
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SearchTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl TypedTool for SearchTool {
+    type Input = SearchInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("search")
+    }
+
+    fn description(&self) -> &'static str {
+        "Searches file contents for a regular expression, recursively from the specified path. Files \
+        ignored by .gitignore, hidden files and binary files are skipped. Returns JSON with a `path`, \
+        `line` and `text` for each match, and the context lines `before` and `after` it. Prefer this \
+        over running grep with run_command. Results are capped, `truncated` is true when the cap is \
+        reached, so narrow the pattern or path to see the rest."
+    }
+
+    async fn run(&self, input: SearchInput) -> ToolResult {
+        let regex = match RegexBuilder::new(&input.pattern)
+            .case_insensitive(input.case_insensitive.unwrap_or(false))
+            .build()
+        {
+            Ok(regex) => regex,
+            Err(e) => {
+                return ToolResult::error(format!("Invalid pattern '{}': {}", input.pattern, e));
+            }
+        };
+
+        let path = input.path.unwrap_or_else(|| ".".to_string());
+        let context_lines = input.context_lines.unwrap_or(DEFAULT_CONTEXT_LINES);
+        let max_matches = input.max_matches.unwrap_or(DEFAULT_MAX_MATCHES);
+
+        let resolved = match self.workspace.resolve(&path) {
+            Ok(resolved) => resolved,
+            Err(message) => {
+                return ToolResult::error(message);
+            }
+        };
+
+        if let Err(e) = tokio::fs::metadata(&resolved).await {
+            return ToolResult::error(format!("Failed to search '{}': {}", path, e));
+        }
+
+        let searched = path.clone();
+        // Walking and reading the files blocks, so it's kept off the async runtime
+        let results = tokio::task::spawn_blocking(move || {
+            search(&resolved, &searched, &regex, context_lines, max_matches)
+        })
+        .await;
+        let (matches, truncated) = match results {
+            Ok(results) => results,
+            Err(e) => {
+                return ToolResult::error(format!("Failed to search '{}': {}", path, e));
+            }
+        };
+
+        ToolResult::success(ToolContent::Json(json!({
+            "match_count": matches.len(),
+            "truncated": truncated,
+            "matches": matches,
+        })))
+    }
+}
+
+/// Every match of `regex` in the files under `resolved`, with `context_lines` lines either side,
+/// stopping after `max_matches`, and whether it stopped before searching everything
+fn search(
+    resolved: &Path,
+    path: &str,
+    regex: &Regex,
+    context_lines: usize,
+    max_matches: usize,
+) -> (Vec<Match>, bool) {
+    let match_count = AtomicUsize::new(0);
+    let files = Mutex::new(Vec::new());
+
+    // Files are searched in parallel, each file's matches are kept together to be sorted after
+    let capped = walk_parallel(&WalkBuilder::new(resolved), |entry| {
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            return true;
+        }
+
+        // Binary and non-UTF-8 files can't be searched as text
+        let Ok(contents) = fs::read_to_string(entry.path()) else {
+            return true;
+        };
+
+        // Show paths the way they were asked for, rather than resolved
+        let file =
+            Path::new(path).join(entry.path().strip_prefix(resolved).unwrap_or(entry.path()));
+        let blocks = matches(&contents, &file, regex, context_lines, max_matches + 1);
+        if blocks.is_empty() {
+            return true;
+        }
+
+        let count = match_count.fetch_add(blocks.len(), Ordering::Relaxed) + blocks.len();
+        let pushed = files
+            .lock()
+            .map(|mut files| files.push((file, blocks)))
+            .is_ok();
+        pushed && count <= max_matches
+    });
+
+    let mut files = files.into_inner().unwrap_or_default();
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut results: Vec<Match> = files.into_iter().flat_map(|(_, blocks)| blocks).collect();
+
+    let truncated = capped || results.len() > max_matches;
+    results.truncate(max_matches);
+    (results, truncated)
+}
+
+/// The first `limit` matches of `regex` in a file, each with `context_lines` lines either side
+fn matches(
+    contents: &str,
+    file: &Path,
+    regex: &Regex,
+    context_lines: usize,
+    limit: usize,
+) -> Vec<Match> {
+    let file = file.display().to_string();
+    let lines: Vec<&str> = contents.lines().collect();
+    let text = |range: std::ops::Range<usize>| lines[range].iter().map(|line| line.to_string());
+
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| regex.is_match(line))
+        .take(limit)
+        .map(|(index, _)| {
+            let start = index.saturating_sub(context_lines);
+            let end = (index + context_lines + 1).min(lines.len());
+            Match {
+                path: file.clone(),
+                line: index + 1,
+                text: lines[index].to_string(),
+                before: text(start..index).collect(),
+                after: text(index + 1..end).collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn returns_each_match_with_its_context() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("a.txt"), "one\ntwo\nthree\nfour\n").unwrap();
+        let regex = Regex::new("two|four").unwrap();
+
+        let (found, truncated) = search(dir.path(), "src", &regex, 1, 1);
+        assert!(truncated);
+        assert_eq!(
+            serde_json::to_value(&found).unwrap(),
+            json!([{
+                "path": "src/a.txt",
+                "line": 2,
+                "text": "two",
+                "before": ["one"],
+                "after": ["three"],
+            }])
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use crate::models::{ToolContent, ToolError, ToolName, ToolResult, TypedTool};
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Rows beyond this many are left out unless the input asks for more
+const DEFAULT_MAX_ROWS: usize = 100;
+
+/// Queries are cancelled after this long
+const QUERY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The statements a query may start with, anything else could change the database
+const READ_ONLY_STATEMENTS: [&str; 7] = [
+    "select", "with", "explain", "show", "values", "table", "pragma",
+];
+
+/// Input parameters for the SQL query tools
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct SqlQueryInput {
+    /// A single read-only statement, e.g. a SELECT
+    pub query: String,
+    /// Return at most this many rows, defaults to 100
+    pub max_rows: Option<usize>,
+}
+
+/// The databases the tool can connect to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Sqlite,
+    Postgres,
+}
+
+/// Tool for running read-only queries against a database declared in the config
+///
+/// SQLite databases are opened read-only, and Postgres queries run in a read-only transaction
+/// that is rolled back, so a query can't change the data even if it gets past the statement
+/// check. The connection is made on the first query.
+#[derive(Clone)]
+pub struct SqlQueryTool {
+    name: ToolName,
+    description: String,
+    backend: Backend,
+    /// The connection string, which may hold a password so is left out of debug output
+    url: String,
+    pool: Arc<OnceCell<Pool>>,
+}
+
+/// Connections to the database, made on the first query
+#[derive(Debug)]
+enum Pool {
+    Sqlite(SqlitePool),
+    Postgres(PgPool),
+}
+
+impl SqlQueryTool {
+    /// Create a tool named `query_<database>` for the database at `url`, e.g.
+    /// `sqlite://data.db` or `postgres://user@localhost/app`
+    ///
+    /// The description, if given, tells the model what the database holds.
+    pub fn new(database: &str, url: &str, description: Option<&str>) -> Result<Self, ToolError> {
+        let fail =
+            |reason: &str| ToolError::InvalidSqlDatabase(database.to_string(), reason.to_string());
+
+        let name = ToolName::try_from(format!("query_{}", database))
+            .map_err(|_| fail("names must be letters, numbers, '_' or '-'"))?;
+        let (backend, url) = match url.split_once(':').map(|(scheme, _)| scheme) {
+            Some("sqlite") if url.contains("mode=") && !url.contains("mode=ro") => {
+                return Err(fail(
+                    "SQLite databases are always opened read-only, remove mode",
+                ));
+            }
+            Some("sqlite") if url.contains("mode=ro") => (Backend::Sqlite, url.to_string()),
+            Some("sqlite") => {
+                let separator = if url.contains('?') { '&' } else { '?' };
+                (Backend::Sqlite, format!("{}{}mode=ro", url, separator))
+            }
+            Some("postgres" | "postgresql") => (Backend::Postgres, url.to_string()),
+            _ => {
+                return Err(fail(
+                    "the url must start with sqlite:, postgres: or postgresql:",
+                ))
+            }
+        };
+
+        let mut tool_description = format!(
+            "Runs a read-only SQL query against the {} {} database and returns the rows as JSON \
+            objects keyed by column name. Only one statement is run, and it must only read data. \
+            Look up the schema before querying tables you haven't seen, and select only the columns \
+            and rows you need. Cast columns of types other than booleans, numbers, text, JSON and \
+            binary data, such as dates, decimals or UUIDs, to text.",
+            database,
+            match backend {
+                Backend::Sqlite => "SQLite",
+                Backend::Postgres => "Postgres",
+            }
+        );
+        if let Some(description) = description {
+            tool_description = format!("{} The database holds: {}", tool_description, description);
+        }
+
+        Ok(SqlQueryTool {
+            name,
+            description: tool_description,
+            backend,
+            url,
+            pool: Arc::default(),
+        })
+    }
+
+    async fn pool(&self) -> Result<&Pool, String> {
+        self.pool
+            .get_or_try_init(|| async {
+                match self.backend {
+                    Backend::Sqlite => SqlitePoolOptions::new()
+                        .max_connections(2)
+                        .connect(&self.url)
+                        .await
+                        .map(Pool::Sqlite),
+                    Backend::Postgres => PgPoolOptions::new()
+                        .max_connections(2)
+                        .connect(&self.url)
+                        .await
+                        .map(Pool::Postgres),
+                }
+            })
+            .await
+            .map_err(|e| format!("Failed to connect to the database: {}", e))
+    }
+
+    /// Run the query in a transaction that is rolled back, returning at most `max_rows` rows
+    /// and whether there were more
+    async fn query(&self, query: &str, max_rows: usize) -> Result<Value, String> {
+        let mut rows = Vec::new();
+        let mut truncated = false;
+
+        match self.pool().await? {
+            Pool::Sqlite(pool) => {
+                let mut transaction = pool.begin().await.map_err(|e| e.to_string())?;
+                {
+                    let mut stream = sqlx::query(query).fetch(&mut *transaction);
+                    while let Some(row) = stream.try_next().await.map_err(|e| e.to_string())? {
+                        if rows.len() == max_rows {
+                            truncated = true;
+                            break;
+                        }
+                        rows.push(sqlite_row_to_json(&row)?);
+                    }
+                }
+                transaction.rollback().await.map_err(|e| e.to_string())?;
+            }
+            Pool::Postgres(pool) => {
+                let mut transaction = pool.begin().await.map_err(|e| e.to_string())?;
+                sqlx::query("SET TRANSACTION READ ONLY")
+                    .execute(&mut *transaction)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                {
+                    let mut stream = sqlx::query(query).fetch(&mut *transaction);
+                    while let Some(row) = stream.try_next().await.map_err(|e| e.to_string())? {
+                        if rows.len() == max_rows {
+                            truncated = true;
+                            break;
+                        }
+                        rows.push(postgres_row_to_json(&row)?);
+                    }
+                }
+                transaction.rollback().await.map_err(|e| e.to_string())?;
+            }
+        }
+
+        Ok(json!({
+            "row_count": rows.len(),
+            "truncated": truncated,
+            "rows": rows,
+        }))
+    }
+}
+
+impl std::fmt::Debug for SqlQueryTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqlQueryTool")
+            .field("name", &self.name)
+            .field("backend", &self.backend)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl TypedTool for SqlQueryTool {
+    type Input = SqlQueryInput;
+
+    fn title(&self) -> ToolName {
+        self.name.clone()
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    async fn run(&self, input: SqlQueryInput) -> ToolResult {
+        let statement = input
+            .query
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if !READ_ONLY_STATEMENTS.contains(&statement.as_str()) {
+            return ToolResult::error(format!(
+                "Only read-only queries are allowed, starting with one of: {}",
+                READ_ONLY_STATEMENTS.join(", ")
+            ));
+        }
+
+        let max_rows = input.max_rows.unwrap_or(DEFAULT_MAX_ROWS);
+        match tokio::time::timeout(QUERY_TIMEOUT, self.query(&input.query, max_rows)).await {
+            Ok(Ok(result)) => ToolResult::success(ToolContent::Json(result)),
+            Ok(Err(message)) => ToolResult::error(format!("Query failed: {}", message)),
+            Err(_) => ToolResult::error(format!(
+                "The query was cancelled after {} seconds",
+                QUERY_TIMEOUT.as_secs()
+            )),
+        }
+    }
+}
+
+/// A SQLite row as a JSON object keyed by column name
+fn sqlite_row_to_json(row: &SqliteRow) -> Result<Value, String> {
+    let mut object = Map::new();
+    for (index, column) in row.columns().iter().enumerate() {
+        let raw = row.try_get_raw(index).map_err(|e| e.to_string())?;
+        // SQLite types are per value, so go by how the value is stored rather than the column
+        let value = match raw.type_info().name() {
+            _ if raw.is_null() => Ok(Value::Null),
+            "INTEGER" | "BOOLEAN" => row.try_get_unchecked::<i64, _>(index).map(Value::from),
+            "REAL" => row.try_get_unchecked::<f64, _>(index).map(Value::from),
+            "BLOB" => row.try_get_unchecked::<Vec<u8>, _>(index).map(blob),
+            _ => row.try_get_unchecked::<String, _>(index).map(Value::from),
+        };
+        let value =
+            value.map_err(|e| format!("Failed to read column '{}': {}", column.name(), e))?;
+        object.insert(column.name().to_string(), value);
+    }
+
+    Ok(Value::Object(object))
+}
+
+/// A Postgres row as a JSON object keyed by column name
+fn postgres_row_to_json(row: &PgRow) -> Result<Value, String> {
+    let mut object = Map::new();
+    for (index, column) in row.columns().iter().enumerate() {
+        let type_name = column.type_info().name();
+        let value = match type_name {
+            "BOOL" => row.try_get::<Option<bool>, _>(index).map(Value::from),
+            "INT2" => row.try_get::<Option<i16>, _>(index).map(Value::from),
+            "INT4" => row.try_get::<Option<i32>, _>(index).map(Value::from),
+            "INT8" => row.try_get::<Option<i64>, _>(index).map(Value::from),
+            "FLOAT4" => row.try_get::<Option<f32>, _>(index).map(Value::from),
+            "FLOAT8" => row.try_get::<Option<f64>, _>(index).map(Value::from),
+            "TEXT" | "VARCHAR" | "CHAR" | "NAME" => {
+                row.try_get::<Option<String>, _>(index).map(Value::from)
+            }
+            "JSON" | "JSONB" => row
+                .try_get::<Option<Value>, _>(index)
+                .map(Option::unwrap_or_default),
+            "BYTEA" => row
+                .try_get::<Option<Vec<u8>>, _>(index)
+                .map(|bytes| bytes.map(blob).unwrap_or_default()),
+            _ => {
+                return Err(format!(
+                    "Column '{}' has type {}, which can't be read, cast it to text",
+                    column.name(),
+                    type_name
+                ))
+            }
+        };
+        let value =
+            value.map_err(|e| format!("Failed to read column '{}': {}", column.name(), e))?;
+        object.insert(column.name().to_string(), value);
+    }
+
+    Ok(Value::Object(object))
+}
+
+/// Binary data is described rather than shown
+fn blob(bytes: Vec<u8>) -> Value {
+    Value::from(format!("({} bytes)", bytes.len()))
+}
@@ -1,60 +1,169 @@
-use crate::models::{Tool, ToolContent, ToolName, ToolResult};
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::walk::{walk_parallel, MAX_WALK_ENTRIES};
+use crate::workspace::Workspace;
 use async_trait::async_trait;
+use ignore::WalkBuilder;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::Path};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::fs;
+
+/// The most entries listed before the tree is cut short
+const MAX_ENTRIES: usize = 500;
 
 /// Input parameters for the tree tool
 #[derive(Deserialize, JsonSchema, Debug)]
 pub struct TreeInput {
     /// The directory path to list files from recursively
     pub dir: String,
+    /// Also list files ignored by .gitignore and hidden files such as .git, defaults to false
+    pub include_ignored: Option<bool>,
+    /// How many levels of subdirectories to descend into, defaults to no limit
+    pub max_depth: Option<usize>,
+    /// List only directories, each still shows how many files it holds, defaults to false
+    pub dirs_only: Option<bool>,
 }
 
 /// Tool for recursively listing all files in a directory and its subdirectories
-#[derive(Debug, Serialize, Clone)]
-pub struct TreeTool;
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct TreeTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
 
 #[async_trait]
-impl Tool<TreeInput> for TreeTool {
+impl TypedTool for TreeTool {
+    type Input = TreeInput;
+
     fn title(&self) -> ToolName {
-        ToolName::Tree
+        ToolName::from("tree")
     }
 
     fn description(&self) -> &'static str {
-        "Recursively lists all files in a directory and its subdirectories. Use absolute paths when possible \
-        to avoid ambiguity. Be cautious with deeply nested directories as this can potentially generate large \
-        outputs. Consider using list_files instead if you only need the immediate contents of a directory."
+        "Recursively lists all files in a directory and its subdirectories as an indented tree, with the \
+        number of files directly in each directory. Use absolute paths when possible to avoid ambiguity. \
+        For large projects, start with a small `max_depth` or `dirs_only` to get an overview, then look \
+        into the directories you need; the listing is capped at 500 entries. Consider using list_files \
+        instead if you only need the immediate contents of a directory. Files ignored by .gitignore, such \
+        as target/ and node_modules/, and hidden files such as .git/ are skipped unless `include_ignored` \
+        is set."
     }
 
     async fn run(&self, input: TreeInput) -> ToolResult {
-        fn visit_dir(dir: &Path, files: &mut Vec<String>) -> Result<(), std::io::Error> {
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if let Some(path_str) = path.to_str() {
-                    files.push(path_str.to_owned());
-                }
-                if path.is_dir() {
-                    visit_dir(&path, files)?;
-                }
+        let dir = match self.workspace.resolve(&input.dir) {
+            Ok(dir) => dir,
+            Err(message) => {
+                return ToolResult::error(message);
             }
-            Ok(())
+        };
+
+        if let Err(e) = fs::read_dir(&dir).await {
+            return ToolResult::error(format!(
+                "Failed to traverse directory '{}': {}",
+                input.dir, e
+            ));
         }
 
-        let mut files = Vec::new();
-        match visit_dir(Path::new(&input.dir), &mut files) {
-            Ok(_) => ToolResult {
-                is_error: false,
-                content: ToolContent::StringArray(files),
-            },
-            Err(e) => ToolResult {
-                is_error: true,
-                content: ToolContent::String(format!(
+        // Walk one level deeper than is shown, so the deepest directories shown have file counts
+        let mut builder = WalkBuilder::new(&dir);
+        builder
+            .standard_filters(!input.include_ignored.unwrap_or(false))
+            // Respect .gitignore even when the directory isn't a git repository
+            .require_git(false)
+            .max_depth(input.max_depth.map(|depth| depth + 1));
+        // Walking blocks, so it's kept off the async runtime
+        let walk = tokio::task::spawn_blocking(move || {
+            let entries = Mutex::new(Vec::new());
+            let capped = walk_parallel(&builder, |entry| {
+                entries
+                    .lock()
+                    .map(|mut entries| entries.push(entry))
+                    .is_ok()
+            });
+            let mut entries = entries.into_inner().unwrap_or_default();
+            // Comparing paths component by component puts each directory before its contents
+            entries.sort_by(|a, b| a.path().cmp(b.path()));
+            (entries, capped)
+        });
+        let (entries, capped) = match walk.await {
+            Ok(walked) => walked,
+            Err(e) => {
+                return ToolResult::error(format!(
                     "Failed to traverse directory '{}': {}",
                     input.dir, e
-                )),
-            },
+                ));
+            }
+        };
+
+        let mut file_counts: HashMap<PathBuf, usize> = HashMap::new();
+        for entry in &entries {
+            let is_file = entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file());
+            if let (true, Some(parent)) = (is_file, entry.path().parent()) {
+                *file_counts.entry(parent.to_path_buf()).or_default() += 1;
+            }
+        }
+
+        let dirs_only = input.dirs_only.unwrap_or(false);
+        let mut lines = Vec::new();
+        let mut omitted = 0;
+
+        for entry in &entries {
+            if input.max_depth.is_some_and(|depth| entry.depth() > depth) {
+                continue;
+            }
+
+            let is_dir = entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_dir());
+            if dirs_only && !is_dir {
+                continue;
+            }
+
+            if lines.len() == MAX_ENTRIES {
+                omitted += 1;
+                continue;
+            }
+
+            let name = if entry.depth() == 0 {
+                input.dir.clone()
+            } else {
+                entry.file_name().to_string_lossy().into_owned()
+            };
+            let indent = "  ".repeat(entry.depth());
+
+            if is_dir {
+                let count = file_counts.get(entry.path()).copied().unwrap_or_default();
+                let noun = if count == 1 { "file" } else { "files" };
+                lines.push(format!(
+                    "{}{}/ ({} {})",
+                    indent,
+                    name.trim_end_matches('/'),
+                    count,
+                    noun
+                ));
+            } else {
+                lines.push(format!("{}{}", indent, name));
+            }
+        }
+
+        if capped {
+            lines.push(format!(
+                "... stopped after {} entries, use max_depth or a subdirectory to see the rest",
+                MAX_WALK_ENTRIES
+            ));
         }
+        if omitted > 0 {
+            lines.push(format!(
+                "... {} more entries not shown, use max_depth, dirs_only or a subdirectory to narrow the listing",
+                omitted
+            ));
+        }
+
+        ToolResult::success(ToolContent::String(lines.join("\n")))
     }
 }
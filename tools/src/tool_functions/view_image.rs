@@ -0,0 +1,90 @@
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::fs;
+
+/// The largest image the providers accept, once it's base64 encoded
+const MAX_ENCODED_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Input parameters for the view_image tool
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct ViewImageInput {
+    /// The path of the image to view, a PNG, JPEG, GIF or WebP file
+    pub path: String,
+}
+
+/// Tool for showing the model an image file, for providers that support vision
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ViewImageTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl TypedTool for ViewImageTool {
+    type Input = ViewImageInput;
+
+    fn title(&self) -> ToolName {
+        ToolName::from("view_image")
+    }
+
+    fn description(&self) -> &'static str {
+        "Shows you an image file so you can look at it, e.g. a screenshot, a design mock or rendered \
+        output. PNG, JPEG, GIF and WebP files of up to 3.75MB are supported. Use read_file for SVG and \
+        other text formats."
+    }
+
+    async fn run(&self, input: ViewImageInput) -> ToolResult {
+        let resolved = match self.workspace.resolve(&input.path) {
+            Ok(resolved) => resolved,
+            Err(message) => return ToolResult::error(message),
+        };
+
+        let Some(media_type) = media_type(&resolved) else {
+            return ToolResult::error(format!(
+                "'{}' isn't a PNG, JPEG, GIF or WebP image",
+                input.path
+            ));
+        };
+
+        // Base64 takes 4 bytes for every 3, so the file itself must be a quarter smaller
+        match fs::metadata(&resolved).await {
+            Ok(metadata) if 4 * metadata.len().div_ceil(3) > MAX_ENCODED_IMAGE_BYTES => {
+                return ToolResult::error(format!(
+                    "'{}' is {} bytes, images can be at most {} bytes",
+                    input.path,
+                    metadata.len(),
+                    MAX_ENCODED_IMAGE_BYTES / 4 * 3
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => return ToolResult::error(format!("Failed to read file: {}", e)),
+        }
+
+        match fs::read(&resolved).await {
+            Ok(bytes) => ToolResult::success(ToolContent::Image {
+                media_type: media_type.to_string(),
+                data: STANDARD.encode(bytes),
+            }),
+            Err(e) => ToolResult::error(format!("Failed to read file: {}", e)),
+        }
+    }
+}
+
+/// The media type of an image, going by its extension
+fn media_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
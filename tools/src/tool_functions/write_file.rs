@@ -1,8 +1,11 @@
-use crate::models::{Tool, ToolContent, ToolName, ToolResult};
+use crate::backup::Backups;
+use crate::models::{ToolContent, ToolName, ToolResult, TypedTool};
+use crate::workspace::Workspace;
 use async_trait::async_trait;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::Path};
+use similar::TextDiff;
+use tokio::fs;
 
 /// Input parameters for the write_file tool
 #[derive(Deserialize, JsonSchema, Debug)]
@@ -14,51 +17,85 @@ pub struct WriteFileInput {
 }
 
 /// Tool for writing content to files
-#[derive(Debug, Serialize, Clone)]
-pub struct WriteFileTool;
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct WriteFileTool {
+    /// The directory the tool is confined to
+    #[serde(skip)]
+    pub workspace: Workspace,
+    /// Where the original contents of overwritten files are saved
+    #[serde(skip)]
+    pub backups: Backups,
+}
 
 #[async_trait]
-impl Tool<WriteFileInput> for WriteFileTool {
+impl TypedTool for WriteFileTool {
+    type Input = WriteFileInput;
+
     fn title(&self) -> ToolName {
-        ToolName::WriteFile
+        ToolName::from("write_file")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
     }
 
     fn description(&self) -> &'static str {
         "Writes content to a file at the specified path, creating the file and any parent directories \
         if they don't exist. Use absolute paths when possible to avoid ambiguity. Be careful when using \
-        this tool as it will overwrite existing files without warning. Always verify the path is correct."
+        this tool as it will overwrite existing files without warning, although the user can undo the \
+        changes of a run. Always verify the path is correct. When a file is overwritten the result shows \
+        a diff of what changed."
     }
 
     async fn run(&self, input: WriteFileInput) -> ToolResult {
+        let path = match self.workspace.resolve(&input.path) {
+            Ok(path) => path,
+            Err(message) => {
+                return ToolResult::error(message);
+            }
+        };
+
         // Ensure the parent directory exists
-        if let Some(parent) = Path::new(&input.path).parent() {
-            if let Err(e) = fs::create_dir_all(parent) {
-                return ToolResult {
-                    is_error: true,
-                    content: ToolContent::String(format!(
-                        "Failed to create directory '{}': {}",
-                        parent.display(),
-                        e
-                    )),
-                };
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent).await {
+                return ToolResult::error(format!(
+                    "Failed to create directory '{}': {}",
+                    parent.display(),
+                    e
+                ));
             }
         }
 
-        match fs::write(&input.path, &input.contents) {
-            Ok(_) => ToolResult {
-                is_error: false,
-                content: ToolContent::String(format!(
-                    "Successfully wrote to file '{}'",
-                    input.path
-                )),
-            },
-            Err(e) => ToolResult {
-                is_error: true,
-                content: ToolContent::String(format!(
-                    "Failed to write to file '{}': {}",
-                    input.path, e
-                )),
-            },
+        // Save what is being overwritten, so the run can be undone
+        if let Err(message) = self.backups.snapshot_async(&path).await {
+            return ToolResult::error(message);
+        }
+
+        // Kept to show what changed, for files that are being overwritten
+        let original = match fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => fs::read_to_string(&path).await.ok(),
+            _ => None,
+        };
+
+        match fs::write(&path, &input.contents).await {
+            Ok(_) => {
+                let summary = format!("Successfully wrote to file '{}'", input.path);
+                let content = match original {
+                    Some(original) if original == input.contents => {
+                        ToolContent::String(format!("{}, its contents are unchanged", summary))
+                    }
+                    Some(original) => ToolContent::Diff {
+                        summary,
+                        diff: TextDiff::from_lines(&original, &input.contents)
+                            .unified_diff()
+                            .header(&format!("a/{}", input.path), &format!("b/{}", input.path))
+                            .to_string(),
+                    },
+                    None => ToolContent::String(summary),
+                };
+                ToolResult::success(content)
+            }
+            Err(e) => ToolResult::error(format!("Failed to write to file '{}': {}", input.path, e)),
         }
     }
 }
@@ -0,0 +1,38 @@
+use ignore::{DirEntry, WalkBuilder, WalkState};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Walking stops after this many entries, so a huge directory can't stall a tool
+pub(crate) const MAX_WALK_ENTRIES: usize = 100_000;
+
+/// Walk the directories set up in `builder` on several threads, passing each entry to `visit`
+/// until it returns false or [`MAX_WALK_ENTRIES`] have been seen
+///
+/// Entries arrive in no particular order. Returns whether the walk was cut short by the cap.
+pub(crate) fn walk_parallel<F>(builder: &WalkBuilder, visit: F) -> bool
+where
+    F: Fn(DirEntry) -> bool + Sync,
+{
+    let seen = AtomicUsize::new(0);
+    let capped = AtomicBool::new(false);
+    let (seen_ref, capped_ref, visit) = (&seen, &capped, &visit);
+
+    builder.build_parallel().run(|| {
+        Box::new(move |entry| {
+            // Entries that can't be read, e.g. for lack of permission, are skipped
+            let Ok(entry) = entry else {
+                return WalkState::Continue;
+            };
+            if seen_ref.fetch_add(1, Ordering::Relaxed) >= MAX_WALK_ENTRIES {
+                capped_ref.store(true, Ordering::Relaxed);
+                return WalkState::Quit;
+            }
+
+            match visit(entry) {
+                true => WalkState::Continue,
+                false => WalkState::Quit,
+            }
+        })
+    });
+
+    capped.into_inner()
+}
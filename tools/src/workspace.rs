@@ -0,0 +1,209 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// The directory in a project where aria keeps its own files
+const ARIA_DIR: &str = ".aria";
+
+/// The project directory that tools are confined to
+///
+/// Paths given to a tool are resolved against the workspace root, with `..` and symlinks
+/// followed, and refused if they end up outside it. Tools then operate on the resolved path,
+/// so what was checked is what gets touched.
+#[derive(Debug, Clone, Default)]
+pub struct Workspace {
+    /// Defaults to the current directory when unset
+    root: Option<PathBuf>,
+}
+
+impl Workspace {
+    /// Create a workspace rooted at `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Workspace {
+            root: Some(root.into()),
+        }
+    }
+
+    /// The canonical path of the workspace root
+    pub fn root(&self) -> Result<PathBuf, String> {
+        let root = match &self.root {
+            Some(root) => root.clone(),
+            None => env::current_dir()
+                .map_err(|e| format!("Failed to resolve the workspace directory: {}", e))?,
+        };
+
+        root.canonicalize().map_err(|e| {
+            format!(
+                "Failed to resolve the workspace directory '{}': {}",
+                root.display(),
+                e
+            )
+        })
+    }
+
+    /// Resolve a path, relative paths being relative to the root, refusing it if it's
+    /// outside the workspace
+    ///
+    /// The path doesn't need to exist yet, e.g. for a file that is about to be written, but a
+    /// symlink on the way must lead somewhere that does: a dangling one is refused, as writing
+    /// through it would create its target wherever it points.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> Result<PathBuf, String> {
+        let path = path.as_ref();
+        let root = self.root()?;
+
+        let mut resolved = PathBuf::new();
+        for component in root.join(path).components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                component => {
+                    resolved.push(component);
+                    // Follow symlinks as they are reached, so one can't lead outside the
+                    // workspace. What doesn't exist yet can't be a symlink, nor contain one.
+                    let is_symlink = fs::symlink_metadata(&resolved)
+                        .map(|metadata| metadata.file_type().is_symlink())
+                        .unwrap_or(false);
+                    if is_symlink {
+                        resolved = resolved.canonicalize().map_err(|e| {
+                            format!(
+                                "Path '{}' goes through the symlink '{}', which can't be \
+                                resolved: {}",
+                                path.display(),
+                                resolved.display(),
+                                e
+                            )
+                        })?;
+                    }
+                }
+            }
+        }
+
+        if !resolved.starts_with(&root) {
+            return Err(format!(
+                "Path '{}' is outside the workspace '{}'",
+                path.display(),
+                root.display()
+            ));
+        }
+
+        Ok(resolved)
+    }
+}
+
+/// Create a directory for aria's own files, like `dir` under `.aria`, and a `.gitignore` in
+/// `.aria` the first time so its backups, sessions and caches aren't committed
+pub fn create_aria_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let aria_dir = dir
+        .ancestors()
+        .find(|ancestor| ancestor.file_name() == Some(OsStr::new(ARIA_DIR)));
+    if let Some(aria_dir) = aria_dir {
+        let gitignore = aria_dir.join(".gitignore");
+        if !gitignore.exists() {
+            fs::write(gitignore, "*\n")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A workspace in a directory of its own, next to a directory outside it
+    fn workspace() -> (TempDir, Workspace, PathBuf, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("root");
+        let outside = dir.path().join("outside");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        let root = root.canonicalize().unwrap();
+        let outside = outside.canonicalize().unwrap();
+        (dir, Workspace::new(&root), root, outside)
+    }
+
+    #[test]
+    fn resolves_relative_paths_against_the_root() {
+        let (_dir, workspace, root, _) = workspace();
+        assert_eq!(
+            workspace.resolve("src/./new/file.rs").unwrap(),
+            root.join("src/new/file.rs")
+        );
+        assert_eq!(
+            workspace.resolve("src/../Cargo.toml").unwrap(),
+            root.join("Cargo.toml")
+        );
+    }
+
+    #[test]
+    fn refuses_parent_dirs_leading_outside() {
+        let (_dir, workspace, _, _) = workspace();
+        assert!(workspace.resolve("../outside/file").is_err());
+        assert!(workspace.resolve("src/../../outside/file").is_err());
+    }
+
+    #[test]
+    fn refuses_absolute_paths_outside() {
+        let (_dir, workspace, root, outside) = workspace();
+        assert!(workspace.resolve(outside.join("file")).is_err());
+        assert_eq!(
+            workspace.resolve(root.join("src/lib.rs")).unwrap(),
+            root.join("src/lib.rs")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_symlinks_inside_the_root() {
+        let (_dir, workspace, root, _) = workspace();
+        std::os::unix::fs::symlink(root.join("src"), root.join("link")).unwrap();
+        assert_eq!(
+            workspace.resolve("link/lib.rs").unwrap(),
+            root.join("src/lib.rs")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_symlinks_leading_outside() {
+        let (_dir, workspace, root, outside) = workspace();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        assert!(workspace.resolve("link/file").is_err());
+        assert!(workspace.resolve("link").is_err());
+    }
+
+    #[test]
+    fn ignores_the_aria_dir_in_git() {
+        let (_dir, _, root, _) = workspace();
+        create_aria_dir(&root.join(".aria/sessions")).unwrap();
+        assert!(root.join(".aria/sessions").is_dir());
+        assert_eq!(
+            fs::read_to_string(root.join(".aria/.gitignore")).unwrap(),
+            "*\n"
+        );
+
+        fs::write(root.join(".aria/.gitignore"), "sessions\n").unwrap();
+        create_aria_dir(&root.join(".aria/backups")).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join(".aria/.gitignore")).unwrap(),
+            "sessions\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_dangling_symlinks() {
+        let (_dir, workspace, root, outside) = workspace();
+        std::os::unix::fs::symlink(outside.join("new"), root.join("link")).unwrap();
+        std::os::unix::fs::symlink(root.join("missing"), root.join("inside")).unwrap();
+        assert!(workspace.resolve("link").is_err());
+        assert!(workspace.resolve("link/file").is_err());
+        assert!(workspace.resolve("inside").is_err());
+    }
+}